@@ -1,10 +1,17 @@
-pub mod driver_loop;
+#[cfg(target_os = "linux")]
 pub mod master_service;
+#[cfg(target_os = "linux")]
 pub mod slave_service;
-pub mod status;
+#[cfg(target_os = "linux")]
 pub mod ui_server;
 
+/// Capture logic shared with the headless kmf-master
+pub use kmf_middleware::{driver_loop, status};
+
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
+    #[cfg(target_os = "linux")]
     ui_server::run();
+    #[cfg(not(target_os = "linux"))]
+    eprintln!("kmf reads and replays input through evdev and uinput, which only Linux has");
 }
@@ -2,8 +2,14 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
 fn main() {
-    // Load environment variables from .env file (e.g. WEBKIT_DISABLE_COMPOSITING_MODE)
-    dotenvy::dotenv().ok();
+    // Load environment variables from .env file (e.g. WEBKIT_DISABLE_COMPOSITING_MODE),
+    // except the serialization, which the config file overrides
+    for (key, value) in dotenvy::dotenv_iter().into_iter().flatten().flatten() {
+        if key != kmf_protocol::serialization::SERIALIZATION_ENV && std::env::var_os(&key).is_none()
+        {
+            std::env::set_var(key, value);
+        }
+    }
 
     app_lib::run();
 }
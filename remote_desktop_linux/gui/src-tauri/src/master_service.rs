@@ -1,18 +1,18 @@
 use std::collections::HashMap;
-use std::path::PathBuf;
-use std::str::FromStr;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex};
 use std::time::Duration;
-use tokio::sync::{broadcast, oneshot};
+use tokio::sync::{broadcast, oneshot, watch};
 use tokio::task::JoinHandle;
+use tracing::{error, info};
 
-use kmf_driver::driver::{DeviceReader, DriverWriter};
-use kmf_middleware::file_transfer;
+use kmf_middleware::capture::Capture;
+use kmf_middleware::clients::{spawn_client_handler, Clients, Stoppers};
+use kmf_middleware::config::MasterConfig;
+use kmf_middleware::hotkeys::Hotkeys;
 use kmf_protocol::config::ServerMessage;
-use kmf_protocol::{Packet, TransportFactory, TransportType};
+use kmf_protocol::{TransportFactory, TransportType};
 
-use crate::driver_loop::DriverLoopContext;
 use crate::status::MasterStatus;
 pub use crate::status::MasterStatusSnapshot;
 
@@ -30,8 +30,10 @@ pub struct MasterService {
     network_handle: Mutex<Option<JoinHandle<()>>>,
     tx: Mutex<Option<broadcast::Sender<ServerMessage>>>,
     status: Arc<Mutex<MasterStatus>>,
-    clients: Arc<Mutex<Vec<ConnectedClientInfo>>>,
-    client_stoppers: Arc<Mutex<HashMap<String, oneshot::Sender<()>>>>,
+    clients: Clients,
+    client_stoppers: Stoppers,
+    hotkeys: Arc<Mutex<Hotkeys>>,
+    cursor_lock: Arc<AtomicBool>,
 }
 
 impl Default for MasterService {
@@ -50,16 +52,59 @@ impl MasterService {
             status: Arc::new(Mutex::new(MasterStatus::default())),
             clients: Arc::new(Mutex::new(Vec::new())),
             client_stoppers: Arc::new(Mutex::new(HashMap::new())),
+            hotkeys: Arc::new(Mutex::new(Hotkeys::load_user())),
+            cursor_lock: Arc::new(AtomicBool::new(false)),
         }
     }
 
+    /// Confines the cursor to the screen it is on, or lets it cross edges again.
+    pub fn set_cursor_lock(&self, locked: bool) {
+        self.cursor_lock.store(locked, Ordering::SeqCst);
+        self.status
+            .lock()
+            .expect("Failed to lock status")
+            .cursor_locked = locked;
+    }
+
+    pub fn is_cursor_locked(&self) -> bool {
+        self.cursor_lock.load(Ordering::SeqCst)
+    }
+
+    /// Current hotkeys in their config file format.
+    pub fn hotkeys_toml(&self) -> String {
+        self.hotkeys
+            .lock()
+            .expect("Failed to lock hotkeys")
+            .to_toml()
+    }
+
+    /// Replaces the hotkeys, also while running, and saves them to the config file.
+    pub fn set_hotkeys(&self, text: &str) -> Result<(), String> {
+        let hotkeys = Hotkeys::from_toml(text)?;
+        if let Some(path) = Hotkeys::default_path() {
+            hotkeys.save(&path)?;
+        }
+        *self.hotkeys.lock().expect("Failed to lock hotkeys") = hotkeys;
+        Ok(())
+    }
+
     pub fn get_status(&self) -> MasterStatusSnapshot {
         let status = self.status.lock().expect("Failed to lock status");
         MasterStatusSnapshot::from(&*status)
     }
 
     pub fn get_clients(&self) -> Vec<ConnectedClientInfo> {
-        self.clients.lock().expect("Failed to lock clients").clone()
+        self.clients
+            .lock()
+            .expect("Failed to lock clients")
+            .iter()
+            .map(|client| ConnectedClientInfo {
+                id: client.address.clone(),
+                hostname: client.hostname.clone(),
+                ip: client.address.clone(),
+                status: "online".into(),
+            })
+            .collect()
     }
 
     pub fn disconnect_client(&self, id: &str) {
@@ -72,16 +117,16 @@ impl MasterService {
         }
     }
 
-    pub fn start(&self, mouse: Option<String>, keyboard: Option<String>) -> Result<(), String> {
+    /// Starts capturing the devices of `config` and serves the slaves on its bind address.
+    /// `auto_attach` also captures keyboards and mice plugged in later, and with a
+    /// `send_layout` the typed characters are sent along, for slaves with another layout.
+    pub fn start(&self, config: MasterConfig) -> Result<(), String> {
         if self.running.load(Ordering::SeqCst) {
             return Err("Master is already running".to_string());
         }
 
-        eprintln!(
-            "[MasterService] Starting with mouse={:?}, keyboard={:?}",
-            mouse, keyboard
-        );
-        let (reader, writer) = init_driver(mouse, keyboard)?;
+        info!(mouse = ?config.mouse, keyboard = ?config.keyboard, "Starting master");
+        let capture = Capture::open(&config)?;
 
         let running = self.running.clone();
         running.store(true, Ordering::SeqCst);
@@ -89,17 +134,17 @@ impl MasterService {
         let (tx, _rx) = broadcast::channel::<ServerMessage>(100);
         *self.tx.lock().expect("Failed to lock tx") = Some(tx.clone());
 
-        {
-            let mut status = self.status.lock().expect("Failed to lock status");
-            status.reset();
-        }
+        self.cursor_lock.store(false, Ordering::SeqCst);
+        // screens are only switched by crossing edges and hotkeys here
+        let (_, screen_rx) = watch::channel(0);
 
-        let h = Self::spawn_driver_loop(
-            running.clone(),
-            reader,
-            writer,
+        let h = capture.spawn(
             tx.clone(),
             self.status.clone(),
+            running.clone(),
+            self.hotkeys.clone(),
+            self.cursor_lock.clone(),
+            screen_rx,
         );
         *self.handle.lock().expect("Failed to lock handle") = Some(h);
 
@@ -108,6 +153,8 @@ impl MasterService {
             tx.clone(),
             self.clients.clone(),
             self.client_stoppers.clone(),
+            config.bind,
+            config.transport,
         );
         *self
             .network_handle
@@ -133,75 +180,25 @@ impl MasterService {
         Ok(())
     }
 
-    fn spawn_driver_loop(
-        running: Arc<AtomicBool>,
-        mut reader: DeviceReader,
-        writer: DriverWriter,
-        tx: broadcast::Sender<ServerMessage>,
-        status_mutex: Arc<Mutex<MasterStatus>>,
-    ) -> JoinHandle<()> {
-        tokio::spawn(async move {
-            eprintln!("MasterService: Local Driver Loop started");
-
-            let (width, height) = {
-                let s = status_mutex.lock().expect("Failed to lock status");
-                (s.master_width, s.master_height)
-            };
-
-            let mut ctx = DriverLoopContext::new(
-                writer,
-                tx,
-                status_mutex.clone(),
-                running.clone(),
-                width,
-                height,
-            );
-
-            println!("[CAL] Calibration started: Move mouse to bottom-right and press 'c'.");
-
-            while running.load(Ordering::SeqCst) {
-                if let Ok(events) = reader.read_events() {
-                    for event in events {
-                        if !ctx.handle_event(event, &mut reader) {
-                            break;
-                        }
-                    }
-                }
-                tokio::time::sleep(Duration::from_millis(10)).await;
-            }
-
-            if ctx.inputs_grabbed {
-                let _ = reader.ungrab_inputs();
-            }
-            {
-                let mut status = status_mutex.lock().expect("Failed to lock status");
-                status.running = false;
-                status.remote_mode = false;
-            }
-            eprintln!("MasterService: Local Driver Loop stopped");
-        })
-    }
-
     fn spawn_network_loop(
         running: Arc<AtomicBool>,
         tx_for_network: broadcast::Sender<ServerMessage>,
-        clients: Arc<Mutex<Vec<ConnectedClientInfo>>>,
-        stoppers: Arc<Mutex<HashMap<String, oneshot::Sender<()>>>>,
+        clients: Clients,
+        stoppers: Stoppers,
+        bind_addr: String,
+        transport: TransportType,
     ) -> JoinHandle<()> {
         tokio::spawn(async move {
-            let bind_addr = "0.0.0.0:8081";
-            let transport = TransportType::Tcp;
-
-            match TransportFactory::bind_server(transport, bind_addr).await {
+            match TransportFactory::bind_server(transport, &bind_addr).await {
                 Ok(mut listener) => {
-                    println!("MasterService (Net): Listening on {}", bind_addr);
+                    info!("Listening on {}", bind_addr);
 
                     while running.load(Ordering::SeqCst) {
                         if let Ok(Ok((socket, addr))) =
                             tokio::time::timeout(Duration::from_millis(500), listener.accept())
                                 .await
                         {
-                            println!("New client connected: {}", addr);
+                            info!("New client connected: {}", addr);
                             let rx = tx_for_network.subscribe();
                             let (stop_tx, stop_rx) = oneshot::channel();
 
@@ -223,7 +220,7 @@ impl MasterService {
                     let _ = tx_for_network.send(ServerMessage::Quit);
                 }
                 Err(e) => {
-                    eprintln!("Failed to bind server: {}", e);
+                    error!("Failed to bind server: {}", e);
                 }
             }
         })
@@ -231,7 +228,7 @@ impl MasterService {
 
     pub fn stop(&self) {
         if self.running.load(Ordering::SeqCst) {
-            println!("MasterService: Stopping...");
+            info!("Stopping master");
             self.running.store(false, Ordering::SeqCst);
             let mut status = self.status.lock().expect("Failed to lock status");
             status.running = false;
@@ -253,114 +250,3 @@ impl MasterService {
         self.running.load(Ordering::SeqCst)
     }
 }
-
-fn init_driver(
-    mouse: Option<String>,
-    keyboard: Option<String>,
-) -> Result<(DeviceReader, DriverWriter), String> {
-    let mut devices = Vec::new();
-
-    if let Some(path_str) = keyboard {
-        let path = PathBuf::from_str(&path_str).map_err(|e| e.to_string())?;
-        let device = DeviceReader::open_path(path, false, true)
-            .map_err(|e| format!("Failed to open keyboard: {}", e))?;
-        devices.push(device);
-    }
-
-    if let Some(path_str) = mouse {
-        let path = PathBuf::from_str(&path_str).map_err(|e| e.to_string())?;
-        let device = DeviceReader::open_path(path, false, true)
-            .map_err(|e| format!("Failed to open mouse: {}", e))?;
-        devices.push(device);
-    }
-
-    let reader =
-        DeviceReader::new(devices).map_err(|e| format!("Failed to init DriverReader: {}", e))?;
-
-    let keys = reader.available_keys().unwrap_or_default();
-    let axes = reader.available_axes().unwrap_or_default();
-
-    let writer =
-        DriverWriter::new(keys, axes).map_err(|e| format!("Failed to init DriverWriter: {}", e))?;
-
-    Ok((reader, writer))
-}
-
-fn spawn_client_handler(
-    mut socket: Box<dyn kmf_protocol::AsyncStream>,
-    mut rx: broadcast::Receiver<ServerMessage>,
-    addr: String,
-    clients: Arc<Mutex<Vec<ConnectedClientInfo>>>,
-    stoppers: Arc<Mutex<HashMap<String, oneshot::Sender<()>>>>,
-    mut stop_rx: oneshot::Receiver<()>,
-) {
-    tokio::spawn(async move {
-        match kmf_protocol::receive(&mut socket).await {
-            Ok(Packet::ServerHello(config)) => {
-                println!("[INFO] Client config: {:?}", config);
-
-                let client_id = addr.clone();
-                {
-                    let mut guard = clients.lock().expect("Failed to lock clients");
-                    guard.push(ConnectedClientInfo {
-                        id: client_id.clone(),
-                        hostname: config.hostname.clone(),
-                        ip: addr.clone(),
-                        status: "online".into(),
-                    });
-                }
-
-                loop {
-                    tokio::select! {
-                        _ = &mut stop_rx => {
-                            println!("Client {} disconnect requested by master", client_id);
-                            let _ = kmf_protocol::send(Packet::ClientQuit, &mut socket).await;
-                            break;
-                        }
-                        msg = rx.recv() => {
-                            match msg {
-                                Ok(ServerMessage::Action(action)) => {
-                                    if let Err(e) = kmf_protocol::send(Packet::Action(action), &mut socket).await {
-                                        eprintln!("[ERROR] Send action failed: {}", e);
-                                        break;
-                                    }
-                                    let _ = kmf_protocol::receive(&mut socket).await;
-                                }
-                                Ok(ServerMessage::File { path }) => {
-                                    println!("[Master] Sending file to {}: {}", client_id, path);
-                                    if let Err(e) = file_transfer::send_file(&mut socket, &path).await {
-                                        eprintln!("[ERROR] Send file failed: {}", e);
-                                        break;
-                                    }
-                                }
-                                Ok(ServerMessage::Quit) => {
-                                    let _ = kmf_protocol::send(Packet::ClientQuit, &mut socket).await;
-                                    break;
-                                }
-                                _ => {}
-                            }
-                        }
-                    }
-                }
-
-                println!("Client disconnected: {}", client_id);
-                {
-                    let mut guard = clients.lock().expect("Failed to lock clients");
-                    if let Some(pos) = guard.iter().position(|c| c.id == client_id) {
-                        guard.remove(pos);
-                    }
-                }
-                {
-                    let mut guard = stoppers.lock().expect("Failed to lock stoppers");
-                    guard.remove(&client_id);
-                }
-            }
-            Ok(other) => {
-                eprintln!("[ERROR] Expected ServerHello, got {:?}", other);
-            }
-            Err(e) => {
-                eprintln!("[ERROR] Failed to receive ServerHello: {}", e);
-            }
-        }
-    });
-}
@@ -1,13 +1,13 @@
-#[cfg(target_os = "linux")]
-use evdev::{KeyCode, RelativeAxisCode};
-use kmf_driver::driver::DriverWriter;
-#[cfg(not(target_os = "linux"))]
-use kmf_driver::driver::{KeyCode, RelativeAxisCode};
+use kmf_driver::driver::{DriverWriter, InputSink, KeyCode, RelativeAxisCode};
+use kmf_driver::layout::{Layout, LayoutSink};
+use kmf_middleware::config::SlaveConfig;
+use kmf_middleware::metrics::metrics;
 use kmf_protocol::config::ServerConfig;
-use kmf_protocol::{ErrorCode, Packet, TransportFactory, TransportType};
+use kmf_protocol::{ErrorCode, Packet, TransportFactory};
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex};
 use tokio::task::JoinHandle;
+use tracing::{error, info, info_span, warn, Instrument};
 
 pub struct SlaveService {
     running: Arc<AtomicBool>,
@@ -61,10 +61,16 @@ impl SlaveService {
         }
     }
 
-    pub fn start(&self, server_ip: String) -> Result<(), String> {
+    /// Connects to the master of `config` and replays its input until stopped.
+    pub fn start(&self, config: SlaveConfig) -> Result<(), String> {
         if self.running.load(Ordering::SeqCst) {
             return Err("Slave is already running".to_string());
         }
+        if config.server.is_none() {
+            return Err("No master address given".to_string());
+        }
+        // keys sent with their character are typed in this machine's layout
+        let layout = config.layout()?;
 
         self.running.store(true, Ordering::SeqCst);
         {
@@ -77,24 +83,26 @@ impl SlaveService {
         let running_flag = self.running.clone();
         let status_flag = self.status.clone();
 
-        let h = tokio::spawn(async move {
-            let transport = TransportType::Tcp;
+        let span = info_span!("session", server = %config.server.as_deref().unwrap_or_default());
+        let h = tokio::spawn(
+            async move {
+                if let Err(e) =
+                    run_client_internal(config, layout, running_flag, status_flag.clone()).await
+                {
+                    error!("Slave connection error: {}", e);
+                    let mut status = status_flag.lock().unwrap();
+                    status.last_error = Some(e.to_string());
+                    status.connected = false;
+                    status.connecting = false;
+                }
 
-            if let Err(e) =
-                run_client_internal(server_ip, transport, running_flag, status_flag.clone()).await
-            {
-                eprintln!("Slave connection error: {}", e);
                 let mut status = status_flag.lock().unwrap();
-                status.last_error = Some(e.to_string());
+                status.running = false;
                 status.connected = false;
                 status.connecting = false;
             }
-
-            let mut status = status_flag.lock().unwrap();
-            status.running = false;
-            status.connected = false;
-            status.connecting = false;
-        });
+            .instrument(span),
+        );
 
         *self.handle.lock().unwrap() = Some(h);
         Ok(())
@@ -121,13 +129,14 @@ impl SlaveService {
 }
 
 async fn run_client_internal(
-    server_addr: String,
-    transport: TransportType,
+    config: SlaveConfig,
+    layout: Option<Layout>,
     running: Arc<AtomicBool>,
     status: Arc<Mutex<SlaveStatus>>,
 ) -> anyhow::Result<()> {
-    let mut stream = match TransportFactory::connect_client(transport, &server_addr).await {
-        Ok(stream) => stream,
+    let server_addr = config.server.unwrap_or_default();
+    let mut stream = match TransportFactory::connect_client(config.transport, &server_addr).await {
+        Ok(stream) => metrics().peer(&server_addr).count(stream),
         Err(e) => {
             let mut status = status.lock().unwrap();
             status.connecting = false;
@@ -142,17 +151,17 @@ async fn run_client_internal(
         status.connected = true;
     }
 
-    let config = ServerConfig {
+    let hello = ServerConfig {
         version: 1,
-        screen_width: 1920,
-        screen_height: 1080,
+        screen_width: config.screen.width,
+        screen_height: config.screen.height,
         hostname: hostname::get()
             .unwrap_or_default()
             .to_string_lossy()
             .to_string(),
     };
 
-    if let Err(e) = kmf_protocol::send(Packet::ServerHello(config), &mut stream).await {
+    if let Err(e) = kmf_protocol::send(Packet::ServerHello(hello), &mut stream).await {
         return Err(anyhow::anyhow!("Handshake failed: {}", e));
     }
 
@@ -176,6 +185,8 @@ async fn run_client_internal(
         },
     ));
 
+    let mut writer = LayoutSink::new(writer, layout);
+
     while running.load(Ordering::SeqCst) {
         if let Ok(Ok(packet)) = tokio::time::timeout(
             std::time::Duration::from_millis(200),
@@ -198,25 +209,28 @@ async fn run_client_internal(
     Ok(())
 }
 
-async fn handle_packet(
+/// Handles one packet from the master, replaying input into `writer`.
+/// Returns `true` when the master asked to disconnect.
+pub async fn handle_packet(
     stream: &mut Box<dyn kmf_protocol::AsyncStream>,
     packet: Packet,
-    writer: &mut DriverWriter,
+    writer: &mut impl InputSink,
 ) -> Result<bool, anyhow::Error> {
     match packet {
-        Packet::Action(action) => {
-            if let Ok(event) = kmf_middleware::event::action_to_driver_event(action) {
-                if let Err(e) = writer.simulate_event(event) {
-                    eprintln!("Input simulation failed: {}", e);
-                }
+        Packet::Input(frame) => {
+            let captured = frame.captured();
+            let frame = kmf_middleware::event::driver_frame(frame);
+            if let Err(e) = writer.simulate_frame(&frame) {
+                error!("Input simulation failed: {}", e);
             }
+            metrics().record_replayed(captured);
             kmf_protocol::send(Packet::Ok, stream).await?;
             Ok(false)
         }
         Packet::DropSend { filename } => {
-            println!("Receiving file: {}", filename);
+            info!("Receiving file {}", filename);
             if let Err(e) = kmf_middleware::file_transfer::receive_file(stream, &filename).await {
-                eprintln!("File receive failed: {}", e);
+                error!("File receive failed: {}", e);
                 let _ = kmf_protocol::send(
                     Packet::Err {
                         code: ErrorCode::Internal,
@@ -229,13 +243,13 @@ async fn handle_packet(
             Ok(false)
         }
         Packet::DropRequest { filename } => {
-            println!("Sending file: {}", filename);
+            info!("Sending file {}", filename);
             match kmf_middleware::file_transfer::read_file(&filename).await {
                 Ok(data) => {
                     kmf_protocol::send(Packet::Data(data), stream).await?;
                 }
                 Err(e) => {
-                    eprintln!("Failed to read file: {}", e);
+                    error!("Failed to read file: {}", e);
                     let _ = kmf_protocol::send(
                         Packet::Err {
                             code: ErrorCode::Internal,
@@ -255,7 +269,7 @@ async fn handle_packet(
         Packet::ClientQuit => Ok(true),
         Packet::Ok => Ok(false),
         Packet::Err { code, message } => {
-            eprintln!("Server error ({}): {}", code, message);
+            warn!("Server error ({}): {}", code, message);
             Ok(false)
         }
         _ => Ok(false),
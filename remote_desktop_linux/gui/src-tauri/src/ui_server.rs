@@ -1,13 +1,22 @@
 use askama::Template;
 use axum::{
     extract::{Multipart, Path, State},
-    response::Html,
+    http::header,
+    response::{Html, IntoResponse},
     routing::{get, post},
     Form, Router,
 };
+use kmf_driver::device_type::DeviceType;
+use kmf_driver::driver::DeviceReader;
+use kmf_driver::layout::Layout;
+use kmf_middleware::config::{Config, EdgeConfig, MasterConfig};
+use kmf_middleware::logging;
+use kmf_middleware::metrics::metrics;
 use serde::Deserialize;
-use std::sync::Arc;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
 use tauri::Manager;
+use tracing::{error, warn};
 
 use crate::master_service::MasterService;
 use crate::slave_service::{SlaveService, SlaveStatusSnapshot};
@@ -17,6 +26,8 @@ use crate::status::MasterStatusSnapshot;
 struct AppState {
     master_service: Arc<MasterService>,
     slave_service: Arc<SlaveService>,
+    /// Saved settings, the forms start from them
+    config: Mutex<Config>,
     #[allow(dead_code)]
     app_handle: tauri::AppHandle,
 }
@@ -29,6 +40,45 @@ struct Client {
     status: String,
 }
 
+#[derive(Clone)]
+struct DeviceOption {
+    path: String,
+    label: String,
+}
+
+/// Values the master form is filled with, empty for settings that are not set
+struct MasterFormValues {
+    mouse: String,
+    keyboard: String,
+    auto_attach: bool,
+    dwell_ms: String,
+    double_tap_ms: String,
+    cross_modifier: String,
+    corner_size: String,
+    button_guard: bool,
+    layout: String,
+}
+
+impl From<&MasterConfig> for MasterFormValues {
+    fn from(config: &MasterConfig) -> Self {
+        let edge = &config.edge;
+        Self {
+            mouse: config.mouse.clone().unwrap_or_default(),
+            keyboard: config.keyboard.clone().unwrap_or_default(),
+            auto_attach: config.auto_attach,
+            dwell_ms: edge.dwell_ms.to_string(),
+            double_tap_ms: edge
+                .double_tap_ms
+                .map(|ms| ms.to_string())
+                .unwrap_or_default(),
+            cross_modifier: edge.modifier.map(|m| m.to_string()).unwrap_or_default(),
+            corner_size: edge.corner_size.to_string(),
+            button_guard: edge.button_guard,
+            layout: config.send_layout.clone().unwrap_or_default(),
+        }
+    }
+}
+
 // --- TEMPLATES ---
 #[derive(Template)]
 #[template(path = "index.html")]
@@ -39,11 +89,23 @@ struct LandingTemplate;
 struct MasterTemplate {
     clients: Vec<Client>,
     is_running: bool,
+    pointers: Vec<DeviceOption>,
+    keyboards: Vec<DeviceOption>,
+    hotkeys: String,
+    cursor_locked: bool,
+    /// Layout names, with whether the form selects them
+    layouts: Vec<(String, bool)>,
+    form: MasterFormValues,
+    /// Settings file contents for the editor
+    config: String,
 }
 
 #[derive(Template)]
 #[template(path = "slave.html")]
-struct SlaveTemplate;
+struct SlaveTemplate {
+    master_ip: String,
+    config: String,
+}
 
 #[derive(Template)]
 #[template(path = "client_list.html")]
@@ -75,13 +137,45 @@ fn get_clients_from_service(service: &MasterService) -> Vec<Client> {
         .collect()
 }
 
+/// Lists detected devices of the given kinds for the device path dropdowns.
+fn device_options(kinds: &[DeviceType]) -> Vec<DeviceOption> {
+    DeviceReader::list_devices()
+        .into_iter()
+        .filter(|d| kinds.contains(&d.device_type))
+        .map(|d| DeviceOption {
+            path: d.stable_path().display().to_string(),
+            label: format!("{} ({:?}, {})", d.name, d.device_type, d.path.display()),
+        })
+        .collect()
+}
+
 async fn master_handler(State(state): State<Arc<AppState>>) -> Html<String> {
     let clients = get_clients_from_service(&state.master_service);
     let is_running = state.master_service.is_running();
+    let config = state.config.lock().expect("Failed to lock config").clone();
+    let form = MasterFormValues::from(&config.master);
+    let mut layouts = Layout::names();
+    if !matches!(form.layout.as_str(), "" | "auto") && !layouts.contains(&form.layout) {
+        // a variant like `sk(qwerty)` from the settings file stays selected
+        layouts.push(form.layout.clone());
+    }
 
     let template = MasterTemplate {
         clients,
         is_running,
+        pointers: device_options(&[DeviceType::Mouse, DeviceType::Touchpad]),
+        keyboards: device_options(&[DeviceType::Keyboard]),
+        hotkeys: state.master_service.hotkeys_toml(),
+        cursor_locked: state.master_service.is_cursor_locked(),
+        layouts: layouts
+            .into_iter()
+            .map(|name| {
+                let selected = name == form.layout;
+                (name, selected)
+            })
+            .collect(),
+        form,
+        config: config.to_toml(),
     };
     Html(
         template
@@ -90,8 +184,16 @@ async fn master_handler(State(state): State<Arc<AppState>>) -> Html<String> {
     )
 }
 
-async fn slave_handler() -> Html<String> {
-    let template = SlaveTemplate;
+async fn slave_handler(State(state): State<Arc<AppState>>) -> Html<String> {
+    let config = state.config.lock().expect("Failed to lock config").clone();
+    let template = SlaveTemplate {
+        master_ip: config
+            .slave
+            .server
+            .clone()
+            .unwrap_or_else(|| "127.0.0.1".to_string()),
+        config: config.to_toml(),
+    };
     Html(
         template
             .render()
@@ -104,6 +206,53 @@ struct ToggleMasterForm {
     action: String,
     mouse: Option<String>,
     keyboard: Option<String>,
+    /// Checkbox, present only when checked
+    auto_attach: Option<String>,
+    dwell_ms: Option<String>,
+    double_tap_ms: Option<String>,
+    cross_modifier: Option<String>,
+    corner_size: Option<String>,
+    button_guard: Option<String>,
+    /// Empty sends raw key codes, `auto` detects this machine's layout
+    layout: Option<String>,
+}
+
+/// Parses an optional number field, empty means not set.
+fn parse_field<T: FromStr>(value: &Option<String>, name: &str) -> Result<Option<T>, String> {
+    match value.as_deref().map(str::trim) {
+        None | Some("") => Ok(None),
+        Some(text) => text
+            .parse()
+            .map(Some)
+            .map_err(|_| format!("Invalid {}: '{}'", name, text)),
+    }
+}
+
+/// Text field value, empty means not set.
+fn text_field(value: &Option<String>) -> Option<String> {
+    value
+        .as_deref()
+        .map(str::trim)
+        .filter(|text| !text.is_empty())
+        .map(str::to_string)
+}
+
+/// Settings to start the master with, the form's fields replacing the saved ones.
+fn master_config(form: &ToggleMasterForm, mut config: Config) -> Result<MasterConfig, String> {
+    let master = &mut config.master;
+    master.mouse = text_field(&form.mouse);
+    master.keyboard = text_field(&form.keyboard);
+    master.auto_attach = form.auto_attach.is_some();
+    master.edge = EdgeConfig {
+        dwell_ms: parse_field(&form.dwell_ms, "dwell time")?.unwrap_or(0),
+        double_tap_ms: parse_field(&form.double_tap_ms, "double tap time")?,
+        modifier: parse_field(&form.cross_modifier, "modifier")?,
+        corner_size: parse_field(&form.corner_size, "corner size")?.unwrap_or(0),
+        button_guard: form.button_guard.is_some(),
+    };
+    master.send_layout = text_field(&form.layout);
+    config.validate()?;
+    Ok(config.master)
 }
 
 async fn toggle_master_handler(
@@ -117,10 +266,18 @@ async fn toggle_master_handler(
             return Html(render_master_button(true));
         }
 
-        match state.master_service.start(form.mouse, form.keyboard) {
+        let saved = state.config.lock().expect("Failed to lock config").clone();
+        let config = match master_config(&form, saved) {
+            Ok(config) => config,
+            Err(e) => {
+                error!("Failed to start master: {}", e);
+                return Html(render_master_button(false));
+            }
+        };
+        match state.master_service.start(config) {
             Ok(_) => Html(render_master_button(true)),
             Err(e) => {
-                eprintln!("Failed to start master: {}", e);
+                error!("Failed to start master: {}", e);
                 Html(render_master_button(false))
             }
         }
@@ -142,6 +299,20 @@ fn render_master_button(is_running: bool) -> String {
     }
 }
 
+async fn cursor_lock_handler(State(state): State<Arc<AppState>>) -> Html<String> {
+    let locked = !state.master_service.is_cursor_locked();
+    state.master_service.set_cursor_lock(locked);
+    Html(render_cursor_lock_button(locked))
+}
+
+fn render_cursor_lock_button(locked: bool) -> String {
+    if locked {
+        r#"<button hx-post="/api/cursor_lock" hx-swap="outerHTML" class="bg-yellow-600 hover:bg-yellow-500 text-white font-bold py-2 px-4 rounded text-sm transition-colors">Unlock Cursor</button>"#.to_string()
+    } else {
+        r#"<button hx-post="/api/cursor_lock" hx-swap="outerHTML" class="bg-gray-700 hover:bg-gray-600 text-gray-200 font-bold py-2 px-4 rounded text-sm transition-colors">Lock Cursor to Screen</button>"#.to_string()
+    }
+}
+
 async fn connect_client_handler(
     State(state): State<Arc<AppState>>,
     Path(_id): Path<String>,
@@ -180,6 +351,49 @@ async fn clients_list_handler(State(state): State<Arc<AppState>>) -> Html<String
     )
 }
 
+#[derive(Deserialize)]
+struct HotkeysForm {
+    hotkeys: String,
+}
+
+async fn hotkeys_handler(
+    State(state): State<Arc<AppState>>,
+    Form(form): Form<HotkeysForm>,
+) -> Html<String> {
+    match state.master_service.set_hotkeys(&form.hotkeys) {
+        Ok(()) => Html("<span class='text-green-400'>Hotkeys saved</span>".to_string()),
+        Err(e) => Html(format!("<span class='text-red-400'>{}</span>", e)),
+    }
+}
+
+#[derive(Deserialize)]
+struct ConfigForm {
+    config: String,
+}
+
+/// Validates and saves the settings, they apply the next time master or slave starts.
+async fn config_handler(
+    State(state): State<Arc<AppState>>,
+    Form(form): Form<ConfigForm>,
+) -> Html<String> {
+    let saved = Config::from_toml(&form.config).and_then(|config| {
+        let path = Config::default_path().ok_or("No config directory to save to")?;
+        config.save(&path)?;
+        Ok(config)
+    });
+    match saved {
+        Ok(config) => {
+            config.protocol.apply();
+            *state.config.lock().expect("Failed to lock config") = config;
+            Html(
+                "<span class='text-green-400'>Settings saved, they apply on the next start</span>"
+                    .to_string(),
+            )
+        }
+        Err(e) => Html(format!("<span class='text-red-400'>{}</span>", e)),
+    }
+}
+
 #[derive(Deserialize)]
 struct StartSlaveForm {
     master_ip: String,
@@ -189,7 +403,14 @@ async fn start_slave_handler(
     State(state): State<Arc<AppState>>,
     Form(form): Form<StartSlaveForm>,
 ) -> Html<String> {
-    match state.slave_service.start(form.master_ip) {
+    let mut config = state
+        .config
+        .lock()
+        .expect("Failed to lock config")
+        .slave
+        .clone();
+    config.server = Some(form.master_ip.trim().to_string());
+    match state.slave_service.start(config) {
         Ok(_) => Html("".to_string()),
         Err(e) => Html(format!("<span class='text-red-400'>Error: {}</span>", e)),
     }
@@ -265,11 +486,25 @@ async fn status_handler(State(state): State<Arc<AppState>>) -> Html<String> {
         return Html("<span class='text-red-400'>Master Stopped</span>".to_string());
     }
 
-    let mode_label = if status.remote_mode { "SLAVE" } else { "LOCAL" };
+    let mode_label = match (status.remote_mode, status.cursor_locked) {
+        (true, false) => "SLAVE",
+        (false, false) => "LOCAL",
+        (true, true) => "SLAVE, cursor locked",
+        (false, true) => "LOCAL, cursor locked",
+    };
+    let device_warning = if status.disconnected_devices.is_empty() {
+        String::new()
+    } else {
+        format!(
+            "<div class='text-red-400 font-semibold'>{} disconnected</div>",
+            status.disconnected_devices.join(", ")
+        )
+    };
 
     if status.calibration_mode {
         Html(format!(
             "<div class='text-sm text-yellow-300'>\
+                {}\
                 <div class='font-semibold'>Calibration mode</div>\
                 <div>1) Cursor forced to top-left.</div>\
                 <div>2) Move to bottom-right.</div>\
@@ -277,16 +512,18 @@ async fn status_handler(State(state): State<Arc<AppState>>) -> Html<String> {
                 <div class='mt-1 text-gray-300'>Live cursor: x={}, y={}</div>\
                 <div class='text-gray-400 text-xs'>Clients: {}</div>\
             </div>",
-            status.cursor_x, status.cursor_y, count
+            device_warning, status.cursor_x, status.cursor_y, count
         ))
     } else {
         Html(format!(
             "<div class='text-sm text-green-300'>\
+                {}\
                 <div class='font-semibold'>Master active ({})</div>\
                 <div>Screen: {} × {}</div>\
                 <div>Cursor: x={}, y={}</div>\
                 <div class='text-gray-400 text-xs'>Clients: {}</div>\
             </div>",
+            device_warning,
             mode_label,
             status.master_width,
             status.master_height,
@@ -297,15 +534,34 @@ async fn status_handler(State(state): State<Arc<AppState>>) -> Html<String> {
     }
 }
 
+/// Latency and traffic in the Prometheus text format, like `kmfctl stats` shows them
+async fn metrics_handler() -> impl IntoResponse {
+    (
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        metrics().snapshot().to_prometheus(),
+    )
+}
+
 pub fn run() {
     tauri::Builder::default()
         .plugin(tauri_plugin_shell::init())
         .setup(|app| {
             let app_handle = app.handle().clone();
+            let loaded = Config::load_or_default(None);
+            let log = loaded.as_ref().map(|c| c.log.clone()).unwrap_or_default();
+            if let Err(e) = logging::init(&log, "kmf-gui") {
+                eprintln!("{}", e);
+            }
+            let config = loaded.unwrap_or_else(|e| {
+                warn!("{}, using default settings", e);
+                Config::default()
+            });
+            config.protocol.apply();
 
             let state = Arc::new(AppState {
                 master_service: Arc::new(MasterService::new()),
                 slave_service: Arc::new(SlaveService::new()),
+                config: Mutex::new(config),
                 app_handle,
             });
 
@@ -323,8 +579,12 @@ pub fn run() {
                     .route("/api/start_slave", post(start_slave_handler))
                     .route("/api/stop_slave", post(stop_slave_handler))
                     .route("/api/send_file", post(send_file_handler))
+                    .route("/api/hotkeys", post(hotkeys_handler))
+                    .route("/api/config", post(config_handler))
+                    .route("/api/cursor_lock", post(cursor_lock_handler))
                     .route("/api/status", get(status_handler))
                     .route("/api/slave_status", get(slave_status_handler))
+                    .route("/metrics", get(metrics_handler))
                     .with_state(app_state);
 
                 let listener = tokio::net::TcpListener::bind("127.0.0.1:3000")
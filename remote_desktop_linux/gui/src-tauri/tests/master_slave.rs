@@ -0,0 +1,163 @@
+//! End-to-end master -> slave tests over TCP, driven by scripted input instead of uinput.
+#![cfg(target_os = "linux")]
+
+use std::collections::HashMap;
+use std::sync::atomic::AtomicBool;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use app_lib::driver_loop::DriverLoopContext;
+use app_lib::slave_service::handle_packet;
+use app_lib::status::MasterStatus;
+use kmf_driver::driver::{DriverEvent, EventFrame, RecordingSink, ScriptedSource};
+use kmf_driver::event::{LockState, MouseButton};
+use kmf_driver::source::next_frame;
+use kmf_middleware::clients::spawn_client_handler;
+use kmf_protocol::config::{ServerConfig, ServerMessage};
+use kmf_protocol::transport::{TransportFactory, TransportType};
+use kmf_protocol::Packet;
+use tokio::sync::{broadcast, oneshot};
+use tokio::task::JoinHandle;
+
+const KEY_A: u16 = 30;
+
+fn free_port() -> u16 {
+    std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port()
+}
+
+/// Connects a slave replaying everything into `sink` until the master says quit.
+fn spawn_slave(addr: String, sink: RecordingSink) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut sink = sink;
+        let mut stream = loop {
+            match TransportFactory::connect_client(TransportType::Tcp, &addr).await {
+                Ok(stream) => break stream,
+                Err(_) => tokio::time::sleep(Duration::from_millis(20)).await,
+            }
+        };
+        let config = ServerConfig {
+            version: 1,
+            screen_width: 1920,
+            screen_height: 1080,
+            hostname: "test-slave".into(),
+        };
+        kmf_protocol::send(Packet::ServerHello(config), &mut stream)
+            .await
+            .unwrap();
+
+        loop {
+            let packet = kmf_protocol::receive(&mut stream).await.unwrap();
+            if handle_packet(&mut stream, packet, &mut sink).await.unwrap() {
+                break;
+            }
+        }
+    })
+}
+
+/// Runs `frames` through a master with one slave to the right and returns what the
+/// slave replayed, plus the master writer to check its grab state.
+async fn run_session(frames: Vec<EventFrame>) -> (Vec<EventFrame>, RecordingSink) {
+    let addr = format!("127.0.0.1:{}", free_port());
+    let mut listener = TransportFactory::bind_server(TransportType::Tcp, &addr)
+        .await
+        .unwrap();
+
+    let slave_sink = RecordingSink::default();
+    let slave = spawn_slave(addr, slave_sink.clone());
+
+    let (tx, _) = broadcast::channel(64);
+    let clients = Arc::new(Mutex::new(Vec::new()));
+    let (socket, peer) = listener.accept().await.unwrap();
+    let (_stop_tx, stop_rx) = oneshot::channel();
+    spawn_client_handler(
+        socket,
+        tx.subscribe(),
+        peer,
+        clients,
+        Arc::new(Mutex::new(HashMap::new())),
+        stop_rx,
+    );
+
+    let writer = RecordingSink::default();
+    let mut ctx = DriverLoopContext::new(
+        writer.clone(),
+        tx.clone(),
+        Arc::new(Mutex::new(MasterStatus::default())),
+        Arc::new(AtomicBool::new(true)),
+        100,
+        100,
+    );
+    ctx.calibration_mode = false;
+    ctx.cursor_x = 99;
+    ctx.cursor_y = 50;
+
+    let mut source = ScriptedSource::new(frames);
+    while let Some(frame) = next_frame(&mut source).await {
+        assert!(ctx.handle_frame(&frame, &mut source));
+    }
+
+    tx.send(ServerMessage::Quit).unwrap();
+    tokio::time::timeout(Duration::from_secs(5), slave)
+        .await
+        .expect("slave did not finish")
+        .unwrap();
+
+    (slave_sink.take(), writer)
+}
+
+#[tokio::test]
+async fn slave_replays_input_while_focused() {
+    let (frames, writer) = run_session(vec![
+        vec![DriverEvent::mouse_move(5, 0, 0)],
+        vec![DriverEvent::keyboard_press(KEY_A, true)],
+        vec![DriverEvent::keyboard_press(KEY_A, false)],
+        vec![
+            DriverEvent::mouse_click(MouseButton::Left, true),
+            DriverEvent::mouse_move(3, -4, 0),
+        ],
+        vec![DriverEvent::mouse_click(MouseButton::Left, false)],
+    ])
+    .await;
+
+    assert_eq!(
+        frames,
+        vec![
+            vec![
+                DriverEvent::LockState(LockState::default()),
+                DriverEvent::mouse_move(5, 0, 0),
+            ],
+            vec![DriverEvent::keyboard_press(KEY_A, true)],
+            vec![DriverEvent::keyboard_press(KEY_A, false)],
+            vec![
+                DriverEvent::mouse_click(MouseButton::Left, true),
+                DriverEvent::mouse_move(3, -4, 0),
+            ],
+            vec![DriverEvent::mouse_click(MouseButton::Left, false)],
+        ]
+    );
+    assert_eq!(writer.blocked_inputs(), 1);
+}
+
+#[tokio::test]
+async fn slave_gets_nothing_after_focus_returns() {
+    let (frames, writer) = run_session(vec![
+        vec![DriverEvent::mouse_move(5, 0, 0)],
+        vec![DriverEvent::mouse_move(-30, 0, 0)],
+        vec![DriverEvent::keyboard_press(KEY_A, true)],
+        vec![DriverEvent::keyboard_press(KEY_A, false)],
+    ])
+    .await;
+
+    assert_eq!(
+        frames,
+        vec![vec![
+            DriverEvent::LockState(LockState::default()),
+            DriverEvent::mouse_move(5, 0, 0),
+        ]]
+    );
+    assert_eq!(writer.blocked_inputs(), 0);
+}
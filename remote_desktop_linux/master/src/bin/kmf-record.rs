@@ -0,0 +1,155 @@
+use anyhow::{bail, Result};
+use clap::Parser;
+use kmf_driver::driver::{DeviceReader, DriverEvent, EventFrame};
+use kmf_driver::hotplug::stable_path;
+use kmf_middleware::config::LogConfig;
+use kmf_middleware::hotkeys::{Chord, HotkeyAction, Hotkeys};
+use kmf_middleware::logging;
+use kmf_middleware::recording::{Recorder, RecordingFormat};
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::PathBuf;
+use std::time::Instant;
+use tracing::info;
+
+/// Records keyboard and mouse input with timestamps, for kmf-replay
+#[derive(Debug, Parser)]
+#[command(version, about, long_about = None)]
+struct Args {
+    /// File to write, `.jsonl` records JSON lines, anything else binary
+    output: PathBuf,
+
+    #[arg(short, long)]
+    mouse: Option<PathBuf>,
+
+    #[arg(short, long)]
+    keyboard: Option<PathBuf>,
+
+    /// Format overriding the file extension (json, binary)
+    #[arg(short, long)]
+    format: Option<String>,
+
+    /// Keep the recorded input from reaching this machine, only the failsafe hotkey
+    /// (Ctrl+Alt+Q unless rebound) stops the recording then
+    #[arg(short, long)]
+    grab: bool,
+}
+
+/// Tracks the held keys and reports the frame completing the stop chord.
+struct StopChord {
+    chord: Chord,
+    held: HashSet<u16>,
+}
+
+impl StopChord {
+    fn new(chord: Chord) -> Self {
+        Self {
+            chord,
+            held: HashSet::new(),
+        }
+    }
+
+    /// Whether `frame` presses the chord, the held keys are updated otherwise.
+    fn pressed_in(&mut self, frame: &EventFrame) -> bool {
+        for event in frame {
+            let DriverEvent::KeyboardPress(kp) = event else {
+                continue;
+            };
+            if kp.pressed && !kp.repeat && self.chord.matches(kp.key, &self.held) {
+                return true;
+            }
+            if kp.pressed {
+                self.held.insert(kp.key);
+            } else {
+                self.held.remove(&kp.key);
+            }
+        }
+        false
+    }
+
+    /// Releases of the keys still held, e.g. the chord's modifiers, so a replay
+    /// does not leave them pressed.
+    fn releases(&self) -> EventFrame {
+        let mut keys = self.held.iter().copied().collect::<Vec<_>>();
+        keys.sort_unstable();
+        keys.into_iter()
+            .map(|key| DriverEvent::keyboard_press(key, false))
+            .collect()
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let args = Args::parse();
+    logging::init(&LogConfig::default(), "kmf-record").map_err(anyhow::Error::msg)?;
+    if args.mouse.is_none() && args.keyboard.is_none() {
+        bail!("Nothing to record, pass --mouse and/or --keyboard (see kmf-master --list-devices)");
+    }
+    let mut stop = Hotkeys::load_user()
+        .bindings()
+        .find(|(_, action)| *action == HotkeyAction::StopMaster)
+        .map(|(chord, _)| StopChord::new(chord.clone()));
+    if args.grab && stop.is_none() {
+        bail!("--grab needs a stop_master hotkey to end the recording, Ctrl+C cannot reach the terminal");
+    }
+    let format = match &args.format {
+        Some(format) => format.parse().map_err(anyhow::Error::msg)?,
+        None => RecordingFormat::for_path(&args.output),
+    };
+
+    let mut reader = DeviceReader::new(Vec::new())?;
+    for path in [args.keyboard, args.mouse].into_iter().flatten() {
+        let path = stable_path(&path);
+        reader.add_device(
+            DeviceReader::open_path(path.clone(), args.grab, true)?,
+            path,
+        )?;
+    }
+    let mut reader = reader.into_stream()?;
+
+    let mut recorder = Recorder::new(BufWriter::new(File::create(&args.output)?), format)?;
+    match &stop {
+        Some(stop) => info!(
+            "Recording {:?} to {}, Ctrl+C or {} stops",
+            format,
+            args.output.display(),
+            stop.chord
+        ),
+        None => info!(
+            "Recording {:?} to {}, Ctrl+C stops",
+            format,
+            args.output.display()
+        ),
+    }
+
+    let start = Instant::now();
+    let mut frames = 0usize;
+    loop {
+        tokio::select! {
+            frame = reader.next_frame() => {
+                // the chord itself is not recorded, only what it leaves held is released
+                if let Some(stop) = &mut stop {
+                    if stop.pressed_in(&frame) {
+                        let releases = stop.releases();
+                        if !releases.is_empty() {
+                            recorder.record(start.elapsed(), &releases)?;
+                        }
+                        break;
+                    }
+                }
+                recorder.record(start.elapsed(), &frame)?;
+                frames += 1;
+            }
+            _ = tokio::signal::ctrl_c() => break,
+        }
+    }
+    recorder.finish()?;
+
+    info!(
+        "Recorded {} frame(s) over {:.1}s",
+        frames,
+        start.elapsed().as_secs_f64()
+    );
+    Ok(())
+}
@@ -0,0 +1,124 @@
+use anyhow::{anyhow, bail, Result};
+use clap::Parser;
+use kmf_driver::driver::{DriverWriter, KeyCode, RelativeAxisCode};
+use kmf_middleware::config::LogConfig;
+use kmf_middleware::event::input_frame;
+use kmf_middleware::logging;
+use kmf_middleware::recording::{Playback, RecordedFrame};
+use kmf_protocol::{AsyncStream, Packet, TransportFactory, TransportType};
+use std::fs::File;
+use std::io::BufReader;
+use std::path::PathBuf;
+use std::str::FromStr;
+use tokio::time::{sleep_until, Instant};
+use tracing::{error, info, warn};
+
+/// Replays a kmf-record recording to a slave or on this machine
+#[derive(Debug, Parser)]
+#[command(version, about, long_about = None)]
+struct Args {
+    /// Recording made by kmf-record, JSON lines or binary
+    input: PathBuf,
+
+    /// Playback speed, 2 replays twice as fast, 0.5 at half the speed
+    #[arg(short, long, default_value_t = 1.0)]
+    speed: f64,
+
+    /// Replay through a virtual device on this machine instead of a slave
+    #[arg(long)]
+    local: bool,
+
+    /// Hostname of the slave to replay to, the first one connecting when not given
+    #[arg(long)]
+    slave: Option<String>,
+
+    /// Transport type (tcp, quic)
+    #[arg(short, long, default_value = "tcp")]
+    transport: String,
+
+    /// Bind address slaves connect to
+    #[arg(short, long, default_value = "0.0.0.0:8081")]
+    bind: String,
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let args = Args::parse();
+    logging::init(&LogConfig::default(), "kmf-replay").map_err(anyhow::Error::msg)?;
+    if !(args.speed.is_finite() && args.speed > 0.0) {
+        bail!("Speed must be a positive number, got {}", args.speed);
+    }
+
+    let frames = Playback::new(BufReader::new(File::open(&args.input)?))?
+        .collect::<std::io::Result<Vec<RecordedFrame>>>()
+        .map_err(|e| anyhow!("Failed to read {}: {}", args.input.display(), e))?;
+    let duration = frames.last().map(|f| f.replay_at(args.speed));
+    info!(
+        "Replaying {} frame(s), {:.1}s at {}x speed",
+        frames.len(),
+        duration.unwrap_or_default().as_secs_f64(),
+        args.speed
+    );
+
+    if args.local {
+        let keys = (0..0x2ff).map(KeyCode::new).collect();
+        let axes = vec![
+            RelativeAxisCode::REL_X,
+            RelativeAxisCode::REL_Y,
+            RelativeAxisCode::REL_WHEEL,
+            RelativeAxisCode::REL_HWHEEL,
+        ];
+        let mut writer = DriverWriter::new(keys, axes)?;
+        let start = Instant::now();
+        for frame in &frames {
+            sleep_until(start + frame.replay_at(args.speed)).await;
+            writer.simulate_frame(&frame.events)?;
+        }
+    } else {
+        let transport = TransportType::from_str(&args.transport).map_err(anyhow::Error::msg)?;
+        let mut socket = wait_for_slave(&args.bind, transport, args.slave.as_deref()).await?;
+        let start = Instant::now();
+        for frame in &frames {
+            sleep_until(start + frame.replay_at(args.speed)).await;
+            kmf_protocol::send(Packet::Input(input_frame(&frame.events)), &mut socket).await?;
+            match kmf_protocol::receive(&mut socket).await? {
+                Packet::Ok => {}
+                Packet::Err { code, message } => {
+                    error!("Slave error (code {}): {}", code, message);
+                }
+                other => warn!("Unexpected reply {:?}", other.redacted()),
+            }
+        }
+        let _ = kmf_protocol::send(Packet::ClientQuit, &mut socket).await;
+    }
+
+    info!("Replay finished");
+    Ok(())
+}
+
+/// Accepts slaves until one with the wanted hostname, or any if `None`, says hello.
+async fn wait_for_slave(
+    bind: &str,
+    transport: TransportType,
+    hostname: Option<&str>,
+) -> Result<Box<dyn AsyncStream>> {
+    let mut listener = TransportFactory::bind_server(transport, bind).await?;
+    info!(%bind, ?transport, "Waiting for a slave");
+    loop {
+        let (mut socket, addr) = listener.accept().await?;
+        match kmf_protocol::receive(&mut socket).await {
+            Ok(Packet::ServerHello(config))
+                if hostname.is_none_or(|wanted| wanted == config.hostname) =>
+            {
+                info!(hostname = %config.hostname, %addr, "Replaying to slave");
+                return Ok(socket);
+            }
+            Ok(Packet::ServerHello(config)) => {
+                info!(hostname = %config.hostname, %addr, "Skipping slave");
+                let _ = kmf_protocol::send(Packet::ClientQuit, &mut socket).await;
+            }
+            Ok(other) => error!("Expected ServerHello, got {:?}", other.redacted()),
+            Err(e) => error!("Failed to receive ServerHello: {}", e),
+        }
+    }
+}
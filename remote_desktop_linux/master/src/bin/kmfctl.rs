@@ -0,0 +1,216 @@
+use anyhow::{anyhow, Result};
+use clap::{Parser, Subcommand};
+use kmf_middleware::control::{call, socket_path, ClientEntry, MasterState, SlaveState};
+use kmf_middleware::metrics::{MetricsSnapshot, Summary};
+use serde_json::{json, Value};
+use std::path::PathBuf;
+
+/// Controls a running kmf-master or kmf-slave through its control socket
+#[derive(Debug, Parser)]
+#[command(version, about, long_about = None)]
+struct Args {
+    /// Control socket, the default one of the master or slave when not given
+    #[arg(long, global = true)]
+    socket: Option<PathBuf>,
+
+    /// Talk to the slave instead of the master
+    #[arg(long, global = true)]
+    slave: bool,
+
+    /// Print results as JSON
+    #[arg(long, global = true)]
+    json: bool,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Show whether it runs, since when and with how many clients
+    Status,
+    /// List the slaves connected to the master
+    Clients,
+    /// Send a file to all connected slaves
+    Send { file: PathBuf },
+    /// Move input to a screen, 0 is the master and 1 the slaves
+    Switch { screen: usize },
+    /// Keep the cursor on the screen it is on
+    Lock,
+    /// Let the cursor cross screen edges again
+    Unlock,
+    /// Disconnect a slave by the address `clients` lists
+    Disconnect { address: String },
+    /// Show input latency, broadcast queue depth and traffic per peer
+    Stats,
+    /// Stop the daemon like SIGTERM would
+    Stop,
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let args = Args::parse();
+    let socket = args
+        .socket
+        .unwrap_or_else(|| socket_path(if args.slave { "slave" } else { "master" }));
+
+    let (method, params) = match &args.command {
+        Command::Status => ("status", Value::Null),
+        Command::Clients => ("clients", Value::Null),
+        Command::Send { file } => {
+            // the master resolves paths against its own working directory
+            let path = file
+                .canonicalize()
+                .map_err(|e| anyhow!("{}: {}", file.display(), e))?;
+            ("send_file", json!({ "path": path }))
+        }
+        Command::Switch { screen } => ("switch_screen", json!({ "screen": screen })),
+        Command::Lock => ("lock_cursor", json!({ "locked": true })),
+        Command::Unlock => ("lock_cursor", json!({ "locked": false })),
+        Command::Disconnect { address } => ("disconnect", json!({ "address": address })),
+        Command::Stats => ("stats", Value::Null),
+        Command::Stop => ("stop", Value::Null),
+    };
+    let result = call(&socket, method, params)
+        .await
+        .map_err(anyhow::Error::msg)?;
+
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&result)?);
+        return Ok(());
+    }
+    match args.command {
+        Command::Status if args.slave => print_slave(serde_json::from_value(result)?),
+        Command::Status => print_master(serde_json::from_value(result)?),
+        Command::Clients => print_clients(serde_json::from_value(result)?),
+        Command::Send { file } => println!("Sending {}", file.display()),
+        Command::Switch { screen } => println!("Switched to screen {}", screen),
+        Command::Lock => println!("Cursor locked to its screen"),
+        Command::Unlock => println!("Cursor unlocked"),
+        Command::Disconnect { address } => println!("Disconnected {}", address),
+        Command::Stats => print_stats(serde_json::from_value(result)?),
+        Command::Stop => println!("Stopping"),
+    }
+    Ok(())
+}
+
+fn print_master(state: MasterState) {
+    println!(
+        "master on {} ({}){}",
+        state.bind,
+        state.transport,
+        if state.daemon { ", daemon" } else { "" }
+    );
+    println!("uptime:  {}s", state.uptime_secs);
+    println!("clients: {}", state.clients);
+    if !state.capturing {
+        println!("input:   console only, no devices captured");
+        return;
+    }
+    if state.calibrating {
+        println!("input:   calibrating, move the mouse to the bottom-right and press 'c'");
+    }
+    println!(
+        "screen:  {}{}",
+        if state.active_screen == 0 {
+            "master"
+        } else {
+            "slaves"
+        },
+        if state.cursor_locked { ", locked" } else { "" }
+    );
+    if !state.disconnected_devices.is_empty() {
+        println!("unplugged: {}", state.disconnected_devices.join(", "));
+    }
+}
+
+fn print_slave(state: SlaveState) {
+    println!(
+        "slave {} {} {}",
+        state.hostname,
+        if state.connected {
+            "connected to"
+        } else {
+            "waiting for"
+        },
+        state.server
+    );
+    println!("uptime:  {}s", state.uptime_secs);
+    println!(
+        "layout:  {}",
+        state.layout.as_deref().unwrap_or("raw key codes")
+    );
+    println!("frames:  {}", state.frames);
+}
+
+fn print_clients(clients: Vec<ClientEntry>) {
+    if clients.is_empty() {
+        println!("No clients connected");
+    }
+    for (index, client) in clients.iter().enumerate() {
+        println!(
+            "{} | {} | {} | {}x{}",
+            index + 1,
+            client.hostname,
+            client.address,
+            client.screen_width,
+            client.screen_height
+        );
+    }
+}
+
+fn print_stats(stats: MetricsSnapshot) {
+    println!("uptime:     {}s", stats.uptime_secs);
+    print_latency("latency:", &stats.latency_us);
+    print_latency("round trip:", &stats.round_trip_us);
+    if stats.queue_depth.count > 0 {
+        let depth = &stats.queue_depth;
+        println!(
+            "queue:      p50 {} | p90 {} | p99 {} | max {} messages",
+            depth.p50, depth.p90, depth.p99, depth.max
+        );
+    }
+    println!(
+        "dropped:    {} frame(s) without slaves, {} message(s) lagged",
+        stats.dropped, stats.lagged
+    );
+    println!(
+        "traffic:    {} sent, {} received",
+        bytes(stats.bytes_sent),
+        bytes(stats.bytes_received)
+    );
+    for peer in &stats.peers {
+        println!(
+            "  {} | {} sent | {} received | {} lagged",
+            peer.address,
+            bytes(peer.bytes_sent),
+            bytes(peer.bytes_received),
+            peer.lagged
+        );
+    }
+}
+
+/// Skipped when nothing was measured, the master has no latency and the slave no round trip
+fn print_latency(label: &str, summary: &Summary) {
+    if summary.count == 0 {
+        return;
+    }
+    let ms = |us: u64| us as f64 / 1000.0;
+    println!(
+        "{:<11} p50 {:.2} | p90 {:.2} | p99 {:.2} | max {:.2} ms over {} frames",
+        label,
+        ms(summary.p50),
+        ms(summary.p90),
+        ms(summary.p99),
+        ms(summary.max),
+        summary.count
+    );
+}
+
+fn bytes(count: u64) -> String {
+    match count {
+        0..1024 => format!("{} B", count),
+        1024..1_048_576 => format!("{:.1} KiB", count as f64 / 1024.0),
+        _ => format!("{:.1} MiB", count as f64 / 1_048_576.0),
+    }
+}
@@ -1,22 +1,46 @@
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use clap::Parser;
-use kmf_driver::driver::{DeviceReader, DriverWriter};
-use kmf_middleware::command::parse_command;
+use kmf_driver::driver::DeviceReader;
+use kmf_driver::layout::Layout;
+use kmf_middleware::capture::Capture;
+use kmf_middleware::clients::{spawn_client_handler, Clients, Stoppers};
+use kmf_middleware::command::{check_arity, command_names, help, parse_verb, tokenize, Commands};
+use kmf_middleware::config::Config;
+use kmf_middleware::control::{
+    parse_params, socket_path, termination, unknown_method, ControlServer, MasterState,
+    MASTER_METHODS,
+};
+use kmf_middleware::hotkeys::{Hotkeys, Modifier};
+use kmf_middleware::logging;
+use kmf_middleware::metrics::metrics;
+use kmf_middleware::script::{Script, Step};
+use kmf_middleware::status::MasterStatus;
 use kmf_protocol::config::ServerMessage;
 use kmf_protocol::{Packet, TransportFactory, TransportType};
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::HashMap;
 use std::io;
 use std::io::{BufRead, Write};
+use std::ops::ControlFlow;
 use std::path::{Path, PathBuf};
-use std::str::FromStr;
 use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::Arc;
-use std::time::Duration;
-use tokio::sync::broadcast;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::{broadcast, oneshot, watch};
 use tokio::time::sleep;
+use tracing::{debug, error, info, warn};
+
+/// Messages the broadcast channel queues for slow slaves before they lag
+const BROADCAST_CAPACITY: usize = 100;
 
 #[derive(Debug, Parser)]
 #[command(version, about, long_about = None)]
 struct Args {
+    /// Config file, defaults to $XDG_CONFIG_HOME/kmf/config.toml
+    #[arg(long)]
+    config: Option<PathBuf>,
+
     #[arg(short, long)]
     mouse: Option<String>,
 
@@ -24,95 +48,250 @@ struct Args {
     keyboard: Option<String>,
 
     /// Transport type (tcp, quic)
-    #[arg(short, long, default_value = "tcp")]
-    transport: String,
+    #[arg(short, long)]
+    transport: Option<TransportType>,
 
     /// Bind address for server
-    #[arg(short, long, default_value = "0.0.0.0:8081")]
-    bind: String,
-}
+    #[arg(short, long)]
+    bind: Option<String>,
 
-#[tokio::main]
-async fn main() -> Result<()> {
-    let args = Args::parse();
+    /// Print the detected input devices and exit
+    #[arg(long)]
+    list_devices: bool,
 
-    println!("devices:");
-    for (path, name, t) in DeviceReader::list_devices() {
-        println!("{t:?} | {name} | {}", path.display());
-    }
+    /// Also capture keyboards and mice plugged in while running
+    #[arg(long)]
+    auto_attach: bool,
 
-    // Parse transport type
-    let transport = TransportType::from_str(&args.transport).unwrap_or_else(|e| {
-        eprintln!("Warning: {}. Using TCP.", e);
-        TransportType::Tcp
-    });
+    /// Send the typed characters along in this layout (or `auto`), for slaves with another layout
+    #[arg(long)]
+    send_layout: Option<String>,
 
-    println!(
-        "kmf-master starting with {:?} transport on {}",
-        transport, args.bind
-    );
-    run_master(&args.bind, transport).await?;
+    /// Milliseconds the cursor has to push against the edge before crossing
+    #[arg(long)]
+    edge_dwell: Option<u64>,
 
-    let mouse = args.mouse.map(|m| PathBuf::from_str(&m).unwrap());
-    let keyboard = args.keyboard.map(|k| PathBuf::from_str(&k).unwrap());
+    /// Cross only on a second push against the edge within this many milliseconds
+    #[arg(long)]
+    edge_double_tap: Option<u64>,
 
-    let mut devices = Vec::new();
+    /// Cross only while this modifier is held (ctrl, shift, alt, meta)
+    #[arg(long)]
+    edge_modifier: Option<Modifier>,
 
-    if let Some(keyboard) = keyboard.map(|k| DeviceReader::open_path(k, true, true).unwrap()) {
-        devices.push(keyboard);
-    }
+    /// Height in pixels at the top and bottom of the edge where it cannot be crossed
+    #[arg(long)]
+    edge_corner: Option<i32>,
 
-    if let Some(mouse) = mouse.map(|m| DeviceReader::open_path(m, false, true).unwrap()) {
-        devices.push(mouse);
-    }
+    /// No crossing with a mouse button held
+    #[arg(long)]
+    edge_button_guard: bool,
 
-    let mut reader = DeviceReader::new(devices).unwrap();
-    let keys = reader.available_keys().unwrap_or_default();
-    let axes = reader.available_axes().unwrap_or_default();
+    /// Console script to run once the first slave connects
+    #[arg(long)]
+    script: Option<PathBuf>,
 
-    let mut writer = DriverWriter::new(keys, axes)?;
+    /// Run headless without the console, controlled through kmfctl until SIGTERM
+    #[arg(long)]
+    daemon: bool,
 
-    loop {
-        let events = reader.read_events()?;
+    /// Control socket for kmfctl, defaults to $XDG_RUNTIME_DIR/kmf/master.sock
+    #[arg(long)]
+    control: Option<PathBuf>,
+}
 
-        for e in events {
-            println!("event: {e:?}");
-            writer.simulate_event(e)?;
+impl Args {
+    /// The config file's settings with the ones given on the command line replacing them.
+    fn config(&self) -> Result<Config> {
+        let mut config =
+            Config::load_or_default(self.config.as_deref()).map_err(anyhow::Error::msg)?;
+        let master = &mut config.master;
+        if let Some(mouse) = &self.mouse {
+            master.mouse = Some(mouse.clone());
+        }
+        if let Some(keyboard) = &self.keyboard {
+            master.keyboard = Some(keyboard.clone());
+        }
+        if let Some(transport) = self.transport {
+            master.transport = transport;
+        }
+        if let Some(bind) = &self.bind {
+            master.bind = bind.clone();
+        }
+        master.auto_attach |= self.auto_attach;
+        if let Some(layout) = &self.send_layout {
+            master.send_layout = Some(layout.clone());
+        }
+        if let Some(dwell) = self.edge_dwell {
+            master.edge.dwell_ms = dwell;
         }
-        std::thread::sleep(std::time::Duration::from_millis(50));
+        if let Some(double_tap) = self.edge_double_tap {
+            master.edge.double_tap_ms = Some(double_tap);
+        }
+        if let Some(modifier) = self.edge_modifier {
+            master.edge.modifier = Some(modifier);
+        }
+        if let Some(corner) = self.edge_corner {
+            master.edge.corner_size = corner;
+        }
+        master.edge.button_guard |= self.edge_button_guard;
+        config
+            .validate()
+            .map_err(|e| anyhow!("Invalid settings: {}", e))?;
+        Ok(config)
     }
 }
 
+#[tokio::main]
+async fn main() -> Result<()> {
+    let args = Args::parse();
+
+    if args.list_devices {
+        print_devices();
+        return Ok(());
+    }
+
+    let config = args.config()?;
+    logging::init(&config.log, "kmf-master").map_err(anyhow::Error::msg)?;
+    config.protocol.apply();
+    let master = config.master;
+    info!(
+        transport = ?master.transport,
+        bind = %master.bind,
+        "kmf-master starting"
+    );
+    // `type` strings are typed with the keys of this machine's layout
+    let layout = Layout::detect()
+        .or_else(|| Layout::named("us"))
+        .ok_or_else(|| anyhow!("No keyboard layout to type in, libxkbcommon is missing"))?;
+    let script = match &args.script {
+        Some(path) => Some(Script::load(path, &layout).map_err(anyhow::Error::msg)?),
+        None => None,
+    };
+    let console = Console {
+        layout,
+        script,
+        interactive: !args.daemon,
+    };
+    let capture = if master.mouse.is_none() && master.keyboard.is_none() && !master.auto_attach {
+        info!("No input devices given, only the console feeds the slaves");
+        None
+    } else {
+        Some(Capture::open(&master).map_err(anyhow::Error::msg)?)
+    };
+    let control = args
+        .control
+        .clone()
+        .unwrap_or_else(|| socket_path("master"));
+    run_master(&master.bind, master.transport, console, capture, &control).await
+}
+
+/// Prints all input devices with their kind, ids and stable path.
+fn print_devices() {
+    println!("devices:");
+    for info in DeviceReader::list_devices() {
+        println!(
+            "{:?} | {} | {} | {:04x}:{:04x} | {}",
+            info.device_type,
+            info.name,
+            info.path.display(),
+            info.vendor,
+            info.product,
+            info.stable_path().display()
+        );
+    }
+}
+
+/// What drives the master besides the control socket
+pub struct Console {
+    /// Layout console `type` commands are typed with
+    pub layout: Layout,
+    /// Script run once the first slave connects
+    pub script: Option<Script>,
+    /// Whether commands are read from stdin after the script
+    pub interactive: bool,
+}
+
 /// Starts the server and listens for client connections.
 /// # Arguments
 ///
 /// * `bind_addr` - The address to bind to (e.g., "0.0.0.0:8080")
 /// * `transport` - The transport type to use (TCP, QUIC, etc.)
+/// * `console` - Script and stdin console feeding the slaves
+/// * `capture` - Input devices forwarded to the slaves while the cursor is on their screen
+/// * `control_path` - Unix socket kmfctl connects to
 ///
 /// # Returns
 ///
-/// Returns `Ok(())` on success, or an error if binding fails.
-pub async fn run_master(bind_addr: &str, transport: TransportType) -> anyhow::Result<()> {
+/// Returns `Ok(())` once stopped by `quit`, SIGTERM or `kmfctl stop`, or an error if binding fails.
+pub async fn run_master(
+    bind_addr: &str,
+    transport: TransportType,
+    console: Console,
+    capture: Option<Capture>,
+    control_path: &Path,
+) -> anyhow::Result<()> {
     let mut listener = TransportFactory::bind_server(transport, bind_addr).await?;
-    println!(
-        "Server listening on {} using {:?} transport",
-        bind_addr, transport
-    );
+    info!(bind = %bind_addr, ?transport, "Server listening");
 
     // Use broadcast channel to send server messages to all connected clients
-    // Channel capacity of 100 means up to 100 messages can be queued
-    let (tx, _rx) = broadcast::channel::<ServerMessage>(100);
+    let (tx, _rx) = broadcast::channel::<ServerMessage>(BROADCAST_CAPACITY);
 
     // Shared flag to signal server shutdown
     let shutdown = Arc::new(AtomicBool::new(false));
     let shutdown_clone = shutdown.clone();
 
-    spawn_input_handler(tx.clone(), shutdown_clone);
+    let (screen, screen_rx) = watch::channel(0);
+    let control = Arc::new(MasterControl {
+        bind: bind_addr.to_string(),
+        transport,
+        daemon: !console.interactive,
+        capturing: capture.is_some(),
+        started: Instant::now(),
+        tx: tx.clone(),
+        clients: Arc::new(Mutex::new(Vec::new())),
+        stoppers: Arc::new(Mutex::new(HashMap::new())),
+        status: Arc::new(Mutex::new(MasterStatus::default())),
+        cursor_lock: Arc::new(AtomicBool::new(false)),
+        screen,
+        shutdown: shutdown.clone(),
+    });
+    let handler = control.clone();
+    let _control_server = ControlServer::bind(
+        control_path,
+        Arc::new(move |method: &str, params: Value| handler.handle(method, params)),
+    )
+    .await
+    .map_err(|e| anyhow::anyhow!("Control socket {}: {}", control_path.display(), e))?;
+    info!("Control socket at {}", control_path.display());
+
+    let shutdown_signal = shutdown.clone();
+    tokio::spawn(async move {
+        termination().await;
+        info!("Termination requested");
+        shutdown_signal.store(true, Ordering::Relaxed);
+    });
+
+    // cleared by the failsafe hotkey, which stops the whole master
+    let capturing = Arc::new(AtomicBool::new(true));
+    let capture_handle = capture.map(|capture| {
+        capture.spawn(
+            tx.clone(),
+            control.status.clone(),
+            capturing.clone(),
+            Arc::new(Mutex::new(Hotkeys::load_user())),
+            control.cursor_lock.clone(),
+            screen_rx,
+        )
+    });
+
+    spawn_input_handler(tx.clone(), shutdown_clone, console);
 
     loop {
         // Check if shutdown was requested
-        if shutdown.load(Ordering::Relaxed) {
-            println!("[INFO] Server shutting down...");
+        if shutdown.load(Ordering::Relaxed) || !capturing.load(Ordering::SeqCst) {
+            info!("Server shutting down...");
+            capturing.store(false, Ordering::SeqCst);
             // Send quit to all connected clients
             let _ = tx.send(ServerMessage::Quit);
             // Give clients time to receive the quit message
@@ -126,12 +305,25 @@ pub async fn run_master(bind_addr: &str, transport: TransportType) -> anyhow::Re
 
         match accept_result {
             Ok(Ok((socket, addr))) => {
-                println!("[INFO] New client connected: {}", addr);
+                info!("New client connected: {}", addr);
                 let rx = tx.subscribe();
-                spawn_client_handler(socket, rx);
+                let (stop_tx, stop_rx) = oneshot::channel();
+                control
+                    .stoppers
+                    .lock()
+                    .expect("Failed to lock stoppers")
+                    .insert(addr.clone(), stop_tx);
+                spawn_client_handler(
+                    socket,
+                    rx,
+                    addr,
+                    control.clients.clone(),
+                    control.stoppers.clone(),
+                    stop_rx,
+                );
             }
             Ok(Err(e)) => {
-                eprintln!("[ERROR] Failed to accept connection: {}", e);
+                error!("Failed to accept connection: {}", e);
             }
             Err(_) => {
                 // Timeout - just continue loop to check shutdown flag
@@ -140,10 +332,153 @@ pub async fn run_master(bind_addr: &str, transport: TransportType) -> anyhow::Re
         }
     }
 
-    println!("[INFO] Server stopped.");
+    if let Some(handle) = capture_handle {
+        let _ = handle.await;
+    }
+    info!("Server stopped.");
     Ok(())
 }
 
+/// Master state the control socket reports and changes
+struct MasterControl {
+    bind: String,
+    transport: TransportType,
+    daemon: bool,
+    /// Whether input devices are captured, switching screens needs them
+    capturing: bool,
+    started: Instant,
+    tx: broadcast::Sender<ServerMessage>,
+    /// Slaves that completed the handshake, in connection order
+    clients: Clients,
+    /// Disconnects a client by its address
+    stoppers: Stoppers,
+    status: Arc<Mutex<MasterStatus>>,
+    cursor_lock: Arc<AtomicBool>,
+    /// Screen the capture loop is asked to focus, 0 for the master and 1 for the slaves.
+    /// Every value sent is applied by [`DriverLoopContext::focus_screen`]
+    screen: watch::Sender<usize>,
+    shutdown: Arc<AtomicBool>,
+}
+
+#[derive(Deserialize)]
+struct SendFileParams {
+    path: PathBuf,
+}
+
+#[derive(Deserialize)]
+struct SwitchScreenParams {
+    screen: usize,
+}
+
+#[derive(Deserialize)]
+struct LockCursorParams {
+    locked: bool,
+}
+
+#[derive(Deserialize)]
+struct DisconnectParams {
+    address: String,
+}
+
+impl MasterControl {
+    /// Answers a call on the control socket.
+    fn handle(&self, method: &str, params: Value) -> Result<Value, String> {
+        match method {
+            "status" => {
+                let clients = self.clients.lock().expect("Failed to lock clients").len();
+                let status = self.status.lock().expect("Failed to lock status");
+                to_value(MasterState {
+                    bind: self.bind.clone(),
+                    transport: format!("{:?}", self.transport).to_lowercase(),
+                    daemon: self.daemon,
+                    uptime_secs: self.started.elapsed().as_secs(),
+                    clients,
+                    capturing: self.capturing,
+                    calibrating: self.capturing && status.calibration_mode,
+                    active_screen: usize::from(status.remote_mode),
+                    cursor_locked: status.cursor_locked,
+                    disconnected_devices: status.disconnected_devices.clone(),
+                })
+            }
+            "stats" => to_value(metrics().snapshot()),
+            "clients" => to_value(&*self.clients.lock().expect("Failed to lock clients")),
+            "send_file" => {
+                let SendFileParams { path } = parse_params(method, params)?;
+                if !path.is_file() {
+                    return Err(format!("No such file: {}", path.display()));
+                }
+                if self.tx.receiver_count() == 0 {
+                    return Err("No clients connected".to_string());
+                }
+                self.tx
+                    .send(ServerMessage::File {
+                        path: path.display().to_string(),
+                    })
+                    .map_err(|_| "No clients connected".to_string())?;
+                Ok(Value::Null)
+            }
+            "switch_screen" => {
+                let SwitchScreenParams { screen } = parse_params(method, params)?;
+                if !self.capturing {
+                    return Err("No input devices are captured".to_string());
+                }
+                if self
+                    .status
+                    .lock()
+                    .expect("Failed to lock status")
+                    .calibration_mode
+                {
+                    return Err(
+                        "Not calibrated yet, move the mouse to the bottom-right and press 'c'"
+                            .to_string(),
+                    );
+                }
+                if screen > 1 {
+                    return Err(format!(
+                        "No screen {}, 0 is this machine and 1 the slaves",
+                        screen
+                    ));
+                }
+                // the capture loop is the only receiver, gone once it stopped
+                self.screen
+                    .send(screen)
+                    .map_err(|_| "The input capture has stopped".to_string())?;
+                Ok(Value::Null)
+            }
+            "lock_cursor" => {
+                let LockCursorParams { locked } = parse_params(method, params)?;
+                self.cursor_lock.store(locked, Ordering::SeqCst);
+                self.status
+                    .lock()
+                    .expect("Failed to lock status")
+                    .cursor_locked = locked;
+                Ok(Value::Null)
+            }
+            "disconnect" => {
+                let DisconnectParams { address } = parse_params(method, params)?;
+                let stopper = self
+                    .stoppers
+                    .lock()
+                    .expect("Failed to lock stoppers")
+                    .remove(&address)
+                    .ok_or_else(|| format!("No client {}", address))?;
+                let _ = stopper.send(());
+                Ok(Value::Null)
+            }
+            "stop" => {
+                info!("Stop requested over the control socket");
+                self.shutdown.store(true, Ordering::Relaxed);
+                Ok(Value::Null)
+            }
+            _ => Err(unknown_method(method, MASTER_METHODS)),
+        }
+    }
+}
+
+fn to_value(value: impl serde::Serialize) -> Result<Value, String> {
+    serde_json::to_value(value).map_err(|e| e.to_string())
+}
+
 /// Spawns a dedicated OS thread for handling command-line input.
 ///
 /// This function **must** run in a separate OS thread (not a tokio task) because:
@@ -151,132 +486,138 @@ pub async fn run_master(bind_addr: &str, transport: TransportType) -> anyhow::Re
 /// - Blocking the tokio runtime would prevent network operations
 /// - OS threads allow true parallel execution with the async runtime
 ///
-/// The thread continuously reads commands and broadcasts them to all connected clients.
+/// The thread first runs the console script, if any, as soon as a slave is connected,
+/// then, unless running as a daemon, continuously reads console commands (see
+/// [`Script`]) and broadcasts them to all connected clients. Lines are collected
+/// until an open `repeat` block is closed.
 ///
 /// # Arguments
 ///
 /// * `tx` - Broadcast sender for distributing commands to client handlers
 /// * `shutdown` - Atomic flag to signal server shutdown when quit is entered
-pub fn spawn_input_handler(tx: broadcast::Sender<ServerMessage>, shutdown: Arc<AtomicBool>) {
+/// * `console` - Layout, script and whether to read stdin
+pub fn spawn_input_handler(
+    tx: broadcast::Sender<ServerMessage>,
+    shutdown: Arc<AtomicBool>,
+    console: Console,
+) {
+    let Console {
+        layout,
+        script,
+        interactive,
+    } = console;
     // IMPORTANT: Use std::thread::spawn, NOT tokio::spawn
     std::thread::spawn(move || {
+        if let Some(script) = script {
+            info!("Waiting for a slave to run the script...");
+            while tx.receiver_count() == 0 {
+                std::thread::sleep(Duration::from_millis(100));
+            }
+            if run_script(&script, &tx, &shutdown).is_break() {
+                return;
+            }
+            info!("Script finished");
+        }
+        if !interactive {
+            return;
+        }
+
         let stdin = io::stdin();
         let mut reader = stdin.lock();
         let mut line = String::new();
+        let mut pending = String::new();
 
         loop {
-            print!("Enter command (move <x> <y> | click <left|right|middle> <down|up> | key <key> <down|up> | file <path> | quit): ");
+            if pending.is_empty() {
+                print!("Enter command ({}): ", command_names());
+            } else {
+                print!("... ");
+            }
             io::stdout().flush().expect("Failed to flush stdout");
 
             line.clear();
-            if reader.read_line(&mut line).is_err() {
-                eprintln!("Failed to read input");
-                continue;
+            match reader.read_line(&mut line) {
+                Ok(0) => break,
+                Ok(_) => {}
+                Err(_) => {
+                    error!("Failed to read input");
+                    continue;
+                }
             }
-
-            let input = line.trim();
-            if let Some(message) = parse_command(input) {
-                // Check if it's a quit command
-                if matches!(message, ServerMessage::Quit) {
-                    println!("[INFO] Quit command received. Shutting down server...");
-                    shutdown.store(true, Ordering::Relaxed);
-                    // Still broadcast quit to connected clients
-                    let _ = tx.send(message);
-                    break; // Exit the input handler thread
+            if pending.is_empty() {
+                if let Some(text) = console_help(&line) {
+                    println!("{}", text);
+                    continue;
                 }
+            }
+            pending.push_str(&line);
 
-                match tx.send(message) {
-                    Ok(n) => println!("[INFO] Command broadcast to {} client(s)", n),
-                    Err(_) => println!("[WARN] No clients connected"),
+            let script = match Script::parse(&pending, &layout) {
+                Ok(script) => script,
+                Err(e) if e.is_incomplete() => continue,
+                Err(e) => {
+                    let text = pending.lines().nth(e.line - 1).unwrap_or_default();
+                    eprintln!("{}", e.error.report(text));
+                    pending.clear();
+                    continue;
                 }
-            } else if !input.is_empty() {
-                eprintln!("Unknown command");
+            };
+            pending.clear();
+            if run_script(&script, &tx, &shutdown).is_break() {
+                break; // Exit the input handler thread
             }
         }
     });
 }
 
-/// Spawns an async task to handle a connected client.
-/// # Arguments
-///
-/// * `socket` - The stream for this client connection
-/// * `rx` - Broadcast receiver subscribed to server messages
-///
-/// # Note
-///
-/// This function spawns a tokio task that runs until:
-/// - The client disconnects
-/// - An error occurs during send/receive
-/// - A Quit message is broadcast
-pub fn spawn_client_handler(
-    mut socket: Box<dyn kmf_protocol::AsyncStream>,
-    mut rx: broadcast::Receiver<ServerMessage>,
-) {
-    tokio::spawn(async move {
-        // Wait for client's ServerHello - this is the handshake protocol
-        match kmf_protocol::receive(&mut socket).await {
-            Ok(Packet::ServerHello(config)) => {
-                println!("[INFO] Client config: {:?}", config);
-            }
-            Ok(other) => {
-                eprintln!("[ERROR] Expected ServerHello, got {:?}", other);
-                return;
-            }
-            Err(e) => {
-                eprintln!("[ERROR] Failed to receive ServerHello: {}", e);
-                return;
-            }
-        }
+/// Output of a `help` line, `None` for other lines.
+fn console_help(line: &str) -> Option<String> {
+    let tokens = tokenize(line).ok()?;
+    if tokens.first()?.text != Commands::Help.name() {
+        return None;
+    }
+    let text = parse_verb(&tokens[0])
+        .and_then(|spec| check_arity(spec, &tokens[0], &tokens[1..], line.trim_end().len()))
+        .and_then(|()| help(tokens.get(1)));
+    Some(text.unwrap_or_else(|e| e.report(line)))
+}
 
-        // Main message handling loop
-        loop {
-            match rx.recv().await {
-                Ok(ServerMessage::Action(action)) => {
-                    println!("[DEBUG] Broadcasting action to client");
-                    if let Err(e) = kmf_protocol::send(Packet::Action(action), &mut socket).await {
-                        eprintln!("[ERROR] Failed to send action to client: {}", e);
-                        break;
-                    }
-
-                    // Wait for acknowledgment - ensures client received and processed action
-                    match kmf_protocol::receive(&mut socket).await {
-                        Ok(Packet::Ok) => {
-                            println!("[DEBUG] Client acknowledged action")
-                        }
-                        Ok(Packet::Err { code, message }) => {
-                            eprintln!("[ERROR] Client error (code {}): {}", code, message);
-                        }
-                        Err(e) => {
-                            eprintln!("[ERROR] Failed to receive ack: {}", e);
-                            break;
-                        }
-                        _ => {}
-                    }
-                }
-                Ok(ServerMessage::File { path }) => {
-                    println!("[DEBUG] Broadcasting file to client");
-                    match kmf_middleware::file_transfer::send_file(&mut socket, &path).await {
-                        Ok(_) => println!("[INFO] File sent"),
-                        Err(e) => {
-                            eprintln!("[ERROR] Failed to send file: {}", e);
-                            break;
-                        }
-                    }
-                }
-                Ok(ServerMessage::Quit) => {
-                    println!("[INFO] Sending quit to client");
-                    let _ = kmf_protocol::send(Packet::ClientQuit, &mut socket).await;
-                    break;
+/// Broadcasts the steps of `script`, breaking once it quit the server.
+fn run_script(
+    script: &Script,
+    tx: &broadcast::Sender<ServerMessage>,
+    shutdown: &AtomicBool,
+) -> ControlFlow<()> {
+    let mut sent = 0;
+    let flow = script.run(|step| {
+        match step {
+            Step::Sleep(duration) => std::thread::sleep(*duration),
+            Step::Send(ServerMessage::Quit) => {
+                info!("Quit command received. Shutting down server...");
+                shutdown.store(true, Ordering::Relaxed);
+                // Still broadcast quit to connected clients
+                let _ = tx.send(ServerMessage::Quit);
+                return ControlFlow::Break(());
+            }
+            Step::Send(message) => {
+                // Let slaves catch up instead of lagging behind the broadcast channel
+                while tx.len() >= BROADCAST_CAPACITY / 2 {
+                    std::thread::sleep(Duration::from_millis(5));
                 }
-                Err(e) => {
-                    eprintln!("[ERROR] Broadcast receive error: {}", e);
-                    break;
+                if tx.send(message.clone()).is_ok() {
+                    sent += 1;
                 }
             }
         }
-
-        println!("[INFO] Client disconnected");
+        ControlFlow::Continue(())
     });
+    if flow.is_continue() && !script.is_empty() {
+        match tx.receiver_count() {
+            0 => warn!("No clients connected"),
+            n => info!("{} message(s) broadcast to {} client(s)", sent, n),
+        }
+    }
+    flow
 }
 
 /// Sends a file to a client using the two-protocol protocol.
@@ -304,11 +645,11 @@ async fn send_file(
 
     kmf_protocol::send(Packet::Data(data.clone()), socket).await?;
 
-    println!("[DEBUG] File sent: {} ({} bytes)", filename, data.len());
+    debug!("File sent: {} ({} bytes)", filename, data.len());
 
     match kmf_protocol::receive(socket).await {
         Ok(Packet::Ok) => {
-            println!("[DEBUG] File transfer acknowledged");
+            debug!("File transfer acknowledged");
             Ok(())
         }
         Ok(Packet::Err {
@@ -6,42 +6,23 @@ if [ "$EUID" -ne 0 ]; then
     exit 1
 fi
 
-# Check if libinput-tools is installed
-if ! command -v libinput &> /dev/null; then
-    echo "❌ 'libinput-tools' is not installed."
-    echo "➡ Please install it using: sudo apt install libinput-tools"
+# Device detection lives in kmf-driver, kmf-master prints it with --list-devices
+ROOT_DIR="$(cd "$(dirname "$0")/.." && pwd)"
+
+if command -v kmf-master &> /dev/null; then
+    MASTER="kmf-master"
+elif [ -x "$ROOT_DIR/target/release/kmf-master" ]; then
+    MASTER="$ROOT_DIR/target/release/kmf-master"
+elif [ -x "$ROOT_DIR/target/debug/kmf-master" ]; then
+    MASTER="$ROOT_DIR/target/debug/kmf-master"
+else
+    echo "❌ 'kmf-master' was not found."
+    echo "➡ Please build it first using: cargo build --bin kmf-master"
     exit 1
 fi
 
-echo "Scanning for mice and keyboards..."
+echo "Scanning for input devices..."
 echo "------------------------------------"
+echo "type | name | node | vendor:product | stable path"
 
-# Use awk to parse the output of libinput list-devices
-libinput list-devices | awk '
-    # When a line starts with "Device:", store the device name
-    /^Device:/ {
-        # Remove "Device:" prefix and leading/trailing whitespace
-        gsub(/^Device:\s*|\s*$/, "");
-        device_name = $0;
-    }
-    # When a line starts with "Kernel:", store the device path
-    /^Kernel:/ {
-        # Remove "Kernel:" prefix and leading/trailing whitespace
-        gsub(/^Kernel:\s*|\s*$/, "");
-        device_path = $0;
-    }
-    # When a line starts with "Capabilities:", check for "pointer" or "keyboard"
-    /^Capabilities:/ {
-        if ($0 ~ /pointer/) {
-            printf "✔ Found Mouse:\n";
-            printf "  Name: %s\n", device_name;
-            printf "  Path: %s\n\n", device_path;
-        }
-        if ($0 ~ /keyboard/) {
-            printf "✔ Found Keyboard:\n";
-            printf "  Name: %s\n", device_name;
-            printf "  Path: %s\n\n", device_path;
-        }
-    }
-'
-  
\ No newline at end of file
+"$MASTER" --list-devices | tail -n +2
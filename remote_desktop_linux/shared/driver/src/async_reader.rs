@@ -0,0 +1,309 @@
+use std::collections::VecDeque;
+use std::future::poll_fn;
+use std::io::Result;
+use std::os::fd::AsFd;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use evdev::{Device, EventStream, EventType, InputEvent, KeyCode, SynchronizationCode};
+use futures_core::Stream;
+
+use crate::event::{DriverEvent, EventFrame, LockState};
+use crate::grab::InputGrab;
+use crate::reader::{is_replayed, is_unplugged, DeviceState};
+use crate::stream::{InputStream, ToStream};
+use crate::touchpad::TouchpadConfig;
+
+/// Async counterpart of [`crate::reader::DriverReader`], merging the event streams
+/// of all devices into one.
+///
+/// Complete frames are read with [`DriverStream::next_frame`], single events through
+/// the `Stream` implementation. Nothing is polled on a timer, the task is woken by
+/// the devices. With no device attached the stream stays pending instead of ending,
+/// so devices can be added back after an unplug.
+pub struct DriverStream<T>
+where
+    T: InputStream + Unpin,
+{
+    sources: Vec<T>,
+    states: Vec<DeviceState>,
+    touchpad_config: TouchpadConfig,
+    /// Grab over the devices, shared with whoever else suppresses them
+    grab: InputGrab,
+    /// Whether the stream holds `grab`
+    grabbed: bool,
+    /// Decoded frames not handed out yet
+    ready: VecDeque<EventFrame>,
+    /// Rest of the frame being handed out event by event
+    current: VecDeque<DriverEvent>,
+    /// Paths of devices dropped because they were unplugged
+    disconnected: Vec<PathBuf>,
+}
+
+pub type DeviceStream = DriverStream<EventStream>;
+
+impl<T> Default for DriverStream<T>
+where
+    T: InputStream + Unpin,
+{
+    fn default() -> Self {
+        Self::with_touchpad_config(TouchpadConfig::default())
+    }
+}
+
+impl<T> DriverStream<T>
+where
+    T: InputStream + Unpin,
+{
+    pub fn with_touchpad_config(config: TouchpadConfig) -> Self {
+        Self::with_grab(config, InputGrab::default())
+    }
+
+    pub(crate) fn with_grab(config: TouchpadConfig, grab: InputGrab) -> Self {
+        Self {
+            sources: Vec::new(),
+            states: Vec::new(),
+            touchpad_config: config,
+            grab,
+            grabbed: false,
+            ready: VecDeque::new(),
+            current: VecDeque::new(),
+            disconnected: Vec::new(),
+        }
+    }
+
+    pub(crate) fn push_source(&mut self, source: T, state: DeviceState) {
+        self.sources.push(source);
+        self.states.push(state);
+    }
+
+    pub(crate) fn set_grabbed(&mut self, grabbed: bool) {
+        self.grabbed = grabbed;
+    }
+
+    /// Grabs the devices for as long as the stream wants them, see [`Self::grab_with`].
+    pub fn grab_inputs(&mut self) -> Result<()> {
+        if !self.grabbed {
+            let grab = self.grab.clone();
+            self.grab_with(|| grab.acquire())?;
+            self.grabbed = true;
+        }
+        Ok(())
+    }
+
+    /// Runs `grab`, which takes a hold on the stream's [`InputGrab`], e.g. through
+    /// [`crate::writer::DriverWriter::block_inputs`].
+    ///
+    /// Keys and buttons held at that moment are released for everyone else first,
+    /// the desktop would keep them pressed (and repeating) while the devices are
+    /// grabbed. The kernel holds them again afterwards, so their physical release
+    /// still reaches the stream.
+    pub fn grab_with(&mut self, grab: impl FnOnce() -> Result<()>) -> Result<()> {
+        let held = if self.grab.is_grabbed() {
+            Vec::new()
+        } else {
+            self.release_held()
+        };
+        let grabbed = grab();
+        // the held inputs have to be restored whether the grab worked or not
+        for (index, keys) in held {
+            self.inject_keys(index, &keys, 1);
+        }
+        grabbed
+    }
+
+    /// Writes a release of every held key and button into its device,
+    /// returns the released keys per source.
+    fn release_held(&mut self) -> Vec<(usize, Vec<u16>)> {
+        let mut held = Vec::new();
+        for index in 0..self.sources.len() {
+            let keys = self.sources[index]
+                .held_keys()
+                .into_iter()
+                .filter(|key| is_replayed(KeyCode(*key)))
+                .collect::<Vec<_>>();
+            if !keys.is_empty() && self.inject_keys(index, &keys, 0) {
+                held.push((index, keys));
+            }
+        }
+        held
+    }
+
+    /// Writes `value` for `keys` into a device, their echo is dropped when read back.
+    fn inject_keys(&mut self, index: usize, keys: &[u16], value: i32) -> bool {
+        let mut events = keys
+            .iter()
+            .map(|key| InputEvent::new(EventType::KEY.0, *key, value))
+            .collect::<Vec<_>>();
+        events.push(InputEvent::new(
+            EventType::SYNCHRONIZATION.0,
+            SynchronizationCode::SYN_REPORT.0,
+            0,
+        ));
+        match self.sources[index].inject(&events) {
+            Ok(()) => {
+                let echoes = &mut self.states[index].echoes;
+                echoes.extend(keys.iter().map(|key| (*key, value)));
+                true
+            }
+            Err(e) => {
+                tracing::warn!(?keys, "Failed to write held keys into a device: {}", e);
+                false
+            }
+        }
+    }
+
+    /// Gives up the stream's grab, devices stay grabbed while anyone else holds them.
+    pub fn ungrab_inputs(&mut self) -> Result<()> {
+        if self.grabbed {
+            self.grabbed = false;
+            self.grab.release()?;
+        }
+        Ok(())
+    }
+
+    /// Grab shared by the stream's devices, see [`crate::writer::DriverWriter::attach_inputs`].
+    #[must_use]
+    pub fn input_grab(&self) -> InputGrab {
+        self.grab.clone()
+    }
+
+    /// Removes the device opened from `path`, returns `false` if there is none.
+    pub fn remove_device(&mut self, path: &Path) -> bool {
+        let Some(index) = self
+            .states
+            .iter()
+            .position(|s| s.path.as_deref() == Some(path))
+        else {
+            return false;
+        };
+        let state = self.states.remove(index);
+        self.sources.remove(index);
+        if let Some(id) = state.grab_id {
+            self.grab.detach(id);
+        }
+        true
+    }
+
+    /// Paths of the devices currently read from, devices added without one are skipped.
+    pub fn device_paths(&self) -> impl Iterator<Item = &Path> {
+        self.states.iter().filter_map(|s| s.path.as_deref())
+    }
+
+    /// Returns the paths of devices dropped since the last call because they were unplugged.
+    pub fn take_disconnected(&mut self) -> Vec<PathBuf> {
+        std::mem::take(&mut self.disconnected)
+    }
+
+    /// Lock state shown by the LEDs of the first keyboard, `None` without keyboards.
+    pub fn lock_state(&self) -> Option<LockState> {
+        self.states.iter().find_map(|s| s.leds)
+    }
+
+    /// Lights the lock LEDs of all keyboards.
+    pub fn set_lock_leds(&mut self, locks: LockState) -> Result<()> {
+        for (source, state) in self.sources.iter_mut().zip(&mut self.states) {
+            if let Some(leds) = &mut state.leds {
+                source.set_leds(locks)?;
+                *leds = locks;
+            }
+        }
+        Ok(())
+    }
+
+    /// Polls all devices for the next complete frame.
+    pub fn poll_next_frame(&mut self, cx: &mut Context<'_>) -> Poll<EventFrame> {
+        if let Some(frame) = self.ready.pop_front() {
+            return Poll::Ready(frame);
+        }
+
+        let mut index = 0;
+        'sources: while index < self.sources.len() {
+            let mut frames = Vec::new();
+            // one frame per device and poll, a busy mouse must not starve the keyboard
+            while frames.is_empty() {
+                match self.sources[index].poll_next_event(cx) {
+                    Poll::Ready(Ok(event)) => self.states[index].decode(event, &mut frames),
+                    Poll::Ready(Err(e)) if is_unplugged(&e) => {
+                        self.sources.remove(index);
+                        let state = self.states.remove(index);
+                        if let Some(id) = state.grab_id {
+                            self.grab.detach(id);
+                        }
+                        self.disconnected.extend(state.path);
+                        continue 'sources;
+                    }
+                    // the device is still there, it is read again on the next poll
+                    Poll::Ready(Err(e)) => {
+                        tracing::warn!("Failed to read an input device: {}", e);
+                        break;
+                    }
+                    Poll::Pending => break,
+                }
+            }
+            self.ready.extend(frames);
+            index += 1;
+        }
+
+        match self.ready.pop_front() {
+            Some(frame) => Poll::Ready(frame),
+            None => Poll::Pending,
+        }
+    }
+
+    /// Waits for the next complete frame of any device. Cancel safe.
+    pub async fn next_frame(&mut self) -> EventFrame {
+        poll_fn(|cx| self.poll_next_frame(cx)).await
+    }
+}
+
+impl DeviceStream {
+    /// Adds a (re)opened device, grabbing it if the other inputs are grabbed.
+    pub fn add_device(&mut self, device: Device, path: PathBuf) -> Result<()> {
+        let mut state = DeviceState::new(&device, self.touchpad_config, Some(path));
+        let id = self.grab.attach(device.as_fd())?;
+        state.grab_id = Some(id);
+        match device.to_stream() {
+            Ok(stream) => {
+                self.push_source(stream, state);
+                Ok(())
+            }
+            Err(e) => {
+                self.grab.detach(id);
+                Err(e)
+            }
+        }
+    }
+}
+
+impl<T> Stream for DriverStream<T>
+where
+    T: InputStream + Unpin,
+{
+    type Item = DriverEvent;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<DriverEvent>> {
+        let this = self.get_mut();
+        loop {
+            if let Some(event) = this.current.pop_front() {
+                return Poll::Ready(Some(event));
+            }
+            match this.poll_next_frame(cx) {
+                Poll::Ready(frame) => this.current.extend(frame),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+impl<T> Drop for DriverStream<T>
+where
+    T: InputStream + Unpin,
+{
+    fn drop(&mut self) {
+        if self.grabbed {
+            let _ = self.grab.release();
+        }
+    }
+}
@@ -0,0 +1,75 @@
+use serde::{Deserialize, Serialize};
+
+/// Key or button code, the value of the kernel's `KEY_*`/`BTN_*` constants.
+///
+/// Mirrors `evdev::KeyCode` without depending on it, so code naming keys builds on
+/// every platform. Only the codes the crate itself needs have constants.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct KeyCode(pub u16);
+
+impl KeyCode {
+    pub const KEY_A: Self = Self(30);
+    pub const BTN_LEFT: Self = Self(0x110);
+    pub const BTN_RIGHT: Self = Self(0x111);
+    pub const BTN_MIDDLE: Self = Self(0x112);
+
+    #[must_use]
+    pub const fn new(code: u16) -> Self {
+        Self(code)
+    }
+
+    #[must_use]
+    pub const fn code(self) -> u16 {
+        self.0
+    }
+}
+
+/// Relative axis code, the value of the kernel's `REL_*` constants
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct RelativeAxisCode(pub u16);
+
+impl RelativeAxisCode {
+    pub const REL_X: Self = Self(0x00);
+    pub const REL_Y: Self = Self(0x01);
+    pub const REL_HWHEEL: Self = Self(0x06);
+    pub const REL_WHEEL: Self = Self(0x08);
+
+    #[must_use]
+    pub const fn new(code: u16) -> Self {
+        Self(code)
+    }
+
+    #[must_use]
+    pub const fn code(self) -> u16 {
+        self.0
+    }
+}
+
+#[cfg(feature = "linux")]
+mod evdev_conversions {
+    use super::{KeyCode, RelativeAxisCode};
+
+    impl From<evdev::KeyCode> for KeyCode {
+        fn from(code: evdev::KeyCode) -> Self {
+            Self(code.code())
+        }
+    }
+
+    impl From<KeyCode> for evdev::KeyCode {
+        fn from(code: KeyCode) -> Self {
+            Self::new(code.0)
+        }
+    }
+
+    impl From<evdev::RelativeAxisCode> for RelativeAxisCode {
+        fn from(code: evdev::RelativeAxisCode) -> Self {
+            Self(code.0)
+        }
+    }
+
+    impl From<RelativeAxisCode> for evdev::RelativeAxisCode {
+        fn from(code: RelativeAxisCode) -> Self {
+            Self(code.0)
+        }
+    }
+}
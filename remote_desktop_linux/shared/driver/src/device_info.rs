@@ -0,0 +1,125 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use evdev::Device;
+use serde::Serialize;
+
+use crate::device_type::DeviceType;
+
+pub(crate) const BY_ID_DIR: &str = "/dev/input/by-id";
+
+/// Raw event codes a device declares it can report
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct DeviceCapabilities {
+    pub keys: Vec<u16>,
+    pub relative_axes: Vec<u16>,
+    pub absolute_axes: Vec<u16>,
+    pub leds: Vec<u16>,
+}
+
+impl DeviceCapabilities {
+    #[must_use]
+    pub fn from_device(device: &Device) -> Self {
+        Self {
+            keys: device
+                .supported_keys()
+                .map(|keys| keys.iter().map(|k| k.code()).collect())
+                .unwrap_or_default(),
+            relative_axes: device
+                .supported_relative_axes()
+                .map(|axes| axes.iter().map(|a| a.0).collect())
+                .unwrap_or_default(),
+            absolute_axes: device
+                .supported_absolute_axes()
+                .map(|axes| axes.iter().map(|a| a.0).collect())
+                .unwrap_or_default(),
+            leds: device
+                .supported_leds()
+                .map(|leds| leds.iter().map(|l| l.0).collect())
+                .unwrap_or_default(),
+        }
+    }
+
+    #[must_use]
+    pub fn has_key(&self, code: u16) -> bool {
+        self.keys.contains(&code)
+    }
+
+    #[must_use]
+    pub fn has_relative_axis(&self, code: u16) -> bool {
+        self.relative_axes.contains(&code)
+    }
+
+    #[must_use]
+    pub fn has_absolute_axis(&self, code: u16) -> bool {
+        self.absolute_axes.contains(&code)
+    }
+}
+
+/// Description of an input device node, used to pick master devices
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DeviceInfo {
+    /// Kernel node, e.g. `/dev/input/event4`
+    pub path: PathBuf,
+    /// Stable `/dev/input/by-id` link to the node, if udev created one
+    pub by_id: Option<PathBuf>,
+    pub name: String,
+    /// Physical topology reported by the driver, e.g. `usb-0000:00:14.0-2/input0`
+    pub phys: Option<String>,
+    pub bus_type: u16,
+    pub vendor: u16,
+    pub product: u16,
+    pub version: u16,
+    pub capabilities: DeviceCapabilities,
+    pub device_type: DeviceType,
+}
+
+impl DeviceInfo {
+    #[must_use]
+    pub fn from_device(path: PathBuf, device: &Device) -> Self {
+        let id = device.input_id();
+        let capabilities = DeviceCapabilities::from_device(device);
+
+        Self {
+            path,
+            by_id: None,
+            name: device.name().unwrap_or_default().to_string(),
+            phys: device.physical_path().map(str::to_string),
+            bus_type: id.bus_type().0,
+            vendor: id.vendor(),
+            product: id.product(),
+            version: id.version(),
+            device_type: DeviceType::classify(&capabilities),
+            capabilities,
+        }
+    }
+
+    /// Path to show and store in configuration, preferring the stable by-id link.
+    #[must_use]
+    pub fn stable_path(&self) -> &Path {
+        self.by_id.as_deref().unwrap_or(&self.path)
+    }
+}
+
+/// Maps event nodes to their `/dev/input/by-id` links.
+///
+/// A node can have several links (one per USB interface), the `-event-` ones are preferred.
+pub(crate) fn by_id_links() -> HashMap<PathBuf, PathBuf> {
+    let mut links = HashMap::new();
+    let Ok(entries) = fs::read_dir(BY_ID_DIR) else {
+        return links;
+    };
+
+    let mut entries = entries.flatten().map(|e| e.path()).collect::<Vec<_>>();
+    // `-event-` links sort after the legacy `-mouse` ones, keep them by inserting last
+    entries.sort_by_key(|p| p.to_string_lossy().contains("-event-"));
+
+    for link in entries {
+        if let Ok(target) = fs::canonicalize(&link) {
+            links.insert(target, link);
+        }
+    }
+
+    links
+}
@@ -1,8 +1,55 @@
+use evdev::{AbsoluteAxisCode, KeyCode, RelativeAxisCode};
 use serde::Serialize;
 
+use crate::device_info::DeviceCapabilities;
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
 pub enum DeviceType {
     Mouse,
     Keyboard,
+    Touchpad,
+    Tablet,
+    Gamepad,
     Other,
 }
+
+/// Keys every real keyboard has, power buttons and media remotes lack them
+const TYPING_KEYS: [KeyCode; 4] = [
+    KeyCode::KEY_A,
+    KeyCode::KEY_Z,
+    KeyCode::KEY_SPACE,
+    KeyCode::KEY_ENTER,
+];
+
+impl DeviceType {
+    /// Classifies a device by what it can report, the most specific kind wins.
+    #[must_use]
+    pub fn classify(caps: &DeviceCapabilities) -> Self {
+        let has_position = caps.has_absolute_axis(AbsoluteAxisCode::ABS_X.0)
+            && caps.has_absolute_axis(AbsoluteAxisCode::ABS_Y.0);
+
+        if caps.has_key(KeyCode::BTN_SOUTH.code()) || caps.has_key(KeyCode::BTN_TRIGGER.code()) {
+            return Self::Gamepad;
+        }
+        if has_position
+            && (caps.has_key(KeyCode::BTN_TOOL_PEN.code())
+                || caps.has_key(KeyCode::BTN_STYLUS.code()))
+        {
+            return Self::Tablet;
+        }
+        if has_position && caps.has_key(KeyCode::BTN_TOOL_FINGER.code()) {
+            return Self::Touchpad;
+        }
+        if caps.has_relative_axis(RelativeAxisCode::REL_X.0)
+            && caps.has_relative_axis(RelativeAxisCode::REL_Y.0)
+            && caps.has_key(KeyCode::BTN_LEFT.code())
+        {
+            return Self::Mouse;
+        }
+        if TYPING_KEYS.iter().all(|k| caps.has_key(k.code())) {
+            return Self::Keyboard;
+        }
+
+        Self::Other
+    }
+}
@@ -1,3 +1,10 @@
-pub use crate::event::{DriverEvent, KeyboardPress, MouseClick, MouseMove};
+#[cfg(feature = "linux")]
+pub use crate::async_reader::{DeviceStream, DriverStream};
+pub use crate::codes::{KeyCode, RelativeAxisCode};
+pub use crate::event::{DriverEvent, EventFrame, KeyboardPress, MouseClick, MouseMove};
+#[cfg(feature = "linux")]
 pub use crate::reader::{DeviceReader, DriverReader, VirtualDevicerReader};
+pub use crate::sink::{InputSink, RecordingSink};
+pub use crate::source::{InputSource, ScriptedSource};
+#[cfg(feature = "linux")]
 pub use crate::writer::DriverWriter;
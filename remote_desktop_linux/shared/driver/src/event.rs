@@ -38,7 +38,7 @@ impl std::ops::AddAssign for MouseMove {
     }
 }
 
-#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, Serialize, Deserialize)]
 pub enum MouseButton {
     Left,
     Right,
@@ -55,6 +55,78 @@ pub struct MouseClick {
 pub struct KeyboardPress {
     pub key: u16,
     pub pressed: bool,
+    /// Autorepeat of an already held key (evdev value 2), `pressed` stays `true`
+    #[serde(default)]
+    pub repeat: bool,
+    /// Character the master's layout typed, lets a slave with another layout
+    /// press the key typing the same, see [`crate::layout`]
+    #[serde(default)]
+    pub keysym: Option<u32>,
+}
+
+/// Caps/Num/Scroll Lock state of one machine, shown by its keyboard LEDs
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+pub struct LockState {
+    pub caps_lock: bool,
+    pub num_lock: bool,
+    pub scroll_lock: bool,
+}
+
+impl LockState {
+    pub const CAPS_LOCK_KEY: u16 = 58; // KEY_CAPSLOCK
+    pub const NUM_LOCK_KEY: u16 = 69; // KEY_NUMLOCK
+    pub const SCROLL_LOCK_KEY: u16 = 70; // KEY_SCROLLLOCK
+
+    pub const NUM_LOCK_LED: u16 = 0; // LED_NUML
+    pub const CAPS_LOCK_LED: u16 = 1; // LED_CAPSL
+    pub const SCROLL_LOCK_LED: u16 = 2; // LED_SCROLLL
+
+    /// Flips the lock toggled by `key`. Returns `false` if `key` is no lock key.
+    pub fn toggle(&mut self, key: u16) -> bool {
+        match key {
+            Self::CAPS_LOCK_KEY => self.caps_lock = !self.caps_lock,
+            Self::NUM_LOCK_KEY => self.num_lock = !self.num_lock,
+            Self::SCROLL_LOCK_KEY => self.scroll_lock = !self.scroll_lock,
+            _ => return false,
+        }
+        true
+    }
+
+    /// Applies an `EV_LED` event, other LEDs are ignored.
+    pub fn set_led(&mut self, led: u16, on: bool) {
+        match led {
+            Self::CAPS_LOCK_LED => self.caps_lock = on,
+            Self::NUM_LOCK_LED => self.num_lock = on,
+            Self::SCROLL_LOCK_LED => self.scroll_lock = on,
+            _ => {}
+        }
+    }
+
+    /// LED codes with their wanted state.
+    #[must_use]
+    pub const fn leds(&self) -> [(u16, bool); 3] {
+        [
+            (Self::NUM_LOCK_LED, self.num_lock),
+            (Self::CAPS_LOCK_LED, self.caps_lock),
+            (Self::SCROLL_LOCK_LED, self.scroll_lock),
+        ]
+    }
+
+    /// Lock keys to tap to get from this state to `target`.
+    #[must_use]
+    pub fn keys_to_reach(&self, target: Self) -> Vec<u16> {
+        [
+            (Self::CAPS_LOCK_KEY, self.caps_lock != target.caps_lock),
+            (Self::NUM_LOCK_KEY, self.num_lock != target.num_lock),
+            (
+                Self::SCROLL_LOCK_KEY,
+                self.scroll_lock != target.scroll_lock,
+            ),
+        ]
+        .into_iter()
+        .filter_map(|(key, differs)| differs.then_some(key))
+        .collect()
+    }
 }
 
 #[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
@@ -62,6 +134,8 @@ pub enum DriverEvent {
     MouseMove(MouseMove),
     MouseClick(MouseClick),
     KeyboardPress(KeyboardPress),
+    /// Lock state the receiving machine should switch to, sent when it gets focus
+    LockState(LockState),
 }
 
 impl DriverEvent {
@@ -74,6 +148,23 @@ impl DriverEvent {
     }
 
     pub const fn keyboard_press(key: u16, pressed: bool) -> Self {
-        Self::KeyboardPress(KeyboardPress { key, pressed })
+        Self::KeyboardPress(KeyboardPress {
+            key,
+            pressed,
+            repeat: false,
+            keysym: None,
+        })
+    }
+
+    pub const fn keyboard_repeat(key: u16) -> Self {
+        Self::KeyboardPress(KeyboardPress {
+            key,
+            pressed: true,
+            repeat: true,
+            keysym: None,
+        })
     }
 }
+
+/// Events the hardware reported between two `SYN_REPORT`s, replayed as one batch
+pub type EventFrame = Vec<DriverEvent>;
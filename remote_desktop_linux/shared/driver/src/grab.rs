@@ -0,0 +1,178 @@
+use std::io::{Error, ErrorKind, Result};
+use std::os::fd::{AsRawFd, BorrowedFd, OwnedFd};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+use crate::reader::is_unplugged;
+
+mod sys {
+    nix::ioctl_write_int!(eviocgrab, b'E', 0x90);
+}
+
+/// Identifies a device attached to an [`InputGrab`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GrabId(u64);
+
+/// Reference counted grab over a set of physical input devices, clones share it.
+///
+/// The devices are grabbed while at least one hold is taken and released with
+/// the last one. Each device is kept as a duplicate of its file descriptor, so
+/// readers, streams and writers can all suppress the same inputs.
+#[derive(Debug, Clone, Default)]
+pub struct InputGrab {
+    inner: Arc<Mutex<GrabState>>,
+}
+
+#[derive(Debug, Default)]
+struct GrabState {
+    devices: Vec<(GrabId, OwnedFd)>,
+    holds: usize,
+    next_id: u64,
+}
+
+/// Hold on an [`InputGrab`], released on drop
+#[must_use = "the inputs are released as soon as the guard is dropped"]
+#[derive(Debug)]
+pub struct GrabGuard {
+    grab: InputGrab,
+}
+
+fn set_grab(fd: &OwnedFd, grab: bool) -> Result<()> {
+    // SAFETY: EVIOCGRAB only reads its integer argument, `fd` is open for the call.
+    unsafe { sys::eviocgrab(fd.as_raw_fd(), u64::from(grab) as _) }
+        .map(drop)
+        .map_err(Error::from)
+}
+
+impl GrabState {
+    /// Grabs every device, unplugged ones are dropped. On failure the devices
+    /// grabbed so far are released again.
+    fn grab_all(&mut self) -> Result<()> {
+        let mut index = 0;
+        while index < self.devices.len() {
+            match set_grab(&self.devices[index].1, true) {
+                Ok(()) => index += 1,
+                Err(e) if is_unplugged(&e) => {
+                    tracing::debug!("Dropping an unplugged device from the grab");
+                    self.devices.remove(index);
+                }
+                Err(e) => {
+                    for (_, fd) in &self.devices[..index] {
+                        let _ = set_grab(fd, false);
+                    }
+                    return Err(e);
+                }
+            }
+        }
+        tracing::debug!(devices = self.devices.len(), "Inputs grabbed");
+        Ok(())
+    }
+
+    fn ungrab_all(&mut self) {
+        self.devices.retain(|(_, fd)| match set_grab(fd, false) {
+            Err(e) => !is_unplugged(&e),
+            Ok(()) => true,
+        });
+        tracing::debug!(devices = self.devices.len(), "Inputs released");
+    }
+}
+
+impl Drop for GrabState {
+    fn drop(&mut self) {
+        if self.holds > 0 {
+            self.ungrab_all();
+        }
+    }
+}
+
+impl InputGrab {
+    /// Poisoning is ignored, the inputs must stay releasable after a panic.
+    fn state(&self) -> MutexGuard<'_, GrabState> {
+        self.inner.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Adds a device, grabbing it right away if the inputs are held.
+    pub fn attach(&self, fd: BorrowedFd<'_>) -> Result<GrabId> {
+        let fd = fd.try_clone_to_owned()?;
+        let mut state = self.state();
+        if state.holds > 0 {
+            set_grab(&fd, true)?;
+        }
+        let id = GrabId(state.next_id);
+        state.next_id += 1;
+        state.devices.push((id, fd));
+        Ok(id)
+    }
+
+    /// Forgets a device, releasing it if it is still grabbed.
+    pub fn detach(&self, id: GrabId) {
+        let mut state = self.state();
+        let Some(index) = state.devices.iter().position(|(i, _)| *i == id) else {
+            return;
+        };
+        let (_, fd) = state.devices.remove(index);
+        if state.holds > 0 {
+            let _ = set_grab(&fd, false);
+        }
+    }
+
+    /// Takes a hold, grabbing all devices if it is the first one.
+    pub fn acquire(&self) -> Result<()> {
+        let mut state = self.state();
+        if state.holds == 0 {
+            state.grab_all()?;
+        }
+        state.holds += 1;
+        Ok(())
+    }
+
+    /// Gives a hold back, releasing all devices with the last one.
+    pub fn release(&self) -> Result<()> {
+        let mut state = self.state();
+        match state.holds {
+            0 => Err(Error::new(ErrorKind::InvalidInput, "inputs are not held")),
+            1 => {
+                state.holds = 0;
+                state.ungrab_all();
+                Ok(())
+            }
+            _ => {
+                state.holds -= 1;
+                Ok(())
+            }
+        }
+    }
+
+    /// Takes a hold given back when the guard is dropped, also while unwinding.
+    pub fn hold(&self) -> Result<GrabGuard> {
+        self.acquire()?;
+        Ok(GrabGuard { grab: self.clone() })
+    }
+
+    /// Number of holds currently taken.
+    #[must_use]
+    pub fn holds(&self) -> usize {
+        self.state().holds
+    }
+
+    #[must_use]
+    pub fn is_grabbed(&self) -> bool {
+        self.holds() > 0
+    }
+
+    /// Number of attached devices.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.state().devices.len()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl Drop for GrabGuard {
+    fn drop(&mut self) {
+        let _ = self.grab.release();
+    }
+}
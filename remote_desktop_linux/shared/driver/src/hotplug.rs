@@ -0,0 +1,314 @@
+use std::collections::HashSet;
+use std::fs;
+use std::io::Result;
+use std::path::{Path, PathBuf};
+
+use evdev::Device;
+use nix::sys::inotify::{AddWatchFlags, InitFlags, Inotify, WatchDescriptor};
+
+use crate::async_reader::DeviceStream;
+use crate::device_info::{by_id_links, DeviceInfo, BY_ID_DIR};
+use crate::device_type::DeviceType;
+use crate::reader::DeviceReader;
+use crate::writer::VIRTUAL_DEVICE_NAME;
+
+const INPUT_DIR: &str = "/dev/input";
+
+/// Change of the devices a [`DeviceSet`] reads from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HotplugEvent {
+    /// A watched device was unplugged and is no longer read
+    Disconnected { label: String, path: PathBuf },
+    /// A watched device was plugged back in and is read again
+    Reconnected { label: String, path: PathBuf },
+    /// A new keyboard or pointer was plugged in and added automatically
+    Attached {
+        path: PathBuf,
+        name: String,
+        device_type: DeviceType,
+    },
+}
+
+/// Devices read together, kept in sync by [`HotplugWatcher`]
+pub trait DeviceSet {
+    /// Adds a (re)opened device, grabbing it if the other inputs are grabbed.
+    fn add_device(&mut self, device: Device, path: PathBuf) -> Result<()>;
+    /// Removes the device opened from `path`, returns `false` if there is none.
+    fn remove_device(&mut self, path: &Path) -> bool;
+    fn device_paths(&self) -> Vec<PathBuf>;
+    /// Paths of devices dropped since the last call because they were unplugged.
+    fn take_disconnected(&mut self) -> Vec<PathBuf>;
+}
+
+impl DeviceSet for DeviceReader {
+    fn add_device(&mut self, device: Device, path: PathBuf) -> Result<()> {
+        DeviceReader::add_device(self, device, path)
+    }
+
+    fn remove_device(&mut self, path: &Path) -> bool {
+        DeviceReader::remove_device(self, path)
+    }
+
+    fn device_paths(&self) -> Vec<PathBuf> {
+        DeviceReader::device_paths(self)
+            .map(Path::to_path_buf)
+            .collect()
+    }
+
+    fn take_disconnected(&mut self) -> Vec<PathBuf> {
+        DeviceReader::take_disconnected(self)
+    }
+}
+
+impl DeviceSet for DeviceStream {
+    fn add_device(&mut self, device: Device, path: PathBuf) -> Result<()> {
+        DeviceStream::add_device(self, device, path)
+    }
+
+    fn remove_device(&mut self, path: &Path) -> bool {
+        DeviceStream::remove_device(self, path)
+    }
+
+    fn device_paths(&self) -> Vec<PathBuf> {
+        DeviceStream::device_paths(self)
+            .map(Path::to_path_buf)
+            .collect()
+    }
+
+    fn take_disconnected(&mut self) -> Vec<PathBuf> {
+        DeviceStream::take_disconnected(self)
+    }
+}
+
+struct WatchedDevice {
+    label: String,
+    path: PathBuf,
+    connected: bool,
+}
+
+/// Watches `/dev/input` and keeps a [`DeviceSet`] in sync with plugged devices.
+///
+/// Watched devices are reopened by their stable path once they reappear, with
+/// `auto_attach` new keyboards, mice and touchpads are added as well.
+pub struct HotplugWatcher {
+    inotify: Inotify,
+    dirs: Vec<(WatchDescriptor, PathBuf)>,
+    by_id_dir: PathBuf,
+    devices: Vec<WatchedDevice>,
+    auto_attach: bool,
+    /// New event nodes which could not be opened yet, udev sets their permissions
+    /// only after creating them
+    pending: HashSet<PathBuf>,
+}
+
+/// Resolves a device path to its `/dev/input/by-id` link, which survives replugging
+/// unlike the `eventN` node. Paths without a link are returned unchanged.
+#[must_use]
+pub fn stable_path(path: &Path) -> PathBuf {
+    fs::canonicalize(path)
+        .ok()
+        .and_then(|node| by_id_links().remove(&node))
+        .unwrap_or_else(|| path.to_path_buf())
+}
+
+impl HotplugWatcher {
+    pub fn new(auto_attach: bool) -> Result<Self> {
+        Self::with_dirs(INPUT_DIR, BY_ID_DIR, auto_attach)
+    }
+
+    /// Watches other directories than `/dev/input` and `/dev/input/by-id`.
+    pub fn with_dirs(
+        input_dir: impl Into<PathBuf>,
+        by_id_dir: impl Into<PathBuf>,
+        auto_attach: bool,
+    ) -> Result<Self> {
+        let mut watcher = Self {
+            inotify: Inotify::init(InitFlags::IN_NONBLOCK | InitFlags::IN_CLOEXEC)?,
+            dirs: Vec::new(),
+            by_id_dir: by_id_dir.into(),
+            devices: Vec::new(),
+            auto_attach,
+            pending: HashSet::new(),
+        };
+        watcher.add_dir(input_dir.into())?;
+        // the by-id directory only exists while some device has an id
+        let _ = watcher.add_dir(watcher.by_id_dir.clone());
+
+        Ok(watcher)
+    }
+
+    /// Follows a device already added to the reader under `path`,
+    /// which should come from [`stable_path`].
+    pub fn watch(&mut self, label: impl Into<String>, path: PathBuf) {
+        self.devices.push(WatchedDevice {
+            label: label.into(),
+            path,
+            connected: true,
+        });
+    }
+
+    /// Labels of the watched devices which are currently unplugged.
+    pub fn disconnected(&self) -> impl Iterator<Item = &str> {
+        self.devices
+            .iter()
+            .filter(|d| !d.connected)
+            .map(|d| d.label.as_str())
+    }
+
+    /// Handles devices the reader lost and the changes in `/dev/input` since the last call.
+    ///
+    /// Never blocks, meant to be called between reads.
+    pub fn poll(&mut self, reader: &mut impl DeviceSet) -> Vec<HotplugEvent> {
+        let mut events = Vec::new();
+        for path in reader.take_disconnected() {
+            self.mark_disconnected(&path, &mut events);
+        }
+
+        let changes = self.read_changes();
+        if changes.is_empty() {
+            return events;
+        }
+
+        for (path, removed) in changes {
+            if removed {
+                self.pending.remove(&path);
+                reader.remove_device(&path);
+                self.mark_disconnected(&path, &mut events);
+            } else if self.auto_attach && is_event_node(&path) {
+                self.pending.insert(path);
+            }
+        }
+
+        self.reopen(reader, &mut events);
+        self.attach_pending(reader, &mut events);
+        events
+    }
+
+    fn add_dir(&mut self, dir: PathBuf) -> Result<()> {
+        let flags = AddWatchFlags::IN_CREATE
+            | AddWatchFlags::IN_DELETE
+            | AddWatchFlags::IN_ATTRIB
+            | AddWatchFlags::IN_MOVED_TO
+            | AddWatchFlags::IN_MOVED_FROM;
+        let wd = self.inotify.add_watch(&dir, flags)?;
+        self.dirs.push((wd, dir));
+        Ok(())
+    }
+
+    /// Drains the inotify queue into `(path, removed)` pairs.
+    fn read_changes(&mut self) -> Vec<(PathBuf, bool)> {
+        // EAGAIN, nothing happened since the last call
+        let Ok(notifications) = self.inotify.read_events() else {
+            return Vec::new();
+        };
+
+        let mut changes = Vec::new();
+        for notification in notifications {
+            if notification.mask.contains(AddWatchFlags::IN_IGNORED) {
+                self.dirs.retain(|(wd, _)| *wd != notification.wd);
+                continue;
+            }
+            let Some(dir) = self
+                .dirs
+                .iter()
+                .find(|(wd, _)| *wd == notification.wd)
+                .map(|(_, dir)| dir.clone())
+            else {
+                continue;
+            };
+            let Some(name) = notification.name else {
+                continue;
+            };
+
+            let path = dir.join(name);
+            let removed = notification
+                .mask
+                .intersects(AddWatchFlags::IN_DELETE | AddWatchFlags::IN_MOVED_FROM);
+            if !removed && path == self.by_id_dir {
+                let _ = self.add_dir(path.clone());
+            }
+            changes.push((path, removed));
+        }
+
+        changes
+    }
+
+    fn mark_disconnected(&mut self, path: &Path, events: &mut Vec<HotplugEvent>) {
+        for device in &mut self.devices {
+            if device.connected && device.path == path {
+                device.connected = false;
+                events.push(HotplugEvent::Disconnected {
+                    label: device.label.clone(),
+                    path: device.path.clone(),
+                });
+            }
+        }
+    }
+
+    fn reopen(&mut self, reader: &mut impl DeviceSet, events: &mut Vec<HotplugEvent>) {
+        for device in self.devices.iter_mut().filter(|d| !d.connected) {
+            // permissions may not be set yet, the following IN_ATTRIB retries
+            let opened = match DeviceReader::open_path(device.path.clone(), false, true) {
+                Ok(opened) => opened,
+                Err(e) => {
+                    tracing::debug!(path = %device.path.display(), "Reopening failed: {}", e);
+                    continue;
+                }
+            };
+            if reader.add_device(opened, device.path.clone()).is_ok() {
+                device.connected = true;
+                events.push(HotplugEvent::Reconnected {
+                    label: device.label.clone(),
+                    path: device.path.clone(),
+                });
+            }
+        }
+    }
+
+    fn attach_pending(&mut self, reader: &mut impl DeviceSet, events: &mut Vec<HotplugEvent>) {
+        let read_nodes = reader
+            .device_paths()
+            .into_iter()
+            .filter_map(|p| fs::canonicalize(p).ok())
+            .collect::<HashSet<_>>();
+
+        for node in std::mem::take(&mut self.pending) {
+            if read_nodes.contains(&node) {
+                continue;
+            }
+            let Ok(device) = DeviceReader::open_path(node.clone(), false, true) else {
+                self.pending.insert(node);
+                continue;
+            };
+            let info = DeviceInfo::from_device(node.clone(), &device);
+            if !is_attachable(&info) {
+                continue;
+            }
+
+            let path = stable_path(&node);
+            if reader.add_device(device, path.clone()).is_ok() {
+                self.watch(info.name.clone(), path.clone());
+                events.push(HotplugEvent::Attached {
+                    path,
+                    name: info.name,
+                    device_type: info.device_type,
+                });
+            }
+        }
+    }
+}
+
+fn is_event_node(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .is_some_and(|name| name.starts_with("event"))
+}
+
+/// Only keyboards and pointers are attached, never our own virtual device
+fn is_attachable(info: &DeviceInfo) -> bool {
+    !info.name.starts_with(VIRTUAL_DEVICE_NAME)
+        && matches!(
+            info.device_type,
+            DeviceType::Keyboard | DeviceType::Mouse | DeviceType::Touchpad
+        )
+}
@@ -0,0 +1,455 @@
+use std::collections::{HashMap, HashSet};
+use std::io::Result;
+
+use crate::event::{DriverEvent, EventFrame, KeyboardPress};
+use crate::sink::InputSink;
+
+/// KEY_LEFTSHIFT, KEY_RIGHTSHIFT
+const SHIFT_KEYS: [u16; 2] = [42, 54];
+/// KEY_RIGHTALT, which is AltGr on layouts that have a third level
+pub const ALTGR_KEY: u16 = 100;
+
+/// File Debian-style systems keep the console and X keyboard layout in
+const KEYBOARD_CONFIG: &str = "/etc/default/keyboard";
+/// xkb rules listing the layouts the system's xkb data has
+const XKB_RULES_LIST: &str = "/usr/share/X11/xkb/rules/evdev.lst";
+
+/// X11 keysym, the character a key produces independent of where it sits.
+///
+/// Follows X11's Unicode mapping: Latin-1 characters are their own code point,
+/// everything else is the code point plus `0x0100_0000`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Keysym(pub u32);
+
+impl Keysym {
+    const UNICODE_OFFSET: u32 = 0x0100_0000;
+
+    #[must_use]
+    pub const fn from_char(c: char) -> Self {
+        match c as u32 {
+            code @ (0x20..=0x7e | 0xa0..=0xff) => Self(code),
+            code => Self(Self::UNICODE_OFFSET + code),
+        }
+    }
+
+    #[must_use]
+    pub fn to_char(self) -> Option<char> {
+        match self.0 {
+            code @ (0x20..=0x7e | 0xa0..=0xff) => char::from_u32(code),
+            code => char::from_u32(code.checked_sub(Self::UNICODE_OFFSET)?),
+        }
+    }
+}
+
+/// Shift level a character sits on
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Level {
+    Base,
+    Shift,
+    AltGr,
+}
+
+/// Characters of one layout: rows of consecutive key codes with their base and
+/// shifted characters, `\0` marking keys without one (dead keys), plus AltGr keys.
+/// Only test fixtures, real layouts come from the xkb keymap.
+struct LayoutTable {
+    name: &'static str,
+    rows: &'static [(u16, &'static str, &'static str)],
+    altgr: &'static [(u16, char)],
+}
+
+const LAYOUTS: &[LayoutTable] = &[
+    LayoutTable {
+        name: "us",
+        rows: &[
+            (41, "`", "~"),
+            (2, "1234567890-=", "!@#$%^&*()_+"),
+            (16, "qwertyuiop[]", "QWERTYUIOP{}"),
+            (30, "asdfghjkl;'", "ASDFGHJKL:\""),
+            (43, "\\", "|"),
+            (44, "zxcvbnm,./", "ZXCVBNM<>?"),
+            (57, " ", " "),
+        ],
+        altgr: &[],
+    },
+    LayoutTable {
+        name: "sk",
+        rows: &[
+            (41, ";", "\0"),
+            (2, "+ľščťžýáíé=", "1234567890%"),
+            (16, "qwertzuiopúä", "QWERTZUIOP/("),
+            (30, "asdfghjklô§", "ASDFGHJKL\"!"),
+            (43, "ň", ")"),
+            (44, "yxcvbnm,.-", "YXCVBNM?:_"),
+            (57, " ", " "),
+            (86, "\\", "|"),
+        ],
+        altgr: &[
+            (16, '\\'),
+            (17, '|'),
+            (18, '€'),
+            (33, '['),
+            (34, ']'),
+            (39, '$'),
+            (45, '#'),
+            (46, '&'),
+            (47, '@'),
+            (48, '{'),
+            (49, '}'),
+            (51, '<'),
+            (52, '>'),
+        ],
+    },
+    LayoutTable {
+        name: "de",
+        rows: &[
+            (41, "\0", "°"),
+            (2, "1234567890ß", "!\"§$%&/()=?"),
+            (16, "qwertzuiopü+", "QWERTZUIOPÜ*"),
+            (30, "asdfghjklöä", "ASDFGHJKLÖÄ"),
+            (43, "#", "'"),
+            (44, "yxcvbnm,.-", "YXCVBNM;:_"),
+            (57, " ", " "),
+            (86, "<", ">"),
+        ],
+        altgr: &[
+            (3, '²'),
+            (4, '³'),
+            (8, '{'),
+            (9, '['),
+            (10, ']'),
+            (11, '}'),
+            (12, '\\'),
+            (16, '@'),
+            (18, '€'),
+            (27, '~'),
+            (50, 'µ'),
+            (86, '|'),
+        ],
+    },
+];
+
+/// Keyboard layout mapping key codes to the characters they type.
+///
+/// Only keys of the main block are covered, everything else (Enter, arrows,
+/// modifiers, ...) is forwarded as a raw key code. Layouts are compiled by
+/// libxkbcommon from the system's xkb data, custom keymaps and layout
+/// switching at runtime are not seen.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Layout {
+    name: String,
+    keys: HashMap<(u16, Level), Keysym>,
+}
+
+impl Layout {
+    /// Looks up a layout by its xkb name with an optional variant, like `sk` or `sk(qwerty)`.
+    /// `None` if libxkbcommon is missing or does not know the layout or variant.
+    #[must_use]
+    pub fn named(name: &str) -> Option<Self> {
+        let (layout, variant) = split_name(name)?;
+        Some(Self {
+            keys: crate::xkb::keymap_keys(&layout, &variant)?,
+            name: join_name(&layout, &variant),
+        })
+    }
+
+    /// Offline table of `us`, `sk` or `de`, for tests that must not depend on the
+    /// system's xkb data. `None` for other layouts and any variant.
+    #[must_use]
+    pub fn fixture(name: &str) -> Option<Self> {
+        let (layout, variant) = split_name(name)?;
+        if !variant.is_empty() {
+            return None;
+        }
+        LAYOUTS
+            .iter()
+            .find(|table| table.name == layout)
+            .map(Self::from_table)
+    }
+
+    /// Layouts listed in the system's xkb rules, empty without xkb data.
+    #[must_use]
+    pub fn names() -> Vec<String> {
+        let Ok(list) = std::fs::read_to_string(XKB_RULES_LIST) else {
+            return Vec::new();
+        };
+        list.lines()
+            .skip_while(|line| line.trim() != "! layout")
+            .skip(1)
+            .take_while(|line| !line.starts_with('!'))
+            .filter_map(|line| line.split_whitespace().next())
+            .map(str::to_string)
+            .collect()
+    }
+
+    /// Layout of this machine, guessed from `XKB_DEFAULT_LAYOUT` and `XKB_DEFAULT_VARIANT`
+    /// or the first layout and variant in `/etc/default/keyboard`. The desktop's active
+    /// layout is not queried, so a layout picked in its settings is missed.
+    /// `None` if it is unknown.
+    #[must_use]
+    pub fn detect() -> Option<Self> {
+        if let Ok(layout) = std::env::var("XKB_DEFAULT_LAYOUT") {
+            let variant = std::env::var("XKB_DEFAULT_VARIANT").unwrap_or_default();
+            return Self::named(&join_name(first(&layout), first(&variant)));
+        }
+        let config = std::fs::read_to_string(KEYBOARD_CONFIG).ok()?;
+        Self::from_keyboard_config(&config)
+    }
+
+    /// First layout and variant of the `XKBLAYOUT` and `XKBVARIANT` lines in an
+    /// `/etc/default/keyboard` file.
+    #[must_use]
+    pub fn from_keyboard_config(config: &str) -> Option<Self> {
+        let value = |key: &str| {
+            config
+                .lines()
+                .find_map(|line| line.trim().strip_prefix(key))
+                .map(|value| first(value.trim().trim_matches('"')))
+        };
+        let layout = value("XKBLAYOUT=")?;
+        Self::named(&join_name(layout, value("XKBVARIANT=").unwrap_or_default()))
+    }
+
+    fn from_table(table: &LayoutTable) -> Self {
+        let mut keys = HashMap::new();
+        for &(start, base, shift) in table.rows {
+            for (level, chars) in [(Level::Base, base), (Level::Shift, shift)] {
+                for (code, c) in (start..).zip(chars.chars()) {
+                    if c != '\0' {
+                        keys.insert((code, level), Keysym::from_char(c));
+                    }
+                }
+            }
+        }
+        for &(code, c) in table.altgr {
+            keys.insert((code, Level::AltGr), Keysym::from_char(c));
+        }
+        Self {
+            name: table.name.to_string(),
+            keys,
+        }
+    }
+
+    /// xkb name, with the variant in parentheses if there is one.
+    #[must_use]
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Keysym `code` types at `level`.
+    #[must_use]
+    pub fn keysym(&self, code: u16, level: Level) -> Option<Keysym> {
+        self.keys.get(&(code, level)).copied()
+    }
+
+    /// Keysym `code` types with the given modifiers held, none for Shift and AltGr
+    /// together as the fourth level is not read.
+    #[must_use]
+    pub fn keysym_held(&self, code: u16, shift: bool, altgr: bool) -> Option<Keysym> {
+        match (shift, altgr) {
+            (false, false) => self.keysym(code, Level::Base),
+            (true, false) => self.keysym(code, Level::Shift),
+            (false, true) => self.keysym(code, Level::AltGr),
+            (true, true) => None,
+        }
+    }
+
+    /// Key and level typing `keysym`, preferring the base level, then Shift, then AltGr.
+    #[must_use]
+    pub fn locate(&self, keysym: Keysym) -> Option<(u16, Level)> {
+        [Level::Base, Level::Shift, Level::AltGr]
+            .into_iter()
+            .find_map(|level| {
+                self.keys
+                    .iter()
+                    .filter(|((_, l), sym)| *l == level && **sym == keysym)
+                    .map(|((code, _), _)| (*code, level))
+                    .min()
+            })
+    }
+}
+
+/// Splits `sk(qwerty)` into its lowercase layout and variant, the variant empty if not given.
+fn split_name(name: &str) -> Option<(String, String)> {
+    let name = name.trim().to_lowercase();
+    let (layout, variant) = match name.split_once('(') {
+        Some((layout, variant)) => (layout, variant.strip_suffix(')')?),
+        None => (name.as_str(), ""),
+    };
+    let layout = layout.trim();
+    (!layout.is_empty()).then(|| (layout.to_string(), variant.trim().to_string()))
+}
+
+fn join_name(layout: &str, variant: &str) -> String {
+    if variant.is_empty() {
+        layout.to_string()
+    } else {
+        format!("{}({})", layout, variant)
+    }
+}
+
+/// First entry of a comma separated xkb list.
+fn first(list: &str) -> &str {
+    list.split(',').next().unwrap_or_default().trim()
+}
+
+/// Turns key presses carrying a keysym back into key codes of the local layout.
+///
+/// Shift and AltGr are pressed or released around a translated key as its level
+/// needs and put back to what the master holds once it is released. Presses
+/// without a keysym, or with one the layout cannot type, stay raw key codes.
+#[derive(Debug, Clone)]
+pub struct KeyTranslator {
+    layout: Option<Layout>,
+    /// Keys the master holds, as forwarded raw
+    held: HashSet<u16>,
+    /// Keys currently down on this machine
+    down: HashSet<u16>,
+    /// Master key code to the local key it was translated to
+    active: HashMap<u16, u16>,
+}
+
+impl KeyTranslator {
+    /// Translator into `layout`, `None` keeps every key raw.
+    #[must_use]
+    pub fn new(layout: Option<Layout>) -> Self {
+        Self {
+            layout,
+            held: HashSet::new(),
+            down: HashSet::new(),
+            active: HashMap::new(),
+        }
+    }
+
+    #[must_use]
+    pub fn layout(&self) -> Option<&Layout> {
+        self.layout.as_ref()
+    }
+
+    pub fn translate(&mut self, frame: &[DriverEvent]) -> EventFrame {
+        let mut out = EventFrame::new();
+        for event in frame {
+            match event {
+                DriverEvent::KeyboardPress(kp) => self.translate_key(*kp, &mut out),
+                event => out.push(*event),
+            }
+        }
+        out
+    }
+
+    fn translate_key(&mut self, kp: KeyboardPress, out: &mut EventFrame) {
+        if kp.repeat {
+            let key = self.active.get(&kp.key).copied().unwrap_or(kp.key);
+            out.push(DriverEvent::keyboard_repeat(key));
+            return;
+        }
+        if !kp.pressed {
+            if let Some(key) = self.active.remove(&kp.key) {
+                self.set(key, false, out);
+                self.restore_modifiers(out);
+                return;
+            }
+        } else if let Some(target) = kp.keysym.and_then(|sym| self.locate(Keysym(sym))) {
+            self.press_translated(kp.key, target, out);
+            return;
+        }
+
+        if kp.pressed {
+            self.held.insert(kp.key);
+            self.down.insert(kp.key);
+        } else {
+            self.held.remove(&kp.key);
+            self.down.remove(&kp.key);
+        }
+        out.push(DriverEvent::keyboard_press(kp.key, kp.pressed));
+    }
+
+    fn locate(&self, keysym: Keysym) -> Option<(u16, Level)> {
+        self.layout.as_ref()?.locate(keysym)
+    }
+
+    fn press_translated(
+        &mut self,
+        master_key: u16,
+        (key, level): (u16, Level),
+        out: &mut EventFrame,
+    ) {
+        self.set(ALTGR_KEY, level == Level::AltGr, out);
+        let shift = level == Level::Shift;
+        if shift {
+            if !SHIFT_KEYS.iter().any(|k| self.down.contains(k)) {
+                self.set(SHIFT_KEYS[0], true, out);
+            }
+        } else {
+            for k in SHIFT_KEYS {
+                self.set(k, false, out);
+            }
+        }
+        self.set(key, true, out);
+        self.active.insert(master_key, key);
+    }
+
+    /// Brings Shift and AltGr back to what the master holds.
+    fn restore_modifiers(&mut self, out: &mut EventFrame) {
+        for k in SHIFT_KEYS.into_iter().chain([ALTGR_KEY]) {
+            self.set(k, self.held.contains(&k), out);
+        }
+    }
+
+    /// Presses or releases `key` unless it already is in that state.
+    fn set(&mut self, key: u16, pressed: bool, out: &mut EventFrame) {
+        let changed = if pressed {
+            self.down.insert(key)
+        } else {
+            self.down.remove(&key)
+        };
+        if changed {
+            out.push(DriverEvent::keyboard_press(key, pressed));
+        }
+    }
+}
+
+/// Sink translating keysyms into the local layout before replaying into `inner`
+#[derive(Debug)]
+pub struct LayoutSink<S> {
+    inner: S,
+    translator: KeyTranslator,
+}
+
+impl<S: InputSink> LayoutSink<S> {
+    #[must_use]
+    pub fn new(inner: S, layout: Option<Layout>) -> Self {
+        Self {
+            inner,
+            translator: KeyTranslator::new(layout),
+        }
+    }
+
+    #[must_use]
+    pub fn layout(&self) -> Option<&Layout> {
+        self.translator.layout()
+    }
+
+    pub fn inner_mut(&mut self) -> &mut S {
+        &mut self.inner
+    }
+
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+impl<S: InputSink> InputSink for LayoutSink<S> {
+    fn simulate_frame(&mut self, frame: &[DriverEvent]) -> Result<()> {
+        let frame = self.translator.translate(frame);
+        self.inner.simulate_frame(&frame)
+    }
+
+    fn block_inputs(&self) -> Result<()> {
+        self.inner.block_inputs()
+    }
+
+    fn unblock_inputs(&self) -> Result<()> {
+        self.inner.unblock_inputs()
+    }
+}
@@ -1,8 +1,33 @@
+#[cfg(all(feature = "linux", not(target_os = "linux")))]
+compile_error!("the `linux` feature needs evdev and uinput, build with `--no-default-features`");
+
+#[cfg(feature = "linux")]
+pub mod async_reader;
+pub mod codes;
+#[cfg(feature = "linux")]
+pub mod device_info;
+#[cfg(feature = "linux")]
 pub mod device_type;
 pub mod event;
+#[cfg(feature = "linux")]
+pub mod grab;
+#[cfg(feature = "linux")]
+pub mod hotplug;
+pub mod keys;
+pub mod layout;
+#[cfg(feature = "linux")]
 pub mod reader;
+pub mod sink;
+pub mod source;
+#[cfg(feature = "linux")]
 pub mod stream;
+#[cfg(feature = "linux")]
+pub mod touchpad;
+#[cfg(feature = "linux")]
+mod uinput;
+#[cfg(feature = "linux")]
 pub mod writer;
+mod xkb;
 
 pub mod driver;
 pub mod file;
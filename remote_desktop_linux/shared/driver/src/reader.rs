@@ -1,81 +1,110 @@
 use evdev::uinput::VirtualDevice;
-use std::io::{ErrorKind, Result};
-use std::path::PathBuf;
+use std::collections::VecDeque;
+use std::io::{Error, ErrorKind, Result};
+use std::path::{Path, PathBuf};
 
-use crate::device_type::DeviceType;
-use crate::event::{DriverEvent, MouseButton, MouseMove};
-use evdev::{Device, EventSummary, KeyCode, RelativeAxisCode};
+use crate::codes;
+use crate::device_info::{by_id_links, DeviceInfo};
+use crate::event::{DriverEvent, EventFrame, LockState, MouseButton, MouseMove};
+use crate::touchpad::{is_touchpad, TouchpadConfig, TouchpadTracker};
+use evdev::{Device, EventSummary, InputEvent, KeyCode, RelativeAxisCode, SynchronizationCode};
 
+use super::async_reader::DriverStream;
+use super::grab::{GrabId, InputGrab};
 use super::stream::ToStream;
 
+/// EV_KEY value the kernel uses for autorepeated keys (0 = release, 1 = press)
+pub const KEY_REPEAT_VALUE: i32 = 2;
+
 pub struct DriverReader<S>
 where
     S: ToStream + std::marker::Send,
 {
     devices: Vec<S>,
+    states: Vec<DeviceState>,
+    touchpad_config: TouchpadConfig,
+    /// Grab over the devices, shared with whoever else suppresses them
+    grab: InputGrab,
+    /// Whether the reader holds `grab`
+    grabbed: bool,
+    /// Paths of devices removed from the reader because they were unplugged
+    disconnected: Vec<PathBuf>,
+}
+
+/// Per-device reading state, kept across reads
+pub(crate) struct DeviceState {
+    /// Events read after the last `SYN_REPORT`, completed on the next read
+    pending: EventFrame,
+    /// Gesture conversion for touchpads, `None` for relative devices
+    touchpad: Option<TouchpadTracker>,
+    /// Path the device was opened from, reported back when it disappears
+    pub(crate) path: Option<PathBuf>,
+    /// Lock LEDs of keyboards, kept up to date from `EV_LED` events
+    pub(crate) leds: Option<LockState>,
+    /// Device in the owner's [`InputGrab`], `None` for devices that cannot be grabbed
+    pub(crate) grab_id: Option<GrabId>,
+    /// Key events written into the device that come back to be read, dropped on arrival
+    pub(crate) echoes: VecDeque<(u16, i32)>,
 }
 
 pub type DeviceReader = DriverReader<Device>;
 pub type VirtualDevicerReader = DriverReader<VirtualDevice>;
 
 impl DriverReader<Device> {
-    pub fn grab_inputs(&mut self) -> Result<()> {
-        for d in &mut self.devices {
-            d.grab()?;
-        }
-        Ok(())
-    }
-
-    pub fn ungrab_inputs(&mut self) -> Result<()> {
-        for d in &mut self.devices {
-            d.ungrab()?;
-        }
-        Ok(())
+    /// Adds a (re)opened device, grabbing it if the other inputs are grabbed.
+    pub fn add_device(&mut self, device: Device, path: PathBuf) -> Result<()> {
+        self.push_device(device, Some(path))
     }
 
     #[must_use]
-    pub fn available_axes(&self) -> Option<Vec<RelativeAxisCode>> {
+    pub fn available_axes(&self) -> Option<Vec<codes::RelativeAxisCode>> {
         let mut result = Vec::new();
         for d in &self.devices {
-            result.extend(d.supported_relative_axes()?.iter());
+            if let Some(axes) = d.supported_relative_axes() {
+                result.extend(axes.iter().map(codes::RelativeAxisCode::from));
+            }
+            if is_touchpad(d) {
+                result.extend([
+                    codes::RelativeAxisCode::REL_X,
+                    codes::RelativeAxisCode::REL_Y,
+                    codes::RelativeAxisCode::REL_WHEEL,
+                ]);
+            }
         }
         Some(result)
     }
 
     #[must_use]
-    pub fn available_keys(&self) -> Option<Vec<KeyCode>> {
+    pub fn available_keys(&self) -> Option<Vec<codes::KeyCode>> {
         let mut result = Vec::new();
         for d in &self.devices {
-            result.extend(d.supported_keys()?.iter());
+            if let Some(keys) = d.supported_keys() {
+                result.extend(keys.iter().map(codes::KeyCode::from));
+            }
+            if is_touchpad(d) {
+                // taps are replayed as clicks
+                result.extend([
+                    codes::KeyCode::BTN_LEFT,
+                    codes::KeyCode::BTN_RIGHT,
+                    codes::KeyCode::BTN_MIDDLE,
+                ]);
+            }
         }
 
         Some(result)
     }
 
-    pub fn list_devices() -> Vec<(PathBuf, String, DeviceType)> {
-        let mut devices = Vec::new();
+    /// Enumerates all readable input devices with their ids, capabilities and kind.
+    pub fn list_devices() -> Vec<DeviceInfo> {
+        let links = by_id_links();
 
-        for (path, dev) in evdev::enumerate() {
-            let mut name = String::new();
-
-            if let Some(n) = dev.name() {
-                name = n.to_string();
-            }
-
-            let mut t = DeviceType::Mouse;
-
-            let events = dev.supported_events();
-            if !events.contains(evdev::EventType::RELATIVE) {
-                t = DeviceType::Keyboard;
-            }
-            if !events.contains(evdev::EventType::KEY) {
-                t = DeviceType::Other;
-            }
-
-            devices.push((path, name, t));
-        }
-
-        devices
+        evdev::enumerate()
+            .map(|(path, dev)| {
+                let mut info = DeviceInfo::from_device(path, &dev);
+                info.by_id = links.get(&info.path).cloned();
+                info
+            })
+            .collect()
     }
 }
 
@@ -97,79 +126,287 @@ where
         Ok(device)
     }
 
-    pub const fn new(devices: Vec<S>) -> Result<Self> {
-        Ok(Self { devices })
-    }
-
-    fn match_mouse_move(code: RelativeAxisCode, val: i32) -> MouseMove {
-        //println!("DEBUG: code: {:?} s value: {}", code, val); debug for future
-        match code {
-            RelativeAxisCode::REL_X => MouseMove {
-                x: val,
-                y: 0,
-                wheel: 0,
-            },
-            RelativeAxisCode::REL_Y => MouseMove {
-                x: 0,
-                y: val,
-                wheel: 0,
-            },
-            RelativeAxisCode::REL_WHEEL => MouseMove {
-                wheel: val,
-                ..Default::default()
-            },
-            _ => MouseMove::default(), //doesnt crash, we just ignore and create blank message
+    pub fn new(devices: Vec<S>) -> Result<Self> {
+        Self::with_touchpad_config(devices, TouchpadConfig::default())
+    }
+
+    pub fn with_touchpad_config(devices: Vec<S>, config: TouchpadConfig) -> Result<Self> {
+        let mut reader = Self {
+            devices: Vec::new(),
+            states: Vec::new(),
+            touchpad_config: config,
+            grab: InputGrab::default(),
+            grabbed: false,
+            disconnected: Vec::new(),
+        };
+        for d in devices {
+            reader.push_device(d, None)?;
         }
+        Ok(reader)
     }
 
-    const fn match_button(code: KeyCode) -> Option<MouseButton> {
-        match code {
-            KeyCode::BTN_LEFT => Some(MouseButton::Left),
-            KeyCode::BTN_RIGHT => Some(MouseButton::Right),
-            KeyCode::BTN_MIDDLE => Some(MouseButton::Middle),
-            _ => None,
+    /// Adds a device to read from, `path` identifies it in [`Self::take_disconnected`].
+    ///
+    /// The device joins the reader's [`InputGrab`] and is grabbed if the inputs are held.
+    pub fn push_device(&mut self, device: S, path: Option<PathBuf>) -> Result<()> {
+        let mut state = DeviceState::new(&device, self.touchpad_config, path);
+        if let Some(fd) = device.grab_fd() {
+            state.grab_id = Some(self.grab.attach(fd)?);
         }
+        self.states.push(state);
+        self.devices.push(device);
+        Ok(())
     }
 
-    const fn match_key(code: KeyCode, val: i32) -> DriverEvent {
-        let pressed = val != 0;
-        if let Some(button) = Self::match_button(code) {
-            return DriverEvent::mouse_click(button, pressed);
+    /// Grabs the devices for as long as the reader (or a stream made from it) wants them.
+    pub fn grab_inputs(&mut self) -> Result<()> {
+        if !self.grabbed {
+            self.grab.acquire()?;
+            self.grabbed = true;
         }
-        DriverEvent::keyboard_press(code.0, pressed)
+        Ok(())
+    }
+
+    /// Gives up the reader's grab, devices stay grabbed while anyone else holds them.
+    pub fn ungrab_inputs(&mut self) -> Result<()> {
+        if self.grabbed {
+            self.grabbed = false;
+            self.grab.release()?;
+        }
+        Ok(())
+    }
+
+    /// Grab shared by the reader's devices, see [`crate::writer::DriverWriter::attach_inputs`].
+    #[must_use]
+    pub fn input_grab(&self) -> InputGrab {
+        self.grab.clone()
     }
 
-    fn fetch_events(device: &mut S) -> Result<Vec<DriverEvent>> {
+    /// Removes the device opened from `path`, returns `false` if there is none.
+    pub fn remove_device(&mut self, path: &Path) -> bool {
+        let Some(index) = self
+            .states
+            .iter()
+            .position(|s| s.path.as_deref() == Some(path))
+        else {
+            return false;
+        };
+        let state = self.states.remove(index);
+        self.devices.remove(index);
+        if let Some(id) = state.grab_id {
+            self.grab.detach(id);
+        }
+        true
+    }
+
+    /// Paths of the devices currently read from, devices added without one are skipped.
+    pub fn device_paths(&self) -> impl Iterator<Item = &Path> {
+        self.states.iter().filter_map(|s| s.path.as_deref())
+    }
+
+    /// Returns the paths of devices dropped since the last call because they were unplugged.
+    pub fn take_disconnected(&mut self) -> Vec<PathBuf> {
+        std::mem::take(&mut self.disconnected)
+    }
+
+    fn fetch_frames(device: &mut S, state: &mut DeviceState) -> Result<Vec<EventFrame>> {
         let events = match device.get_events() {
             Ok(events) => events,
             Err(e) if e.kind() == ErrorKind::WouldBlock => return Ok(Vec::new()),
             Err(e) => return Err(e),
         };
 
-        Ok(events
-            .iter()
-            .filter_map(|event| match event.destructure() {
-                EventSummary::RelativeAxis(_, code, val) => {
-                    Some(DriverEvent::MouseMove(Self::match_mouse_move(code, val)))
-                }
-                EventSummary::Key(_, code, val) => Some(Self::match_key(code, val)),
-                EventSummary::Repeat(_, _code, _val) => {
-                    // todo?
-                    None
-                }
-                _ => None,
-            })
-            .collect())
+        let mut frames = Vec::new();
+        for event in events {
+            state.decode(event, &mut frames);
+        }
+
+        Ok(frames)
     }
 
-    pub fn read_events(&mut self) -> Result<Vec<DriverEvent>> {
+    /// Reads all complete frames, each one holding what a device reported between two `SYN_REPORT`s.
+    ///
+    /// Relative axes of one frame are merged into a single `MouseMove`, so a diagonal
+    /// movement stays one move.
+    ///
+    /// An unplugged device does not fail the read, it is removed and its path is
+    /// reported by [`Self::take_disconnected`].
+    pub fn read_frames(&mut self) -> Result<Vec<EventFrame>> {
         let mut result = Vec::new();
-        for d in &mut self.devices {
-            result.extend(Self::fetch_events(d)?);
+        let mut index = 0;
+        while index < self.devices.len() {
+            match Self::fetch_frames(&mut self.devices[index], &mut self.states[index]) {
+                Ok(frames) => result.extend(frames),
+                Err(e) if is_unplugged(&e) => {
+                    self.devices.remove(index);
+                    let state = self.states.remove(index);
+                    if let Some(id) = state.grab_id {
+                        self.grab.detach(id);
+                    }
+                    self.disconnected.extend(state.path);
+                    continue;
+                }
+                Err(e) => return Err(e),
+            }
+            index += 1;
         }
 
         Ok(result)
     }
+
+    pub fn read_events(&mut self) -> Result<Vec<DriverEvent>> {
+        Ok(self.read_frames()?.into_iter().flatten().collect())
+    }
+
+    /// Turns the reader into an async stream woken by the devices themselves.
+    ///
+    /// Must be called within a tokio runtime, grabbed devices stay grabbed.
+    pub fn into_stream(mut self) -> Result<DriverStream<S::Stream>>
+    where
+        S::Stream: Unpin,
+    {
+        let mut stream = DriverStream::with_grab(self.touchpad_config, self.grab.clone());
+        let devices = std::mem::take(&mut self.devices);
+        let states = std::mem::take(&mut self.states);
+        for (device, state) in devices.into_iter().zip(states) {
+            stream.push_source(device.to_stream()?, state);
+        }
+        // the hold moves to the stream, dropping the reader must not release it
+        stream.set_grabbed(std::mem::take(&mut self.grabbed));
+
+        Ok(stream)
+    }
+}
+
+impl DeviceState {
+    pub(crate) fn new(
+        device: &impl ToStream,
+        config: TouchpadConfig,
+        path: Option<PathBuf>,
+    ) -> Self {
+        Self {
+            pending: EventFrame::new(),
+            touchpad: device.is_touchpad().then(|| TouchpadTracker::new(config)),
+            path,
+            leds: device.lock_state(),
+            grab_id: None,
+            echoes: VecDeque::new(),
+        }
+    }
+
+    /// Feeds one raw event, pushing the frame it completes (if any) to `frames`.
+    pub(crate) fn decode(&mut self, event: InputEvent, frames: &mut Vec<EventFrame>) {
+        let pending = &mut self.pending;
+        match event.destructure() {
+            EventSummary::Synchronization(_, SynchronizationCode::SYN_REPORT, _) => {
+                if let Some(touchpad) = &mut self.touchpad {
+                    for e in touchpad.sync(event.timestamp()) {
+                        match e {
+                            DriverEvent::MouseMove(mm) => push_mouse_move(pending, mm),
+                            e => pending.push(e),
+                        }
+                    }
+                }
+                if !pending.is_empty() {
+                    frames.push(std::mem::take(pending));
+                }
+            }
+            EventSummary::RelativeAxis(_, code, val) => {
+                push_mouse_move(pending, match_mouse_move(code, val));
+            }
+            EventSummary::AbsoluteAxis(_, code, val) => {
+                if let Some(touchpad) = &mut self.touchpad {
+                    touchpad.on_abs(code, val);
+                }
+            }
+            EventSummary::Key(_, code, val) => {
+                if self.echoes.front() == Some(&(code.0, val)) {
+                    self.echoes.pop_front();
+                    return;
+                }
+                let consumed = self
+                    .touchpad
+                    .as_mut()
+                    .is_some_and(|touchpad| touchpad.on_key(code, val));
+                if !consumed {
+                    pending.push(match_key(code, val));
+                }
+            }
+            EventSummary::Led(_, code, val) => {
+                if let Some(leds) = &mut self.leds {
+                    leds.set_led(code.0, val != 0);
+                }
+            }
+            // EV_REP only reports the device's repeat delay/period, the repeats
+            // themselves arrive as EV_KEY with value 2 and are handled above
+            EventSummary::Repeat(_, _code, _val) => {}
+            _ => {}
+        }
+    }
+}
+
+fn match_mouse_move(code: RelativeAxisCode, val: i32) -> MouseMove {
+    match code {
+        RelativeAxisCode::REL_X => MouseMove {
+            x: val,
+            y: 0,
+            wheel: 0,
+        },
+        RelativeAxisCode::REL_Y => MouseMove {
+            x: 0,
+            y: val,
+            wheel: 0,
+        },
+        RelativeAxisCode::REL_WHEEL => MouseMove {
+            wheel: val,
+            ..Default::default()
+        },
+        _ => MouseMove::default(), //doesnt crash, blank moves are dropped from the frame
+    }
+}
+
+const fn match_button(code: KeyCode) -> Option<MouseButton> {
+    match code {
+        KeyCode::BTN_LEFT => Some(MouseButton::Left),
+        KeyCode::BTN_RIGHT => Some(MouseButton::Right),
+        KeyCode::BTN_MIDDLE => Some(MouseButton::Middle),
+        _ => None,
+    }
+}
+
+/// Whether presses of `code` are replayed, keyboard keys and the three mouse buttons
+pub(crate) const fn is_replayed(code: KeyCode) -> bool {
+    code.0 < KeyCode::BTN_0.0 || match_button(code).is_some()
+}
+
+const fn match_key(code: KeyCode, val: i32) -> DriverEvent {
+    let pressed = val != 0;
+    if let Some(button) = match_button(code) {
+        return DriverEvent::mouse_click(button, pressed);
+    }
+    if val == KEY_REPEAT_VALUE {
+        return DriverEvent::keyboard_repeat(code.0);
+    }
+    DriverEvent::keyboard_press(code.0, pressed)
+}
+
+/// Adds a relative move to the frame, merging it with a move that ends it.
+/// A move after a button stays separate so the click lands where it was made.
+fn push_mouse_move(frame: &mut EventFrame, mouse_move: MouseMove) {
+    if mouse_move == MouseMove::default() {
+        return;
+    }
+
+    if let Some(DriverEvent::MouseMove(mm)) = frame.last_mut() {
+        *mm += mouse_move;
+        return;
+    }
+    frame.push(DriverEvent::MouseMove(mouse_move));
+}
+
+/// `ENODEV` is what reading a removed evdev node fails with
+pub(crate) fn is_unplugged(e: &Error) -> bool {
+    e.raw_os_error() == Some(nix::errno::Errno::ENODEV as i32)
 }
 
 impl<S> Drop for DriverReader<S>
@@ -178,7 +415,9 @@ where
 {
     ///Added trait Drop, if the DriverReader gets closed (panic, close program) rust will call drop() - it will ungrab the program and will not lock you out
     fn drop(&mut self) {
-        let _ = self.devices.iter_mut().map(S::ungrab_device);
-        println!("DriverReader destroyed, ungrabbing inputs .. ");
+        if self.grabbed {
+            let _ = self.grab.release();
+            tracing::info!("DriverReader dropped while grabbing, inputs released");
+        }
     }
 }
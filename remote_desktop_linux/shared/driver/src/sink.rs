@@ -0,0 +1,74 @@
+use std::io::{Error, ErrorKind, Result};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+use crate::event::{DriverEvent, EventFrame};
+
+/// Destination replayed input is written to
+pub trait InputSink {
+    /// Emits all events of a frame as one batch.
+    fn simulate_frame(&mut self, frame: &[DriverEvent]) -> Result<()>;
+
+    /// Grabs the physical inputs attached to the sink, see [`DriverWriter::block_inputs`].
+    fn block_inputs(&self) -> Result<()>;
+
+    /// Gives back one [`Self::block_inputs`].
+    fn unblock_inputs(&self) -> Result<()>;
+}
+
+/// Sink keeping replayed frames in memory, clones share the record.
+///
+/// Stands in for [`DriverWriter`] where no `/dev/uinput` is available.
+#[derive(Debug, Clone, Default)]
+pub struct RecordingSink {
+    frames: Arc<Mutex<Vec<EventFrame>>>,
+    blocks: Arc<AtomicUsize>,
+}
+
+impl RecordingSink {
+    #[must_use]
+    pub fn frames(&self) -> Vec<EventFrame> {
+        self.frames.lock().expect("Failed to lock frames").clone()
+    }
+
+    /// All recorded events with the frame boundaries dropped.
+    #[must_use]
+    pub fn events(&self) -> Vec<DriverEvent> {
+        self.frames().into_iter().flatten().collect()
+    }
+
+    /// Number of [`InputSink::block_inputs`] calls not given back yet.
+    #[must_use]
+    pub fn blocked_inputs(&self) -> usize {
+        self.blocks.load(Ordering::SeqCst)
+    }
+
+    /// Returns the recorded frames and starts a new record.
+    pub fn take(&self) -> Vec<EventFrame> {
+        std::mem::take(&mut *self.frames.lock().expect("Failed to lock frames"))
+    }
+}
+
+impl InputSink for RecordingSink {
+    fn simulate_frame(&mut self, frame: &[DriverEvent]) -> Result<()> {
+        if !frame.is_empty() {
+            self.frames
+                .lock()
+                .expect("Failed to lock frames")
+                .push(frame.to_vec());
+        }
+        Ok(())
+    }
+
+    fn block_inputs(&self) -> Result<()> {
+        self.blocks.fetch_add(1, Ordering::SeqCst);
+        Ok(())
+    }
+
+    fn unblock_inputs(&self) -> Result<()> {
+        self.blocks
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1))
+            .map(drop)
+            .map_err(|_| Error::new(ErrorKind::InvalidInput, "inputs are not blocked"))
+    }
+}
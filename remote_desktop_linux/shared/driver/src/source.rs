@@ -0,0 +1,134 @@
+use std::collections::VecDeque;
+use std::future::poll_fn;
+use std::io::Result;
+use std::task::{Context, Poll};
+
+#[cfg(feature = "linux")]
+use crate::async_reader::DriverStream;
+use crate::event::{DriverEvent, EventFrame, LockState};
+#[cfg(feature = "linux")]
+use crate::stream::InputStream;
+
+/// Input the master's driver loop reads frames from, grabbed while a slave has focus
+pub trait InputSource {
+    /// Polls for the next frame, `None` once the source is exhausted.
+    fn poll_next_frame(&mut self, cx: &mut Context<'_>) -> Poll<Option<EventFrame>>;
+
+    /// Runs `grab`, which takes a hold on the inputs, releasing what is held on them
+    /// for everyone else first, see [`DriverStream::grab_with`].
+    fn grab_with(&mut self, grab: &mut dyn FnMut() -> Result<()>) -> Result<()>;
+
+    /// Lock state shown by the keyboard LEDs, `None` without keyboards.
+    fn lock_state(&self) -> Option<LockState>;
+
+    /// Lights the lock LEDs of all keyboards.
+    fn set_lock_leds(&mut self, locks: LockState) -> Result<()>;
+}
+
+/// Waits for the next frame of `source`, `None` once it is exhausted.
+pub async fn next_frame<S: InputSource + ?Sized>(source: &mut S) -> Option<EventFrame> {
+    poll_fn(|cx| source.poll_next_frame(cx)).await
+}
+
+#[cfg(feature = "linux")]
+impl<T> InputSource for DriverStream<T>
+where
+    T: InputStream + Unpin,
+{
+    fn poll_next_frame(&mut self, cx: &mut Context<'_>) -> Poll<Option<EventFrame>> {
+        DriverStream::poll_next_frame(self, cx).map(Some)
+    }
+
+    fn grab_with(&mut self, grab: &mut dyn FnMut() -> Result<()>) -> Result<()> {
+        DriverStream::grab_with(self, grab)
+    }
+
+    fn lock_state(&self) -> Option<LockState> {
+        DriverStream::lock_state(self)
+    }
+
+    fn set_lock_leds(&mut self, locks: LockState) -> Result<()> {
+        DriverStream::set_lock_leds(self, locks)
+    }
+}
+
+/// Source replaying a fixed list of frames, lets the driver loop run without devices.
+#[derive(Debug, Default)]
+pub struct ScriptedSource {
+    frames: VecDeque<EventFrame>,
+    leds: Option<LockState>,
+    /// Keys and buttons of the frames handed out that are still pressed
+    held: Vec<DriverEvent>,
+    /// Releases the grabs sent to everyone else
+    released: Vec<DriverEvent>,
+}
+
+impl ScriptedSource {
+    pub fn new(frames: impl IntoIterator<Item = EventFrame>) -> Self {
+        Self {
+            frames: frames.into_iter().collect(),
+            ..Default::default()
+        }
+    }
+
+    /// Pretends a keyboard with lock LEDs showing `locks` is attached.
+    #[must_use]
+    pub fn with_leds(mut self, locks: LockState) -> Self {
+        self.leds = Some(locks);
+        self
+    }
+
+    pub fn push_frame(&mut self, frame: EventFrame) {
+        self.frames.push_back(frame);
+    }
+
+    /// Releases of the inputs held while grabbing, as the desktop got them.
+    #[must_use]
+    pub fn released(&self) -> &[DriverEvent] {
+        &self.released
+    }
+
+    fn track_held(&mut self, frame: &EventFrame) {
+        for event in frame {
+            let (pressed, released) = match *event {
+                DriverEvent::KeyboardPress(kp) if !kp.repeat => {
+                    (kp.pressed, DriverEvent::keyboard_press(kp.key, false))
+                }
+                DriverEvent::MouseClick(mc) => {
+                    (mc.pressed, DriverEvent::mouse_click(mc.button, false))
+                }
+                _ => continue,
+            };
+            self.held.retain(|held| *held != released);
+            if pressed {
+                self.held.push(released);
+            }
+        }
+    }
+}
+
+impl InputSource for ScriptedSource {
+    fn poll_next_frame(&mut self, _cx: &mut Context<'_>) -> Poll<Option<EventFrame>> {
+        let frame = self.frames.pop_front();
+        if let Some(frame) = &frame {
+            self.track_held(frame);
+        }
+        Poll::Ready(frame)
+    }
+
+    fn grab_with(&mut self, grab: &mut dyn FnMut() -> Result<()>) -> Result<()> {
+        self.released.extend_from_slice(&self.held);
+        grab()
+    }
+
+    fn lock_state(&self) -> Option<LockState> {
+        self.leds
+    }
+
+    fn set_lock_leds(&mut self, locks: LockState) -> Result<()> {
+        if let Some(leds) = &mut self.leds {
+            *leds = locks;
+        }
+        Ok(())
+    }
+}
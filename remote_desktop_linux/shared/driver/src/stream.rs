@@ -2,13 +2,31 @@ use std::io::Result;
 
 use core::task::{Context, Poll};
 use evdev::uinput::{VirtualDevice, VirtualEventStream};
-use evdev::{Device, EventStream, InputEvent};
+use evdev::{Device, EventStream, EventType, InputEvent, LedCode};
+use std::os::fd::{AsFd, BorrowedFd};
 
 use futures_core::Stream;
 
+use crate::event::LockState;
+
 pub trait InputStream: Stream<Item = Result<InputEvent>> {
     fn get_next_event(&mut self) -> impl std::future::Future<Output = Result<InputEvent>> + Send;
     fn poll_next_event(&mut self, cx: &mut Context<'_>) -> Poll<Result<InputEvent>>;
+
+    /// Lights the lock LEDs, no-op for sources without them
+    fn set_leds(&mut self, _locks: LockState) -> Result<()> {
+        Ok(())
+    }
+
+    /// Keys and buttons the kernel holds pressed on the device, empty for other sources
+    fn held_keys(&self) -> Vec<u16> {
+        Vec::new()
+    }
+
+    /// Writes events into the device as if it had sent them, no-op for other sources
+    fn inject(&mut self, _events: &[InputEvent]) -> Result<()> {
+        Ok(())
+    }
 }
 
 pub trait ToStream {
@@ -16,7 +34,21 @@ pub trait ToStream {
 
     fn to_stream(self) -> Result<Self::Stream>;
     fn get_events(&mut self) -> Result<Vec<InputEvent>>;
-    fn ungrab_device(&mut self) -> Result<()>;
+
+    /// Descriptor to grab the device through, `None` for sources that cannot be grabbed
+    fn grab_fd(&self) -> Option<BorrowedFd<'_>> {
+        None
+    }
+
+    /// Whether the source reports absolute finger contacts that need touchpad conversion
+    fn is_touchpad(&self) -> bool {
+        false
+    }
+
+    /// Current lock LEDs, `None` for sources without them
+    fn lock_state(&self) -> Option<LockState> {
+        None
+    }
 }
 
 impl InputStream for VirtualEventStream {
@@ -37,6 +69,31 @@ impl InputStream for EventStream {
     fn poll_next_event(&mut self, cx: &mut Context<'_>) -> Poll<Result<InputEvent>> {
         self.poll_event(cx)
     }
+
+    fn set_leds(&mut self, locks: LockState) -> Result<()> {
+        let device = self.device_mut();
+        let Some(supported) = device.supported_leds() else {
+            return Ok(());
+        };
+        let events = locks
+            .leds()
+            .into_iter()
+            .filter(|(led, _)| supported.contains(LedCode(*led)))
+            .map(|(led, on)| InputEvent::new(EventType::LED.0, led, i32::from(on)))
+            .collect::<Vec<_>>();
+        device.send_events(&events)
+    }
+
+    fn held_keys(&self) -> Vec<u16> {
+        self.device()
+            .get_key_state()
+            .map(|keys| keys.iter().map(|key| key.code()).collect())
+            .unwrap_or_default()
+    }
+
+    fn inject(&mut self, events: &[InputEvent]) -> Result<()> {
+        self.device_mut().send_events(events)
+    }
 }
 
 impl ToStream for VirtualDevice {
@@ -48,10 +105,6 @@ impl ToStream for VirtualDevice {
     fn get_events(&mut self) -> Result<Vec<InputEvent>> {
         Ok(self.fetch_events()?.collect())
     }
-
-    fn ungrab_device(&mut self) -> Result<()> {
-        Ok(())
-    }
 }
 
 impl ToStream for Device {
@@ -64,7 +117,27 @@ impl ToStream for Device {
         Ok(self.fetch_events()?.collect())
     }
 
-    fn ungrab_device(&mut self) -> Result<()> {
-        self.ungrab()
+    fn grab_fd(&self) -> Option<BorrowedFd<'_>> {
+        Some(self.as_fd())
+    }
+
+    fn is_touchpad(&self) -> bool {
+        crate::touchpad::is_touchpad(self)
+    }
+
+    fn lock_state(&self) -> Option<LockState> {
+        if !self
+            .supported_leds()
+            .is_some_and(|leds| leds.contains(LedCode::LED_CAPSL))
+        {
+            return None;
+        }
+
+        let lit = self.get_led_state().ok()?;
+        let mut locks = LockState::default();
+        for (led, _) in LockState::default().leds() {
+            locks.set_led(led, lit.contains(LedCode(led)));
+        }
+        Some(locks)
     }
 }
@@ -0,0 +1,201 @@
+use std::time::{Duration, SystemTime};
+
+use evdev::{AbsoluteAxisCode, Device, KeyCode};
+
+use crate::device_info::DeviceCapabilities;
+use crate::device_type::DeviceType;
+use crate::event::{DriverEvent, EventFrame, MouseButton};
+
+/// Tuning of the touchpad to relative pointer conversion
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TouchpadConfig {
+    /// Touchpad units per emitted pointer unit
+    pub motion_divisor: i32,
+    /// Vertical two-finger travel (touchpad units) per wheel notch
+    pub scroll_step: i32,
+    /// Longest contact still treated as a tap
+    pub tap_timeout: Duration,
+    /// Largest travel (touchpad units) still treated as a tap
+    pub tap_max_travel: i32,
+}
+
+impl Default for TouchpadConfig {
+    fn default() -> Self {
+        Self {
+            motion_divisor: 2,
+            scroll_step: 60,
+            tap_timeout: Duration::from_millis(180),
+            tap_max_travel: 40,
+        }
+    }
+}
+
+/// Returns `true` for absolute pointing devices reporting finger contacts (touchpads).
+#[must_use]
+pub fn is_touchpad(device: &Device) -> bool {
+    DeviceType::classify(&DeviceCapabilities::from_device(device)) == DeviceType::Touchpad
+}
+
+/// Converts the touchpad's absolute contact reports into relative pointer events.
+///
+/// One finger moves the pointer, two fingers scroll and a short contact without
+/// travel is a tap (one finger left, two right, three middle click).
+/// Only the single-touch `ABS_X`/`ABS_Y` emulation is used, which follows one finger.
+#[derive(Debug, Default)]
+pub struct TouchpadTracker {
+    config: TouchpadConfig,
+    position: (Option<i32>, Option<i32>),
+    last: Option<(i32, i32)>,
+    touching: bool,
+    fingers: u8,
+    fingers_changed: bool,
+    touch: Option<TouchState>,
+    remainder: (i32, i32),
+    scroll: i32,
+}
+
+#[derive(Debug)]
+struct TouchState {
+    start: SystemTime,
+    max_fingers: u8,
+    travel: i32,
+    clicked: bool,
+}
+
+impl TouchpadTracker {
+    #[must_use]
+    pub fn new(config: TouchpadConfig) -> Self {
+        Self {
+            config,
+            ..Default::default()
+        }
+    }
+
+    pub fn on_abs(&mut self, code: AbsoluteAxisCode, value: i32) {
+        match code {
+            AbsoluteAxisCode::ABS_X => self.position.0 = Some(value),
+            AbsoluteAxisCode::ABS_Y => self.position.1 = Some(value),
+            _ => {}
+        }
+    }
+
+    /// Feeds a key event. Returns `true` if the key belongs to the touchpad's
+    /// contact reporting and must not be forwarded as a key press.
+    pub fn on_key(&mut self, code: KeyCode, value: i32) -> bool {
+        let pressed = value != 0;
+        let fingers = match code {
+            KeyCode::BTN_TOUCH => {
+                self.touching = pressed;
+                return true;
+            }
+            KeyCode::BTN_TOOL_FINGER => 1,
+            KeyCode::BTN_TOOL_DOUBLETAP => 2,
+            KeyCode::BTN_TOOL_TRIPLETAP => 3,
+            KeyCode::BTN_TOOL_QUADTAP => 4,
+            KeyCode::BTN_TOOL_QUINTTAP => 5,
+            KeyCode::BTN_LEFT | KeyCode::BTN_RIGHT | KeyCode::BTN_MIDDLE => {
+                // a physical click of a clickpad is forwarded, but cancels the tap
+                if let Some(touch) = &mut self.touch {
+                    touch.clicked = true;
+                }
+                return false;
+            }
+            _ => return false,
+        };
+
+        if pressed {
+            self.fingers = fingers;
+            self.fingers_changed = true;
+        } else if self.fingers == fingers {
+            self.fingers = 0;
+        }
+        true
+    }
+
+    /// Closes a `SYN_REPORT` frame, returning the pointer events it produced.
+    pub fn sync(&mut self, time: SystemTime) -> EventFrame {
+        let mut frame = EventFrame::new();
+
+        if !self.touching {
+            if let Some(touch) = self.touch.take() {
+                self.push_tap(&mut frame, &touch, time);
+            }
+            self.last = None;
+            return frame;
+        }
+
+        let (Some(x), Some(y)) = self.position else {
+            return frame;
+        };
+
+        let touch = self.touch.get_or_insert(TouchState {
+            start: time,
+            max_fingers: self.fingers,
+            travel: 0,
+            clicked: false,
+        });
+        touch.max_fingers = touch.max_fingers.max(self.fingers);
+
+        // the emulated position may jump to another finger when the count changes
+        let fingers_changed = std::mem::take(&mut self.fingers_changed);
+        let Some((last_x, last_y)) = self.last.replace((x, y)).filter(|_| !fingers_changed) else {
+            self.remainder = (0, 0);
+            self.scroll = 0;
+            return frame;
+        };
+
+        let (dx, dy) = (x - last_x, y - last_y);
+        touch.travel += dx.abs() + dy.abs();
+
+        match self.fingers {
+            1 => self.push_motion(&mut frame, dx, dy),
+            2 => self.push_scroll(&mut frame, dy),
+            _ => {}
+        }
+
+        frame
+    }
+
+    fn push_motion(&mut self, frame: &mut EventFrame, dx: i32, dy: i32) {
+        let divisor = self.config.motion_divisor.max(1);
+        let x = self.remainder.0 + dx;
+        let y = self.remainder.1 + dy;
+        self.remainder = (x % divisor, y % divisor);
+
+        let (x, y) = (x / divisor, y / divisor);
+        if x != 0 || y != 0 {
+            frame.push(DriverEvent::mouse_move(x, y, 0));
+        }
+    }
+
+    fn push_scroll(&mut self, frame: &mut EventFrame, dy: i32) {
+        let step = self.config.scroll_step.max(1);
+        self.scroll += dy;
+        let notches = self.scroll / step;
+        self.scroll %= step;
+
+        // fingers moving up scroll up, which is a positive wheel value
+        if notches != 0 {
+            frame.push(DriverEvent::mouse_move(0, 0, -notches));
+        }
+    }
+
+    fn push_tap(&self, frame: &mut EventFrame, touch: &TouchState, time: SystemTime) {
+        let duration = time.duration_since(touch.start).unwrap_or_default();
+        if touch.clicked
+            || duration > self.config.tap_timeout
+            || touch.travel > self.config.tap_max_travel
+        {
+            return;
+        }
+
+        let button = match touch.max_fingers {
+            1 => MouseButton::Left,
+            2 => MouseButton::Right,
+            3 => MouseButton::Middle,
+            _ => return,
+        };
+        frame.push(DriverEvent::mouse_click(button, true));
+        frame.push(DriverEvent::mouse_click(button, false));
+    }
+}
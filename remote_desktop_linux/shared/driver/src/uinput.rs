@@ -0,0 +1,193 @@
+use std::fs::{File, OpenOptions};
+use std::io::{ErrorKind, Read, Result, Write};
+use std::os::fd::{AsRawFd, RawFd};
+use std::os::unix::fs::OpenOptionsExt;
+
+use evdev::{EventType, InputEvent, SynchronizationCode};
+use nix::libc;
+use nix::sys::ioctl::ioctl_param_type;
+
+use crate::codes::{KeyCode, RelativeAxisCode};
+
+const UINPUT_PATH: &str = "/dev/uinput";
+const UINPUT_MAX_NAME_SIZE: usize = 80;
+const BUS_USB: u16 = 0x03;
+
+#[repr(C)]
+struct InputId {
+    bustype: u16,
+    vendor: u16,
+    product: u16,
+    version: u16,
+}
+
+#[repr(C)]
+struct UinputSetup {
+    id: InputId,
+    name: [libc::c_char; UINPUT_MAX_NAME_SIZE],
+    ff_effects_max: u32,
+}
+
+mod sys {
+    nix::ioctl_none!(ui_dev_create, b'U', 1);
+    nix::ioctl_none!(ui_dev_destroy, b'U', 2);
+    nix::ioctl_write_ptr!(ui_dev_setup, b'U', 3, super::UinputSetup);
+    nix::ioctl_write_int!(ui_set_evbit, b'U', 100);
+    nix::ioctl_write_int!(ui_set_keybit, b'U', 101);
+    nix::ioctl_write_int!(ui_set_relbit, b'U', 102);
+    nix::ioctl_write_int!(ui_set_ledbit, b'U', 105);
+}
+
+type SetBit = unsafe fn(libc::c_int, ioctl_param_type) -> nix::Result<libc::c_int>;
+
+/// uinput device with keys, relative axes and LEDs.
+///
+/// evdev's `VirtualDeviceBuilder` cannot declare `EV_LED`, without it the system
+/// never tells the virtual keyboard its Caps/Num/Scroll Lock state.
+pub(crate) struct UinputDevice {
+    file: File,
+}
+
+impl UinputDevice {
+    pub(crate) fn create(
+        name: &str,
+        keys: &[KeyCode],
+        axes: &[RelativeAxisCode],
+        leds: &[u16],
+    ) -> Result<Self> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .custom_flags(libc::O_NONBLOCK)
+            .open(UINPUT_PATH)?;
+        let fd = file.as_raw_fd();
+
+        let mut setup = UinputSetup {
+            id: InputId {
+                bustype: BUS_USB,
+                vendor: 0x1234,
+                product: 0x5678,
+                version: 0x111,
+            },
+            name: [0; UINPUT_MAX_NAME_SIZE],
+            ff_effects_max: 0,
+        };
+        // the last byte stays 0 as the terminator
+        for (dst, src) in setup
+            .name
+            .iter_mut()
+            .zip(name.bytes().take(UINPUT_MAX_NAME_SIZE - 1))
+        {
+            *dst = src as libc::c_char;
+        }
+
+        set_bits(
+            fd,
+            EventType::KEY,
+            keys.iter().map(|k| k.code()),
+            sys::ui_set_keybit,
+        )?;
+        set_bits(
+            fd,
+            EventType::RELATIVE,
+            axes.iter().map(|a| a.0),
+            sys::ui_set_relbit,
+        )?;
+        set_bits(fd, EventType::LED, leds.iter().copied(), sys::ui_set_ledbit)?;
+        // SAFETY: `fd` is an open uinput file and `UinputSetup` matches `struct uinput_setup`
+        unsafe {
+            sys::ui_dev_setup(fd, &setup)?;
+            sys::ui_dev_create(fd)?;
+        }
+
+        Ok(Self { file })
+    }
+
+    /// Writes the events followed by a `SYN_REPORT`.
+    pub(crate) fn emit(&mut self, events: &[InputEvent]) -> Result<()> {
+        let syn = InputEvent::new(
+            EventType::SYNCHRONIZATION.0,
+            SynchronizationCode::SYN_REPORT.0,
+            0,
+        );
+        let raw = events
+            .iter()
+            .chain([&syn])
+            .map(|e| raw_event(e.event_type().0, e.code(), e.value()))
+            .collect::<Vec<_>>();
+
+        // SAFETY: `input_event` is plain old data, the slice covers exactly `raw`
+        let bytes = unsafe {
+            std::slice::from_raw_parts(
+                raw.as_ptr().cast::<u8>(),
+                std::mem::size_of_val(raw.as_slice()),
+            )
+        };
+        self.file.write_all(bytes)
+    }
+
+    /// Drains the `EV_LED` events the system wrote to the device as `(led, on)` pairs.
+    pub(crate) fn read_leds(&mut self) -> Result<Vec<(u16, bool)>> {
+        let mut leds = Vec::new();
+        let mut raw = raw_event(0, 0, 0);
+        loop {
+            // SAFETY: any bytes form a valid `input_event`
+            let buf = unsafe {
+                std::slice::from_raw_parts_mut(
+                    std::ptr::from_mut(&mut raw).cast::<u8>(),
+                    std::mem::size_of::<libc::input_event>(),
+                )
+            };
+            match self.file.read(buf) {
+                Ok(n) if n == buf.len() => {
+                    if raw.type_ == EventType::LED.0 {
+                        leds.push((raw.code, raw.value != 0));
+                    }
+                }
+                Ok(_) => return Ok(leds),
+                Err(e) if e.kind() == ErrorKind::WouldBlock => return Ok(leds),
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+impl Drop for UinputDevice {
+    fn drop(&mut self) {
+        // SAFETY: the device was created on this file
+        let _ = unsafe { sys::ui_dev_destroy(self.file.as_raw_fd()) };
+    }
+}
+
+fn set_bits(
+    fd: RawFd,
+    event_type: EventType,
+    codes: impl Iterator<Item = u16>,
+    set_bit: SetBit,
+) -> Result<()> {
+    let mut codes = codes.peekable();
+    if codes.peek().is_none() {
+        return Ok(());
+    }
+
+    // SAFETY: `fd` is an open uinput file, the codes are plain integers
+    unsafe {
+        sys::ui_set_evbit(fd, ioctl_param_type::from(event_type.0))?;
+        for code in codes {
+            set_bit(fd, ioctl_param_type::from(code))?;
+        }
+    }
+    Ok(())
+}
+
+fn raw_event(event_type: u16, code: u16, value: i32) -> libc::input_event {
+    libc::input_event {
+        time: libc::timeval {
+            tv_sec: 0,
+            tv_usec: 0,
+        },
+        type_: event_type,
+        code,
+        value,
+    }
+}
@@ -1,73 +1,207 @@
-use std::io::Result;
+use std::io::{Error, ErrorKind, Result};
+use std::sync::atomic::{AtomicUsize, Ordering};
 
+use super::codes;
 use super::event::*;
+use super::grab::InputGrab;
+use super::reader::KEY_REPEAT_VALUE;
+use super::sink::InputSink;
+use super::uinput::UinputDevice;
 
-use evdev::{
-    uinput::VirtualDevice, AttributeSet, KeyCode, KeyEvent, RelativeAxisCode, RelativeAxisEvent,
-};
+use evdev::{InputEvent, KeyCode, KeyEvent, RelativeAxisCode, RelativeAxisEvent};
 
 pub struct DriverWriter {
-    device: VirtualDevice,
+    device: UinputDevice,
+    /// Lock state the system last reported through the virtual keyboard's LEDs
+    lock_state: LockState,
+    /// Physical devices suppressed by [`Self::block_inputs`]
+    inputs: Option<InputGrab>,
+    /// Holds on `inputs` taken by [`Self::block_inputs`] and not given back yet
+    blocks: AtomicUsize,
 }
 
-impl DriverWriter {
-    pub fn new(keys: Vec<KeyCode>, axes: Vec<RelativeAxisCode>) -> Result<Self> {
-        // name format "fake-kmr-<timestamp:mili>"
-        let keys = keys.iter().collect::<AttributeSet<KeyCode>>();
-        let axes = axes.iter().collect::<AttributeSet<RelativeAxisCode>>();
-
-        let device = VirtualDevice::builder()?
-            .name("fake-kmdr-")
-            .with_keys(&keys)?
-            .with_relative_axes(&axes)?
-            .build()?;
+/// Name prefix of the virtual devices created by [`DriverWriter`]
+pub const VIRTUAL_DEVICE_NAME: &str = "fake-kmdr-";
 
-        Ok(Self { device })
+impl DriverWriter {
+    /// Creates the virtual device, lock keys and their LEDs are always declared
+    /// so the lock state can be synchronized.
+    pub fn new(mut keys: Vec<codes::KeyCode>, axes: Vec<codes::RelativeAxisCode>) -> Result<Self> {
+        keys.extend(
+            [
+                LockState::CAPS_LOCK_KEY,
+                LockState::NUM_LOCK_KEY,
+                LockState::SCROLL_LOCK_KEY,
+            ]
+            .map(codes::KeyCode::new),
+        );
+        keys.sort_by_key(|k| k.code());
+        keys.dedup();
+
+        let leds = LockState::default().leds().map(|(led, _)| led);
+        let device = UinputDevice::create(VIRTUAL_DEVICE_NAME, &keys, &axes, &leds)?;
+
+        Ok(Self {
+            device,
+            lock_state: LockState::default(),
+            inputs: None,
+            blocks: AtomicUsize::new(0),
+        })
     }
 
-    fn simulate_mouse_move(&mut self, mouse_move: &MouseMove) -> Result<()> {
-        let x = *RelativeAxisEvent::new(RelativeAxisCode::REL_X, mouse_move.x);
-        let y = *RelativeAxisEvent::new(RelativeAxisCode::REL_Y, mouse_move.y);
-        let wheel = *RelativeAxisEvent::new(RelativeAxisCode::REL_WHEEL, mouse_move.wheel);
-        self.device.emit(&[x, y, wheel])?;
-
-        Ok(())
+    fn mouse_move_events(mouse_move: &MouseMove) -> impl Iterator<Item = InputEvent> {
+        [
+            (RelativeAxisCode::REL_X, mouse_move.x),
+            (RelativeAxisCode::REL_Y, mouse_move.y),
+            (RelativeAxisCode::REL_WHEEL, mouse_move.wheel),
+        ]
+        .into_iter()
+        .filter(|(_, value)| *value != 0)
+        .map(|(code, value)| *RelativeAxisEvent::new(code, value))
     }
 
-    fn simulate_mouse_click(&mut self, click: &MouseClick) -> Result<()> {
+    fn mouse_click_event(click: &MouseClick) -> InputEvent {
         let button = match click.button {
             MouseButton::Left => KeyCode::BTN_LEFT,
             MouseButton::Right => KeyCode::BTN_RIGHT,
             MouseButton::Middle => KeyCode::BTN_MIDDLE,
         };
-        let click = *KeyEvent::new(button, i32::from(click.pressed));
+        *KeyEvent::new(button, i32::from(click.pressed))
+    }
 
-        self.device.emit(&[click])?;
+    fn key_press_event(press: &KeyboardPress) -> InputEvent {
+        let value = if press.repeat {
+            KEY_REPEAT_VALUE
+        } else {
+            i32::from(press.pressed)
+        };
+        *KeyEvent::new(KeyCode(press.key), value)
+    }
 
-        Ok(())
+    fn input_events(event: &DriverEvent) -> Vec<InputEvent> {
+        match event {
+            DriverEvent::MouseMove(mouse_move) => Self::mouse_move_events(mouse_move).collect(),
+            DriverEvent::MouseClick(click) => vec![Self::mouse_click_event(click)],
+            DriverEvent::KeyboardPress(press) => vec![Self::key_press_event(press)],
+            // needs key taps in frames of their own, see `sync_lock_state`
+            DriverEvent::LockState(_) => Vec::new(),
+        }
     }
 
-    fn simulate_key_press(&mut self, press: &KeyboardPress) -> Result<()> {
-        let press = *KeyEvent::new(KeyCode(press.key), i32::from(press.pressed));
+    fn emit(&mut self, events: &[InputEvent]) -> Result<()> {
+        if events.is_empty() {
+            return Ok(());
+        }
+        self.device.emit(events)
+    }
+
+    /// Emits all events of a frame followed by a single `SYN_REPORT`.
+    pub fn simulate_frame(&mut self, frame: &[DriverEvent]) -> Result<()> {
+        let mut events = Vec::new();
+        for event in frame {
+            if let DriverEvent::LockState(target) = event {
+                self.emit(&std::mem::take(&mut events))?;
+                self.sync_lock_state(*target)?;
+            } else {
+                events.extend(Self::input_events(event));
+            }
+        }
+
+        self.emit(&events)
+    }
 
-        self.device.emit(&[press])?;
+    /// Lock state last reported by the system through the virtual keyboard's LEDs.
+    pub fn lock_state(&mut self) -> Result<LockState> {
+        for (led, on) in self.device.read_leds()? {
+            self.lock_state.set_led(led, on);
+        }
+        Ok(self.lock_state)
+    }
 
+    /// Taps the lock keys whose state differs from `target`.
+    pub fn sync_lock_state(&mut self, target: LockState) -> Result<()> {
+        let current = self.lock_state()?;
+        for key in current.keys_to_reach(target) {
+            self.device.emit(&[*KeyEvent::new(KeyCode(key), 1)])?;
+            self.device.emit(&[*KeyEvent::new(KeyCode(key), 0)])?;
+        }
+        self.lock_state = target;
         Ok(())
     }
 
     pub fn simulate_event(&mut self, event: DriverEvent) -> Result<()> {
-        match event {
-            DriverEvent::MouseMove(mouse_move) => self.simulate_mouse_move(&mouse_move),
-            DriverEvent::MouseClick(click) => self.simulate_mouse_click(&click),
-            DriverEvent::KeyboardPress(press) => self.simulate_key_press(&press),
-        }
+        self.simulate_frame(&[event])
+    }
+
+    /// Associates the physical devices [`Self::block_inputs`] suppresses, usually
+    /// [`crate::reader::DriverReader::input_grab`]. Blocks on the previous ones are given back.
+    pub fn attach_inputs(&mut self, inputs: InputGrab) {
+        self.release_blocks();
+        self.inputs = Some(inputs);
     }
 
+    /// Grabs the attached devices so their input only reaches this program.
+    ///
+    /// Blocks nest, each one needs its own [`Self::unblock_inputs`]. Whatever is still
+    /// blocked when the writer is dropped gets released.
     pub fn block_inputs(&self) -> Result<()> {
-        unimplemented!()
+        let inputs = self
+            .inputs
+            .as_ref()
+            .ok_or_else(|| Error::new(ErrorKind::NotFound, "no input devices attached"))?;
+        inputs.acquire()?;
+        self.blocks.fetch_add(1, Ordering::SeqCst);
+        Ok(())
     }
 
+    /// Gives back one [`Self::block_inputs`], the devices are released with the last
+    /// hold on them.
     pub fn unblock_inputs(&self) -> Result<()> {
-        unimplemented!()
+        if self
+            .blocks
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1))
+            .is_err()
+        {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "inputs are not blocked",
+            ));
+        }
+        self.inputs.as_ref().map_or(Ok(()), InputGrab::release)
+    }
+
+    /// Number of [`Self::block_inputs`] calls not given back yet.
+    #[must_use]
+    pub fn blocked_inputs(&self) -> usize {
+        self.blocks.load(Ordering::SeqCst)
+    }
+
+    fn release_blocks(&mut self) {
+        let blocks = std::mem::take(self.blocks.get_mut());
+        if let Some(inputs) = &self.inputs {
+            for _ in 0..blocks {
+                let _ = inputs.release();
+            }
+        }
+    }
+}
+
+impl Drop for DriverWriter {
+    fn drop(&mut self) {
+        self.release_blocks();
+    }
+}
+
+impl InputSink for DriverWriter {
+    fn simulate_frame(&mut self, frame: &[DriverEvent]) -> Result<()> {
+        DriverWriter::simulate_frame(self, frame)
+    }
+
+    fn block_inputs(&self) -> Result<()> {
+        DriverWriter::block_inputs(self)
+    }
+
+    fn unblock_inputs(&self) -> Result<()> {
+        DriverWriter::unblock_inputs(self)
     }
 }
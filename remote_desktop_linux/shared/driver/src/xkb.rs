@@ -0,0 +1,94 @@
+//! Characters of an xkb layout, read from the keymap libxkbcommon compiles for it.
+//!
+//! libxkbcommon is loaded at runtime, without it no layout is known.
+
+use std::collections::HashMap;
+
+use crate::layout::{Keysym, Level};
+
+/// Rows of the main block whose keys type characters, everything else stays a raw key code
+#[cfg(feature = "linux")]
+const CHARACTER_KEYS: [(u16, u16); 6] = [(2, 13), (16, 27), (30, 41), (43, 53), (57, 57), (86, 86)];
+
+/// xkb key codes are evdev ones shifted by 8
+#[cfg(feature = "linux")]
+const EVDEV_OFFSET: u32 = 8;
+
+/// Keysyms of the character keys at each level of `layout` with `variant`, empty for none.
+/// `None` if libxkbcommon or the system's xkb data does not know them.
+#[cfg(feature = "linux")]
+pub(crate) fn keymap_keys(layout: &str, variant: &str) -> Option<HashMap<(u16, Level), Keysym>> {
+    use std::ffi::CString;
+    use std::ptr;
+    use xkbcommon_dl::{
+        xkb_context_flags, xkb_keymap_compile_flags, xkb_log_level, xkb_rule_names,
+        xkbcommon_option,
+    };
+
+    let xkb = xkbcommon_option()?;
+    let layout = CString::new(layout).ok()?;
+    let variant = CString::new(variant).ok()?;
+    let names = xkb_rule_names {
+        rules: ptr::null(),
+        model: ptr::null(),
+        layout: layout.as_ptr(),
+        variant: variant.as_ptr(),
+        options: ptr::null(),
+    };
+
+    // SAFETY: `names` and the strings it points to outlive the calls, the context and
+    // keymap are checked for null and unreferenced once done
+    unsafe {
+        let context = (xkb.xkb_context_new)(xkb_context_flags::XKB_CONTEXT_NO_ENVIRONMENT_NAMES);
+        if context.is_null() {
+            return None;
+        }
+        // an unknown layout is reported through `None`, not on stderr
+        (xkb.xkb_context_set_log_level)(context, xkb_log_level::XKB_LOG_LEVEL_CRITICAL);
+        let keymap = (xkb.xkb_keymap_new_from_names)(
+            context,
+            &names,
+            xkb_keymap_compile_flags::XKB_KEYMAP_COMPILE_NO_FLAGS,
+        );
+        (xkb.xkb_context_unref)(context);
+        if keymap.is_null() {
+            return None;
+        }
+
+        let mut keys = HashMap::new();
+        for code in CHARACTER_KEYS
+            .iter()
+            .flat_map(|&(first, last)| first..=last)
+        {
+            let keycode = u32::from(code) + EVDEV_OFFSET;
+            let levels = (xkb.xkb_keymap_num_levels_for_key)(keymap, keycode, 0);
+            // a one-level key like Space types the same with Shift
+            let shift = if levels == 1 { 0 } else { 1 };
+            for (index, level) in [(0, Level::Base), (shift, Level::Shift), (2, Level::AltGr)] {
+                if index >= levels {
+                    continue;
+                }
+                let mut syms = ptr::null();
+                let count =
+                    (xkb.xkb_keymap_key_get_syms_by_level)(keymap, keycode, 0, index, &mut syms);
+                if count != 1 {
+                    continue;
+                }
+                // dead keys and control characters have no character to send
+                let c = char::from_u32((xkb.xkb_keysym_to_utf32)(*syms))
+                    .filter(|c| *c != '\0' && !c.is_control());
+                if let Some(c) = c {
+                    keys.insert((code, level), Keysym::from_char(c));
+                }
+            }
+        }
+        (xkb.xkb_keymap_unref)(keymap);
+        Some(keys)
+    }
+}
+
+/// Without the `linux` feature libxkbcommon is not loaded and no layout is known.
+#[cfg(not(feature = "linux"))]
+pub(crate) fn keymap_keys(_layout: &str, _variant: &str) -> Option<HashMap<(u16, Level), Keysym>> {
+    None
+}
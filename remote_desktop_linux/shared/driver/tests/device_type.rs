@@ -0,0 +1,103 @@
+#![cfg(feature = "linux")]
+
+use evdev::{AbsoluteAxisCode as AAC, KeyCode as KC, LedCode, RelativeAxisCode as RAC};
+use kmf_driver::device_info::DeviceCapabilities;
+use kmf_driver::device_type::DeviceType;
+
+fn caps(keys: &[KC], rel: &[RAC], abs: &[AAC]) -> DeviceCapabilities {
+    DeviceCapabilities {
+        keys: keys.iter().map(|k| k.code()).collect(),
+        relative_axes: rel.iter().map(|a| a.0).collect(),
+        absolute_axes: abs.iter().map(|a| a.0).collect(),
+        leds: Vec::new(),
+    }
+}
+
+#[test]
+fn classifies_mouse() {
+    let mouse = caps(
+        &[KC::BTN_LEFT, KC::BTN_RIGHT, KC::BTN_MIDDLE],
+        &[RAC::REL_X, RAC::REL_Y, RAC::REL_WHEEL],
+        &[],
+    );
+    assert_eq!(DeviceType::classify(&mouse), DeviceType::Mouse);
+}
+
+#[test]
+fn classifies_keyboard() {
+    let mut keyboard = caps(
+        &[
+            KC::KEY_A,
+            KC::KEY_Z,
+            KC::KEY_SPACE,
+            KC::KEY_ENTER,
+            KC::KEY_ESC,
+        ],
+        &[],
+        &[],
+    );
+    keyboard.leds = vec![LedCode::LED_CAPSL.0, LedCode::LED_NUML.0];
+    assert_eq!(DeviceType::classify(&keyboard), DeviceType::Keyboard);
+}
+
+#[test]
+fn power_button_is_not_a_keyboard() {
+    let power = caps(&[KC::KEY_POWER], &[], &[]);
+    assert_eq!(DeviceType::classify(&power), DeviceType::Other);
+
+    let media = caps(
+        &[KC::KEY_VOLUMEUP, KC::KEY_VOLUMEDOWN, KC::KEY_MUTE],
+        &[],
+        &[],
+    );
+    assert_eq!(DeviceType::classify(&media), DeviceType::Other);
+}
+
+#[test]
+fn classifies_touchpad() {
+    let touchpad = caps(
+        &[
+            KC::BTN_LEFT,
+            KC::BTN_TOUCH,
+            KC::BTN_TOOL_FINGER,
+            KC::BTN_TOOL_DOUBLETAP,
+        ],
+        &[],
+        &[
+            AAC::ABS_X,
+            AAC::ABS_Y,
+            AAC::ABS_MT_SLOT,
+            AAC::ABS_MT_POSITION_X,
+            AAC::ABS_MT_POSITION_Y,
+        ],
+    );
+    assert_eq!(DeviceType::classify(&touchpad), DeviceType::Touchpad);
+}
+
+#[test]
+fn classifies_tablet() {
+    let tablet = caps(
+        &[KC::BTN_TOOL_PEN, KC::BTN_TOUCH, KC::BTN_STYLUS],
+        &[],
+        &[AAC::ABS_X, AAC::ABS_Y, AAC::ABS_PRESSURE],
+    );
+    assert_eq!(DeviceType::classify(&tablet), DeviceType::Tablet);
+}
+
+#[test]
+fn classifies_gamepad() {
+    let gamepad = caps(
+        &[KC::BTN_SOUTH, KC::BTN_EAST, KC::BTN_START],
+        &[],
+        &[AAC::ABS_X, AAC::ABS_Y, AAC::ABS_RX, AAC::ABS_RY],
+    );
+    assert_eq!(DeviceType::classify(&gamepad), DeviceType::Gamepad);
+}
+
+#[test]
+fn empty_device_is_other() {
+    assert_eq!(
+        DeviceType::classify(&DeviceCapabilities::default()),
+        DeviceType::Other
+    );
+}
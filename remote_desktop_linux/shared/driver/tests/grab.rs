@@ -0,0 +1,170 @@
+#![cfg(feature = "linux")]
+
+use std::fs::File;
+use std::io;
+use std::os::fd::AsFd;
+
+use evdev::{uinput::VirtualDevice, AttributeSet, Device};
+use kmf_driver::driver::{DeviceReader, DriverWriter, KeyCode};
+use kmf_driver::grab::InputGrab;
+
+#[test]
+fn holds_are_counted() {
+    let grab = InputGrab::default();
+    grab.acquire().unwrap();
+    grab.acquire().unwrap();
+    assert_eq!(grab.holds(), 2);
+
+    grab.release().unwrap();
+    assert!(grab.is_grabbed());
+    grab.release().unwrap();
+    assert!(!grab.is_grabbed());
+
+    assert!(grab.release().is_err());
+}
+
+#[test]
+fn guard_releases_on_panic() {
+    let grab = InputGrab::default();
+    let shared = grab.clone();
+    let result = std::panic::catch_unwind(move || {
+        let _guard = shared.hold().unwrap();
+        panic!("input loop crashed");
+    });
+
+    assert!(result.is_err());
+    assert!(!grab.is_grabbed());
+}
+
+#[test]
+fn failed_grab_takes_no_hold() {
+    // not an input device, EVIOCGRAB fails on it
+    let file = File::open("/dev/null").unwrap();
+    let grab = InputGrab::default();
+    let id = grab.attach(file.as_fd()).unwrap();
+
+    assert!(grab.acquire().is_err());
+    assert!(!grab.is_grabbed());
+
+    grab.detach(id);
+    assert!(grab.is_empty());
+    grab.acquire().unwrap();
+}
+
+#[test]
+fn writer_requires_attached_inputs() {
+    let Ok(writer) = DriverWriter::new(vec![KeyCode::KEY_A], Vec::new()) else {
+        return;
+    };
+    assert_eq!(
+        writer.block_inputs().unwrap_err().kind(),
+        io::ErrorKind::NotFound
+    );
+    assert!(writer.unblock_inputs().is_err());
+}
+
+/// Virtual keyboard and its event node opened a second time, `None` without uinput access.
+fn virtual_keyboard() -> Option<(VirtualDevice, Device, std::path::PathBuf)> {
+    let keys = [evdev::KeyCode::KEY_A]
+        .into_iter()
+        .collect::<AttributeSet<_>>();
+    let mut keyboard = VirtualDevice::builder()
+        .ok()?
+        .name("grab-test-keyboard")
+        .with_keys(&keys)
+        .ok()?
+        .build()
+        .ok()?;
+    let path = keyboard.enumerate_dev_nodes_blocking().ok()?.next()?.ok()?;
+    let device = Device::open(&path).ok()?;
+    Some((keyboard, device, path))
+}
+
+fn grabbed_elsewhere(path: &std::path::Path) -> bool {
+    let mut probe = Device::open(path).unwrap();
+    probe.grab().is_err()
+}
+
+#[test]
+fn writer_blocks_reader_devices() {
+    let Some((_keyboard, device, path)) = virtual_keyboard() else {
+        return;
+    };
+    let Ok(mut writer) = DriverWriter::new(vec![KeyCode::KEY_A], Vec::new()) else {
+        return;
+    };
+    let mut reader = DeviceReader::new(Vec::new()).unwrap();
+    reader.add_device(device, path.clone()).unwrap();
+    writer.attach_inputs(reader.input_grab());
+
+    writer.block_inputs().unwrap();
+    reader.grab_inputs().unwrap();
+    assert!(grabbed_elsewhere(&path));
+
+    // the reader letting go keeps the writer's block
+    reader.ungrab_inputs().unwrap();
+    assert!(grabbed_elsewhere(&path));
+
+    writer.unblock_inputs().unwrap();
+    assert!(!grabbed_elsewhere(&path));
+
+    writer.block_inputs().unwrap();
+    drop(writer);
+    assert!(!grabbed_elsewhere(&path));
+}
+
+#[test]
+fn dropping_reader_releases_grab() {
+    let Some((_keyboard, device, path)) = virtual_keyboard() else {
+        return;
+    };
+    let mut reader = DeviceReader::new(vec![device]).unwrap();
+    reader.grab_inputs().unwrap();
+    assert!(grabbed_elsewhere(&path));
+
+    drop(reader);
+    assert!(!grabbed_elsewhere(&path));
+}
+
+#[tokio::test]
+async fn grabbing_releases_held_keys_for_others() {
+    use evdev::{EventType, InputEvent};
+    use kmf_driver::driver::DriverEvent;
+
+    let Some((mut keyboard, device, path)) = virtual_keyboard() else {
+        return;
+    };
+    // stands in for the desktop, reads the device next to the stream
+    let mut desktop = Device::open(&path).unwrap();
+    let mut stream = DeviceReader::new(vec![device])
+        .unwrap()
+        .into_stream()
+        .unwrap();
+
+    let a = evdev::KeyCode::KEY_A.code();
+    keyboard
+        .emit(&[InputEvent::new(EventType::KEY.0, a, 1)])
+        .unwrap();
+    assert_eq!(
+        stream.next_frame().await,
+        vec![DriverEvent::keyboard_press(a, true)]
+    );
+
+    stream.grab_inputs().unwrap();
+    let seen = desktop
+        .fetch_events()
+        .unwrap()
+        .filter(|e| e.event_type() == EventType::KEY)
+        .map(|e| e.value())
+        .collect::<Vec<_>>();
+    assert_eq!(seen, vec![1, 0]);
+
+    // the written release and press are not read back, the physical release is
+    keyboard
+        .emit(&[InputEvent::new(EventType::KEY.0, a, 0)])
+        .unwrap();
+    assert_eq!(
+        stream.next_frame().await,
+        vec![DriverEvent::keyboard_press(a, false)]
+    );
+}
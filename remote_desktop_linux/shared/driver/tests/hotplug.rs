@@ -0,0 +1,58 @@
+#![cfg(feature = "linux")]
+
+use std::fs;
+use std::path::PathBuf;
+
+use kmf_driver::driver::DeviceReader;
+use kmf_driver::hotplug::{HotplugEvent, HotplugWatcher};
+
+fn scratch_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("kmf-hotplug-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(dir.join("by-id")).unwrap();
+    dir
+}
+
+#[test]
+fn reports_unplugged_device() {
+    let dir = scratch_dir("unplug");
+    let link = dir.join("by-id").join("usb-Mouse-event-mouse");
+    fs::write(&link, "").unwrap();
+
+    let mut reader = DeviceReader::new(Vec::new()).unwrap();
+    let mut watcher = HotplugWatcher::with_dirs(&dir, dir.join("by-id"), false).unwrap();
+    watcher.watch("mouse", link.clone());
+    assert!(watcher.poll(&mut reader).is_empty());
+
+    fs::remove_file(&link).unwrap();
+    assert_eq!(
+        watcher.poll(&mut reader),
+        vec![HotplugEvent::Disconnected {
+            label: "mouse".to_string(),
+            path: link.clone(),
+        }]
+    );
+    assert_eq!(watcher.disconnected().collect::<Vec<_>>(), vec!["mouse"]);
+
+    // not an input device, stays disconnected until it can be opened
+    fs::write(&link, "").unwrap();
+    assert!(watcher.poll(&mut reader).is_empty());
+    assert_eq!(watcher.disconnected().count(), 1);
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn ignores_unwatched_nodes() {
+    let dir = scratch_dir("unwatched");
+    let mut reader = DeviceReader::new(Vec::new()).unwrap();
+    let mut watcher = HotplugWatcher::with_dirs(&dir, dir.join("by-id"), true).unwrap();
+
+    fs::write(dir.join("event42"), "").unwrap();
+    fs::remove_file(dir.join("event42")).unwrap();
+    fs::write(dir.join("mouse0"), "").unwrap();
+    assert!(watcher.poll(&mut reader).is_empty());
+    assert_eq!(watcher.disconnected().count(), 0);
+
+    fs::remove_dir_all(&dir).unwrap();
+}
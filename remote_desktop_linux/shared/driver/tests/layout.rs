@@ -0,0 +1,199 @@
+use kmf_driver::driver::{DriverEvent, InputSink, RecordingSink};
+use kmf_driver::event::KeyboardPress;
+use kmf_driver::layout::{KeyTranslator, Keysym, Layout, LayoutSink, Level};
+
+const KEY_2: u16 = 3;
+const KEY_Y: u16 = 21;
+const KEY_Z: u16 = 44;
+const KEY_V: u16 = 47;
+const KEY_ENTER: u16 = 28;
+const KEY_LEFTSHIFT: u16 = 42;
+const KEY_RIGHTALT: u16 = 100;
+
+fn raw(key: u16, pressed: bool) -> DriverEvent {
+    DriverEvent::keyboard_press(key, pressed)
+}
+
+/// Key event as the master sends it, with the character its layout typed.
+fn typed(key: u16, pressed: bool, c: char) -> DriverEvent {
+    DriverEvent::KeyboardPress(KeyboardPress {
+        key,
+        pressed,
+        repeat: false,
+        keysym: Some(Keysym::from_char(c).0),
+    })
+}
+
+#[test]
+fn keysyms_follow_x11_unicode_mapping() {
+    assert_eq!(Keysym::from_char('a'), Keysym(0x61));
+    assert_eq!(Keysym::from_char('§'), Keysym(0xa7));
+    assert_eq!(Keysym::from_char('ľ'), Keysym(0x0100_013e));
+    assert_eq!(Keysym(0x0100_20ac).to_char(), Some('€'));
+    assert_eq!(Keysym(0x1f).to_char(), None);
+}
+
+#[test]
+fn tables_resolve_both_ways() {
+    let sk = Layout::fixture("SK").unwrap();
+    assert_eq!(sk.name(), "sk");
+    assert_eq!(sk.keysym(KEY_2, Level::Base), Some(Keysym::from_char('ľ')));
+    assert_eq!(
+        sk.keysym_held(KEY_2, true, false),
+        Some(Keysym::from_char('2'))
+    );
+    assert_eq!(
+        sk.keysym_held(KEY_V, false, true),
+        Some(Keysym::from_char('@'))
+    );
+    assert_eq!(sk.keysym(KEY_ENTER, Level::Base), None);
+
+    let us = Layout::fixture("us").unwrap();
+    assert_eq!(
+        us.locate(Keysym::from_char('z')),
+        Some((KEY_Z, Level::Base))
+    );
+    assert_eq!(
+        us.locate(Keysym::from_char('@')),
+        Some((KEY_2, Level::Shift))
+    );
+    assert_eq!(us.locate(Keysym::from_char('ľ')), None);
+
+    // variants have no offline table
+    assert!(Layout::fixture("sk(qwerty)").is_none());
+    assert!(Layout::fixture("dvorak").is_none());
+}
+
+/// Layout compiled from the system's xkb data, `None` without libxkbcommon.
+fn xkb(name: &str) -> Option<Layout> {
+    let layout = Layout::named(name);
+    if layout.is_none() && Layout::named("us").is_none() {
+        eprintln!("libxkbcommon or its xkb data is not available, skipping");
+    }
+    layout
+}
+
+#[test]
+fn xkb_keymaps_follow_the_variant() {
+    let Some(sk) = xkb("sk") else {
+        return;
+    };
+    let qwerty = xkb("SK(qwerty)").unwrap();
+    assert_eq!(qwerty.name(), "sk(qwerty)");
+    assert_eq!(sk.keysym(KEY_Y, Level::Base), Some(Keysym::from_char('z')));
+    assert_eq!(
+        qwerty.keysym(KEY_Y, Level::Base),
+        Some(Keysym::from_char('y'))
+    );
+    assert_eq!(
+        qwerty.keysym(KEY_2, Level::Base),
+        Some(Keysym::from_char('ľ'))
+    );
+    assert_eq!(sk.keysym(KEY_ENTER, Level::Base), None);
+
+    assert!(xkb("sk(nosuchvariant)").is_none());
+    assert!(xkb("nosuchlayout").is_none());
+    assert!(Layout::names().iter().any(|name| name == "de"));
+}
+
+#[test]
+fn xkb_keymaps_agree_with_the_fixtures() {
+    for name in ["us", "sk", "de"] {
+        let Some(layout) = xkb(name) else {
+            return;
+        };
+        let fixture = Layout::fixture(name).unwrap();
+        for code in 1..=86 {
+            for level in [Level::Base, Level::Shift, Level::AltGr] {
+                if let Some(keysym) = fixture.keysym(code, level) {
+                    assert_eq!(
+                        layout.keysym(code, level),
+                        Some(keysym),
+                        "{} key {} at {:?}",
+                        name,
+                        code,
+                        level
+                    );
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn reads_keyboard_config() {
+    assert!(Layout::from_keyboard_config("XKBMODEL=\"pc105\"").is_none());
+    let config = "XKBMODEL=\"pc105\"\nXKBLAYOUT=\"sk,us\"\nXKBVARIANT=\"qwerty,\"\n";
+    let Some(layout) = Layout::from_keyboard_config(config) else {
+        assert!(xkb("us").is_none());
+        return;
+    };
+    assert_eq!(layout.name(), "sk(qwerty)");
+}
+
+#[test]
+fn qwertz_letters_land_on_qwerty_keys() {
+    let mut translator = KeyTranslator::new(Layout::fixture("us"));
+    // Slovak 'z' sits where US has 'y'
+    assert_eq!(
+        translator.translate(&[typed(KEY_Y, true, 'z')]),
+        vec![raw(KEY_Z, true)]
+    );
+    assert_eq!(
+        translator.translate(&[DriverEvent::keyboard_repeat(KEY_Y)]),
+        vec![DriverEvent::keyboard_repeat(KEY_Z)]
+    );
+    assert_eq!(
+        translator.translate(&[typed(KEY_Y, false, 'z')]),
+        vec![raw(KEY_Z, false)]
+    );
+}
+
+#[test]
+fn shift_is_adjusted_for_the_local_level() {
+    let mut translator = KeyTranslator::new(Layout::fixture("us"));
+    // Slovak digits need Shift, US ones must be typed without it
+    assert_eq!(
+        translator.translate(&[raw(KEY_LEFTSHIFT, true)]),
+        vec![raw(KEY_LEFTSHIFT, true)]
+    );
+    assert_eq!(
+        translator.translate(&[typed(KEY_2, true, '2')]),
+        vec![raw(KEY_LEFTSHIFT, false), raw(KEY_2, true)]
+    );
+    assert_eq!(
+        translator.translate(&[typed(KEY_2, false, '2')]),
+        vec![raw(KEY_2, false), raw(KEY_LEFTSHIFT, true)]
+    );
+}
+
+#[test]
+fn altgr_characters_use_the_local_modifiers() {
+    let mut translator = KeyTranslator::new(Layout::fixture("us"));
+    translator.translate(&[raw(KEY_RIGHTALT, true)]);
+    assert_eq!(
+        translator.translate(&[typed(KEY_V, true, '@'), typed(KEY_V, false, '@')]),
+        vec![
+            raw(KEY_RIGHTALT, false),
+            raw(KEY_LEFTSHIFT, true),
+            raw(KEY_2, true),
+            raw(KEY_2, false),
+            raw(KEY_LEFTSHIFT, false),
+            raw(KEY_RIGHTALT, true),
+        ]
+    );
+}
+
+#[test]
+fn falls_back_to_raw_key_codes() {
+    let mut translator = KeyTranslator::new(Layout::fixture("us"));
+    // US cannot type 'ľ', the key code is replayed as is
+    assert_eq!(
+        translator.translate(&[typed(KEY_2, true, 'ľ'), raw(KEY_ENTER, true)]),
+        vec![raw(KEY_2, true), raw(KEY_ENTER, true)]
+    );
+
+    let mut sink = LayoutSink::new(RecordingSink::default(), None);
+    sink.simulate_frame(&[typed(KEY_Y, true, 'z')]).unwrap();
+    assert_eq!(sink.into_inner().events(), vec![raw(KEY_Y, true)]);
+}
@@ -0,0 +1,59 @@
+use kmf_driver::event::LockState;
+
+#[test]
+fn lock_keys_toggle() {
+    let mut locks = LockState::default();
+
+    assert!(locks.toggle(LockState::CAPS_LOCK_KEY));
+    assert!(locks.toggle(LockState::NUM_LOCK_KEY));
+    assert!(locks.toggle(LockState::NUM_LOCK_KEY));
+    // KEY_A
+    assert!(!locks.toggle(30));
+
+    assert_eq!(
+        locks,
+        LockState {
+            caps_lock: true,
+            num_lock: false,
+            scroll_lock: false,
+        }
+    );
+}
+
+#[test]
+fn leds_round_trip() {
+    let target = LockState {
+        caps_lock: false,
+        num_lock: true,
+        scroll_lock: true,
+    };
+
+    let mut locks = LockState::default();
+    for (led, on) in target.leds() {
+        locks.set_led(led, on);
+    }
+    // LED_MUTE is not a lock
+    locks.set_led(7, true);
+
+    assert_eq!(locks, target);
+}
+
+#[test]
+fn taps_only_differing_locks() {
+    let current = LockState {
+        caps_lock: true,
+        num_lock: true,
+        scroll_lock: false,
+    };
+    let target = LockState {
+        caps_lock: false,
+        num_lock: true,
+        scroll_lock: true,
+    };
+
+    assert_eq!(
+        current.keys_to_reach(target),
+        vec![LockState::CAPS_LOCK_KEY, LockState::SCROLL_LOCK_KEY]
+    );
+    assert!(target.keys_to_reach(target).is_empty());
+}
@@ -1,3 +1,5 @@
+#![cfg(feature = "linux")]
+
 #[cfg(test)]
 mod tests {
     use std::{io, thread::sleep, time::Duration};
@@ -168,4 +170,141 @@ mod tests {
 
         assert_eq!(res, mmove);
     }
+
+    #[test]
+    fn reader_key_repeat() {
+        use EventType as ET;
+        use KeyCode as KC;
+
+        let Ok((mut keyboard, _mouse, key_copy, _mouse_copy)) = setup() else {
+            return;
+        };
+
+        let mut reader = DriverReader::new(vec![key_copy]).unwrap();
+
+        let tk = ET::KEY.0;
+        let a = KC::KEY_A.code();
+
+        let press = InputEvent::new(tk, a, 1);
+        let repeat = InputEvent::new(tk, a, 2);
+        let release = InputEvent::new(tk, a, 0);
+
+        keyboard.emit(&[press]).unwrap();
+        keyboard.emit(&[repeat]).unwrap();
+        keyboard.emit(&[repeat]).unwrap();
+        keyboard.emit(&[release]).unwrap();
+
+        sleep(Duration::from_millis(10));
+
+        let es = reader.read_events().unwrap();
+
+        assert_eq!(
+            es,
+            vec![
+                DriverEvent::keyboard_press(a, true),
+                DriverEvent::keyboard_repeat(a),
+                DriverEvent::keyboard_repeat(a),
+                DriverEvent::keyboard_press(a, false),
+            ]
+        );
+    }
+
+    #[test]
+    fn reader_frames() {
+        use EventType as ET;
+        use KeyCode as KC;
+        use RelativeAxisCode as RAC;
+
+        let Ok((_keyboard, mut mouse, _key_copy, mouse_copy)) = setup() else {
+            return;
+        };
+
+        let mut reader = DriverReader::new(vec![mouse_copy]).unwrap();
+
+        let tk = ET::KEY.0;
+        let tr = ET::RELATIVE.0;
+
+        let diagonal = [
+            InputEvent::new(tr, RAC::REL_X.0, 3),
+            InputEvent::new(tr, RAC::REL_Y.0, -4),
+        ];
+        let drag = [
+            InputEvent::new(tk, KC::BTN_LEFT.code(), 1),
+            InputEvent::new(tr, RAC::REL_X.0, 7),
+        ];
+        // the click happens between the two moves and must stay there
+        let move_click_move = [
+            InputEvent::new(tr, RAC::REL_X.0, 2),
+            InputEvent::new(tr, RAC::REL_Y.0, 1),
+            InputEvent::new(tk, KC::BTN_LEFT.code(), 0),
+            InputEvent::new(tr, RAC::REL_X.0, 5),
+        ];
+
+        // every emit ends with its own SYN_REPORT
+        mouse.emit(&diagonal).unwrap();
+        mouse.emit(&drag).unwrap();
+        mouse.emit(&move_click_move).unwrap();
+
+        sleep(Duration::from_millis(10));
+
+        let frames = reader.read_frames().unwrap();
+
+        assert_eq!(
+            frames,
+            vec![
+                vec![DriverEvent::mouse_move(3, -4, 0)],
+                vec![
+                    DriverEvent::mouse_click(MouseButton::Left, true),
+                    DriverEvent::mouse_move(7, 0, 0),
+                ],
+                vec![
+                    DriverEvent::mouse_move(2, 1, 0),
+                    DriverEvent::mouse_click(MouseButton::Left, false),
+                    DriverEvent::mouse_move(5, 0, 0),
+                ],
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn stream_merges_devices() {
+        use futures::StreamExt;
+        use EventType as ET;
+        use KeyCode as KC;
+        use RelativeAxisCode as RAC;
+
+        let Ok((mut keyboard, mut mouse, key_copy, mouse_copy)) = setup() else {
+            return;
+        };
+
+        let mut stream = DriverReader::new(vec![key_copy, mouse_copy])
+            .unwrap()
+            .into_stream()
+            .unwrap();
+
+        keyboard
+            .emit(&[InputEvent::new(ET::KEY.0, KC::KEY_A.code(), 1)])
+            .unwrap();
+        mouse
+            .emit(&[
+                InputEvent::new(ET::RELATIVE.0, RAC::REL_X.0, 2),
+                InputEvent::new(ET::RELATIVE.0, RAC::REL_Y.0, 5),
+            ])
+            .unwrap();
+
+        let mut events = Vec::new();
+        for _ in 0..2 {
+            let next = tokio::time::timeout(Duration::from_secs(1), stream.next()).await;
+            events.push(next.unwrap().unwrap());
+        }
+        events.sort_by_key(|e| matches!(e, DriverEvent::MouseMove(_)));
+
+        assert_eq!(
+            events,
+            vec![
+                DriverEvent::keyboard_press(KC::KEY_A.code(), true),
+                DriverEvent::mouse_move(2, 5, 0),
+            ]
+        );
+    }
 }
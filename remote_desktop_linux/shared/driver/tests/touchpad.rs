@@ -0,0 +1,127 @@
+#![cfg(feature = "linux")]
+
+use std::time::{Duration, SystemTime};
+
+use evdev::{AbsoluteAxisCode as AAC, KeyCode as KC};
+use kmf_driver::event::{DriverEvent, MouseButton};
+use kmf_driver::touchpad::{TouchpadConfig, TouchpadTracker};
+
+fn at(ms: u64) -> SystemTime {
+    SystemTime::UNIX_EPOCH + Duration::from_millis(ms)
+}
+
+fn tracker() -> TouchpadTracker {
+    TouchpadTracker::new(TouchpadConfig {
+        motion_divisor: 2,
+        scroll_step: 10,
+        tap_timeout: Duration::from_millis(150),
+        tap_max_travel: 5,
+    })
+}
+
+fn touch_down(tp: &mut TouchpadTracker, tool: KC, x: i32, y: i32, ms: u64) -> Vec<DriverEvent> {
+    assert!(tp.on_key(KC::BTN_TOUCH, 1));
+    assert!(tp.on_key(tool, 1));
+    tp.on_abs(AAC::ABS_X, x);
+    tp.on_abs(AAC::ABS_Y, y);
+    tp.sync(at(ms))
+}
+
+fn move_to(tp: &mut TouchpadTracker, x: i32, y: i32, ms: u64) -> Vec<DriverEvent> {
+    tp.on_abs(AAC::ABS_X, x);
+    tp.on_abs(AAC::ABS_Y, y);
+    tp.sync(at(ms))
+}
+
+fn lift(tp: &mut TouchpadTracker, tool: KC, ms: u64) -> Vec<DriverEvent> {
+    assert!(tp.on_key(tool, 0));
+    assert!(tp.on_key(KC::BTN_TOUCH, 0));
+    tp.sync(at(ms))
+}
+
+#[test]
+fn one_finger_moves_pointer() {
+    let mut tp = tracker();
+
+    assert!(touch_down(&mut tp, KC::BTN_TOOL_FINGER, 100, 100, 0).is_empty());
+    assert_eq!(
+        move_to(&mut tp, 120, 90, 10),
+        vec![DriverEvent::mouse_move(10, -5, 0)]
+    );
+    // sub-unit remainders are carried over to the next frame
+    assert!(move_to(&mut tp, 121, 90, 20).is_empty());
+    assert_eq!(
+        move_to(&mut tp, 122, 90, 30),
+        vec![DriverEvent::mouse_move(1, 0, 0)]
+    );
+    // moved too far for a tap
+    assert!(lift(&mut tp, KC::BTN_TOOL_FINGER, 40).is_empty());
+}
+
+#[test]
+fn two_fingers_scroll() {
+    let mut tp = tracker();
+
+    touch_down(&mut tp, KC::BTN_TOOL_DOUBLETAP, 100, 100, 0);
+    assert!(move_to(&mut tp, 100, 95, 10).is_empty());
+    assert_eq!(
+        move_to(&mut tp, 100, 85, 20),
+        vec![DriverEvent::mouse_move(0, 0, 1)]
+    );
+    // the -5 left over from scrolling up is carried into the direction change
+    assert_eq!(
+        move_to(&mut tp, 100, 110, 30),
+        vec![DriverEvent::mouse_move(0, 0, -2)]
+    );
+}
+
+#[test]
+fn taps_click_by_finger_count() {
+    let cases = [
+        (KC::BTN_TOOL_FINGER, MouseButton::Left),
+        (KC::BTN_TOOL_DOUBLETAP, MouseButton::Right),
+        (KC::BTN_TOOL_TRIPLETAP, MouseButton::Middle),
+    ];
+
+    for (tool, button) in cases {
+        let mut tp = tracker();
+        touch_down(&mut tp, tool, 100, 100, 0);
+        assert_eq!(
+            lift(&mut tp, tool, 80),
+            vec![
+                DriverEvent::mouse_click(button, true),
+                DriverEvent::mouse_click(button, false),
+            ]
+        );
+    }
+}
+
+#[test]
+fn long_touch_is_not_a_tap() {
+    let mut tp = tracker();
+
+    touch_down(&mut tp, KC::BTN_TOOL_FINGER, 100, 100, 0);
+    assert!(lift(&mut tp, KC::BTN_TOOL_FINGER, 400).is_empty());
+}
+
+#[test]
+fn physical_click_is_forwarded_and_cancels_tap() {
+    let mut tp = tracker();
+
+    touch_down(&mut tp, KC::BTN_TOOL_FINGER, 100, 100, 0);
+    assert!(!tp.on_key(KC::BTN_LEFT, 1));
+    assert!(tp.sync(at(10)).is_empty());
+    assert!(!tp.on_key(KC::BTN_LEFT, 0));
+    assert!(lift(&mut tp, KC::BTN_TOOL_FINGER, 50).is_empty());
+}
+
+#[test]
+fn finger_count_change_does_not_jump() {
+    let mut tp = tracker();
+
+    touch_down(&mut tp, KC::BTN_TOOL_FINGER, 100, 100, 0);
+    // second finger lands, the emulated position follows it
+    assert!(tp.on_key(KC::BTN_TOOL_FINGER, 0));
+    assert!(tp.on_key(KC::BTN_TOOL_DOUBLETAP, 1));
+    assert!(move_to(&mut tp, 400, 300, 10).is_empty());
+}
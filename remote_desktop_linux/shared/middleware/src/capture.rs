@@ -0,0 +1,195 @@
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use tokio::sync::{broadcast, watch};
+use tokio::task::JoinHandle;
+use tracing::{error, info};
+
+use kmf_driver::driver::{DeviceReader, DriverWriter};
+use kmf_driver::hotplug::{HotplugEvent, HotplugWatcher, stable_path};
+use kmf_driver::layout::Layout;
+use kmf_protocol::config::ServerMessage;
+
+use crate::config::{MasterConfig, ScreenSize};
+use crate::driver_loop::{DriverLoopContext, EdgePolicy};
+use crate::hotkeys::Hotkeys;
+use crate::status::MasterStatus;
+
+/// How often the idle capture loop checks for stop requests and hotplug changes
+const HOUSEKEEPING_INTERVAL: Duration = Duration::from_millis(200);
+
+/// Opened input devices and how their input is split between this machine and the slaves
+pub struct Capture {
+    reader: DeviceReader,
+    writer: DriverWriter,
+    watcher: Option<HotplugWatcher>,
+    edge_policy: EdgePolicy,
+    /// Layout to send the typed characters in, `None` sends raw key codes
+    layout: Option<Layout>,
+    /// Screen size from the config, calibrated at startup when not given
+    screen: Option<ScreenSize>,
+}
+
+impl Capture {
+    /// Opens the devices of `config`, with `auto_attach` also those plugged in later.
+    pub fn open(config: &MasterConfig) -> Result<Self, String> {
+        let layout = config.send_layout()?;
+
+        let mut reader = DeviceReader::new(Vec::new())
+            .map_err(|e| format!("Failed to init DriverReader: {}", e))?;
+        let mut watcher = HotplugWatcher::new(config.auto_attach)
+            .map_err(|e| error!("Device hotplug disabled: {}", e))
+            .ok();
+
+        for (label, path) in [("keyboard", &config.keyboard), ("mouse", &config.mouse)] {
+            let Some(path) = path else {
+                continue;
+            };
+            // reopened by the stable path after replugging, the eventN number may change
+            let path = stable_path(&PathBuf::from(path));
+            let device = DeviceReader::open_path(path.clone(), false, true)
+                .map_err(|e| format!("Failed to open {} {}: {}", label, path.display(), e))?;
+            reader
+                .add_device(device, path.clone())
+                .map_err(|e| format!("Failed to add {}: {}", label, e))?;
+            if let Some(watcher) = &mut watcher {
+                watcher.watch(label, path);
+            }
+        }
+
+        let keys = reader.available_keys().unwrap_or_default();
+        let axes = reader.available_axes().unwrap_or_default();
+        let mut writer = DriverWriter::new(keys, axes)
+            .map_err(|e| format!("Failed to init DriverWriter: {}", e))?;
+        writer.attach_inputs(reader.input_grab());
+
+        Ok(Self {
+            reader,
+            writer,
+            watcher,
+            edge_policy: config.edge.policy(),
+            layout,
+            screen: config.screen,
+        })
+    }
+
+    /// Forwards captured input to this machine or the slaves while `running` is set.
+    ///
+    /// Focuses the screens sent on `screen`, the failsafe hotkey clears `running`.
+    /// `hotkeys` and `cursor_lock` are shared so they can be changed while running.
+    pub fn spawn(
+        self,
+        tx: broadcast::Sender<ServerMessage>,
+        status: Arc<Mutex<MasterStatus>>,
+        running: Arc<AtomicBool>,
+        hotkeys: Arc<Mutex<Hotkeys>>,
+        cursor_lock: Arc<AtomicBool>,
+        mut screen: watch::Receiver<usize>,
+    ) -> JoinHandle<()> {
+        let Self {
+            reader,
+            writer,
+            mut watcher,
+            edge_policy,
+            layout,
+            screen: screen_size,
+        } = self;
+        let (width, height) = {
+            let mut status = status.lock().expect("Failed to lock status");
+            status.reset();
+            match screen_size {
+                Some(size) => (size.width as i32, size.height as i32),
+                None => (status.master_width, status.master_height),
+            }
+        };
+        let mut ctx = DriverLoopContext::new(writer, tx, status, running, width, height);
+        ctx.hotkeys = hotkeys;
+        ctx.cursor_lock = cursor_lock;
+        ctx.edge_policy = edge_policy;
+        ctx.layout = layout;
+        if screen_size.is_some() {
+            ctx.skip_calibration();
+        }
+
+        tokio::spawn(async move {
+            let mut reader = match reader.into_stream() {
+                Ok(reader) => reader,
+                Err(e) => {
+                    error!("Failed to stream input devices: {}", e);
+                    ctx.running_flag.store(false, Ordering::SeqCst);
+                    ctx.status_mutex
+                        .lock()
+                        .expect("Failed to lock status")
+                        .running = false;
+                    return;
+                }
+            };
+            if ctx.calibration_mode {
+                info!("Calibration started: Move mouse to bottom-right and press 'c'.");
+            } else {
+                info!(
+                    "Screen size {}x{} from the config",
+                    ctx.master_width, ctx.master_height
+                );
+            }
+            // wakes the loop while idle to notice stop requests and plugged devices
+            let mut housekeeping = tokio::time::interval(HOUSEKEEPING_INTERVAL);
+
+            while ctx.running_flag.load(Ordering::SeqCst) {
+                tokio::select! {
+                    frame = reader.next_frame() => {
+                        ctx.handle_frame(&frame, &mut reader);
+                    }
+                    Ok(()) = screen.changed() => {
+                        let target = *screen.borrow_and_update();
+                        ctx.focus_screen(target, &mut reader);
+                    }
+                    _ = housekeeping.tick() => {}
+                }
+                if let Some(watcher) = &mut watcher {
+                    let events = watcher.poll(&mut reader);
+                    if !events.is_empty() {
+                        report_hotplug(&events, watcher, &ctx.status_mutex);
+                    }
+                }
+            }
+
+            if ctx.inputs_grabbed {
+                let _ = ctx.writer.unblock_inputs();
+            }
+            let mut status = ctx.status_mutex.lock().expect("Failed to lock status");
+            status.running = false;
+            status.remote_mode = false;
+        })
+    }
+}
+
+/// Logs device changes and lists the unplugged devices in the status.
+fn report_hotplug(
+    events: &[HotplugEvent],
+    watcher: &HotplugWatcher,
+    status_mutex: &Arc<Mutex<MasterStatus>>,
+) {
+    for event in events {
+        match event {
+            HotplugEvent::Disconnected { label, path } => {
+                info!("{} disconnected ({})", label, path.display());
+            }
+            HotplugEvent::Reconnected { label, path } => {
+                info!("{} reconnected ({})", label, path.display());
+            }
+            HotplugEvent::Attached {
+                path,
+                name,
+                device_type,
+            } => {
+                info!("Attached {:?} {} ({})", device_type, name, path.display());
+            }
+        }
+    }
+
+    let mut status = status_mutex.lock().expect("Failed to lock status");
+    status.disconnected_devices = watcher.disconnected().map(str::to_string).collect();
+}
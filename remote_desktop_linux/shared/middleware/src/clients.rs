@@ -0,0 +1,143 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::{broadcast, oneshot};
+use tracing::{Instrument, debug, error, info, info_span, trace, warn};
+
+use kmf_protocol::config::ServerMessage;
+use kmf_protocol::{AsyncStream, Packet};
+
+use crate::control::ClientEntry;
+use crate::file_transfer;
+use crate::metrics::metrics;
+
+/// Slaves that completed the handshake, in connection order
+pub type Clients = Arc<Mutex<Vec<ClientEntry>>>;
+
+/// Disconnects a client by its address
+pub type Stoppers = Arc<Mutex<HashMap<String, oneshot::Sender<()>>>>;
+
+/// Serves one connected slave: waits for its `ServerHello`, registers it in `clients`
+/// and forwards broadcast messages until it disconnects, a `Quit` is broadcast or
+/// `stop_rx` fires. Its entries in `clients` and `stoppers` are removed on exit.
+pub fn spawn_client_handler(
+    socket: Box<dyn AsyncStream>,
+    mut rx: broadcast::Receiver<ServerMessage>,
+    addr: String,
+    clients: Clients,
+    stoppers: Stoppers,
+    mut stop_rx: oneshot::Receiver<()>,
+) {
+    let span = info_span!("client", addr = %addr);
+    let task = async move {
+        let peer = metrics().peer(&addr);
+        let mut socket = peer.count(socket);
+        match kmf_protocol::receive(&mut socket).await {
+            Ok(Packet::ServerHello(config)) => {
+                info!(
+                    hostname = %config.hostname,
+                    width = config.screen_width,
+                    height = config.screen_height,
+                    "Client handshake"
+                );
+                clients
+                    .lock()
+                    .expect("Failed to lock clients")
+                    .push(ClientEntry {
+                        address: addr.clone(),
+                        hostname: config.hostname,
+                        screen_width: config.screen_width,
+                        screen_height: config.screen_height,
+                    });
+            }
+            Ok(other) => {
+                error!("Expected ServerHello, got {:?}", other.redacted());
+                stoppers
+                    .lock()
+                    .expect("Failed to lock stoppers")
+                    .remove(&addr);
+                return;
+            }
+            Err(e) => {
+                error!("Failed to receive ServerHello: {}", e);
+                stoppers
+                    .lock()
+                    .expect("Failed to lock stoppers")
+                    .remove(&addr);
+                return;
+            }
+        }
+
+        loop {
+            let message = tokio::select! {
+                _ = &mut stop_rx => {
+                    info!("Disconnecting on request");
+                    let _ = kmf_protocol::send(Packet::ClientQuit, &mut socket).await;
+                    break;
+                }
+                message = rx.recv() => message,
+            };
+            if message.is_ok() {
+                metrics().record_queue_depth(rx.len());
+            }
+            match message {
+                Ok(ServerMessage::Input(frame)) => {
+                    trace!("Forwarding input");
+                    let captured = frame.captured();
+                    if let Err(e) = kmf_protocol::send(Packet::Input(frame), &mut socket).await {
+                        error!("Failed to send input to client: {}", e);
+                        break;
+                    }
+
+                    // the slave acknowledges every frame once it is replayed
+                    match kmf_protocol::receive(&mut socket).await {
+                        Ok(Packet::Ok) => {
+                            trace!("Client acknowledged input");
+                            metrics().record_acknowledged(captured);
+                        }
+                        Ok(Packet::Err { code, message }) => {
+                            error!("Client error (code {}): {}", code, message);
+                        }
+                        Err(e) => {
+                            error!("Failed to receive ack: {}", e);
+                            break;
+                        }
+                        _ => {}
+                    }
+                }
+                Ok(ServerMessage::File { path }) => {
+                    debug!("Broadcasting file to client");
+                    match file_transfer::send_file(&mut socket, &path).await {
+                        Ok(_) => info!("File sent"),
+                        Err(e) => {
+                            error!("Failed to send file: {}", e);
+                            break;
+                        }
+                    }
+                }
+                Ok(ServerMessage::Quit) => {
+                    info!("Sending quit to client");
+                    let _ = kmf_protocol::send(Packet::ClientQuit, &mut socket).await;
+                    break;
+                }
+                Err(RecvError::Lagged(missed)) => {
+                    warn!("Client fell behind, {} message(s) skipped", missed);
+                    peer.record_lagged(missed);
+                }
+                Err(RecvError::Closed) => break,
+            }
+        }
+
+        clients
+            .lock()
+            .expect("Failed to lock clients")
+            .retain(|client| client.address != addr);
+        stoppers
+            .lock()
+            .expect("Failed to lock stoppers")
+            .remove(&addr);
+        info!("Client disconnected");
+    };
+    tokio::spawn(task.instrument(span));
+}
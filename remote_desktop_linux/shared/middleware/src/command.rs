@@ -1,23 +1,442 @@
-use base64::{Engine as _, engine::general_purpose};
-use kmf_protocol::SerializationMode;
-use kmf_protocol::config::ServerMessage;
-use kmf_protocol::serialization::{deserialize_bin, serialize_bin};
-use serde::{Deserialize, Serialize};
-use serde_json::Value;
+use std::fmt;
+use std::ops::Range;
 use std::path::Path;
 use std::str::FromStr;
 
-/// Generic action/event for protocol, decoupled from driver crate.
-#[derive(Debug, Serialize, Deserialize)]
-pub enum GenericAction {
-    MouseMove { x: i32, y: i32, wheel: i32 },
-    MouseClick { button: String, pressed: bool },
-    KeyPress { key: String, pressed: bool },
+use kmf_protocol::config::ServerMessage;
+use kmf_protocol::input::{Button, InputEvent, InputFrame};
+
+use crate::hotkeys::Chord;
+use crate::script::ScriptError;
+
+/// Byte range of the command line a token or error covers
+pub type Span = Range<usize>;
+
+/// Commands of the master console and its scripts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Commands {
+    /// Move mouse cursor
+    Move,
+    /// Click mouse button
+    Click,
+    /// Press keyboard key
+    Key,
+    /// Type a string
+    Type,
+    /// Wait before the next command
+    Sleep,
+    /// Run a block several times
+    Repeat,
+    /// Run a script file
+    Run,
+    /// Transfer file
+    File,
+    /// Disconnect all clients
+    Quit,
+    /// Show the commands
+    Help,
+}
+
+/// Name, arguments and description of a command, the one table parsing and help use
+#[derive(Debug)]
+pub struct CommandSpec {
+    pub command: Commands,
+    pub name: &'static str,
+    /// Arguments as the usage shows them
+    pub args: &'static str,
+    pub min_args: usize,
+    pub max_args: usize,
+    pub summary: &'static str,
+}
+
+pub const COMMANDS: &[CommandSpec] = &[
+    CommandSpec {
+        command: Commands::Move,
+        name: "move",
+        args: "<x> <y>",
+        min_args: 2,
+        max_args: 2,
+        summary: "Move the cursor by x, y",
+    },
+    CommandSpec {
+        command: Commands::Click,
+        name: "click",
+        args: "<left|right|middle> [down|up]",
+        min_args: 1,
+        max_args: 2,
+        summary: "Press or release a mouse button, both without down/up",
+    },
+    CommandSpec {
+        command: Commands::Key,
+        name: "key",
+        args: "<chord|code> [down|up]",
+        min_args: 1,
+        max_args: 2,
+        summary: "Press or release a key or chord like ctrl+shift+t, both without down/up",
+    },
+    CommandSpec {
+        command: Commands::Type,
+        name: "type",
+        args: "\"text\"",
+        min_args: 1,
+        max_args: 1,
+        summary: "Type a string with this machine's layout, \\n \\t \\\" \\\\ escapes",
+    },
+    CommandSpec {
+        command: Commands::Sleep,
+        name: "sleep",
+        args: "<duration>",
+        min_args: 1,
+        max_args: 1,
+        summary: "Wait, e.g. 200ms or 1.5s",
+    },
+    CommandSpec {
+        command: Commands::Repeat,
+        name: "repeat",
+        args: "<n> {",
+        min_args: 2,
+        max_args: 2,
+        summary: "Run the lines up to the closing } n times",
+    },
+    CommandSpec {
+        command: Commands::Run,
+        name: "run",
+        args: "<script>",
+        min_args: 1,
+        max_args: 1,
+        summary: "Run the commands of a script file",
+    },
+    CommandSpec {
+        command: Commands::File,
+        name: "file",
+        args: "<path>",
+        min_args: 1,
+        max_args: 1,
+        summary: "Send a file to all slaves",
+    },
+    CommandSpec {
+        command: Commands::Quit,
+        name: "quit",
+        args: "",
+        min_args: 0,
+        max_args: 0,
+        summary: "Disconnect all slaves and stop",
+    },
+    CommandSpec {
+        command: Commands::Help,
+        name: "help",
+        args: "[command]",
+        min_args: 0,
+        max_args: 1,
+        summary: "List the commands or show the usage of one",
+    },
+];
+
+impl Commands {
+    #[must_use]
+    pub fn spec(self) -> &'static CommandSpec {
+        COMMANDS
+            .iter()
+            .find(|spec| spec.command == self)
+            .expect("every command has a spec")
+    }
+
+    #[must_use]
+    pub fn name(self) -> &'static str {
+        self.spec().name
+    }
+}
+
+impl CommandSpec {
+    /// Usage line like `move <x> <y>`
+    #[must_use]
+    pub fn usage(&self) -> String {
+        if self.args.is_empty() {
+            self.name.to_string()
+        } else {
+            format!("{} {}", self.name, self.args)
+        }
+    }
+}
+
+impl FromStr for Commands {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        COMMANDS
+            .iter()
+            .find(|spec| spec.name == s)
+            .map(|spec| spec.command)
+            .ok_or(())
+    }
+}
+
+/// Why a command line could not be parsed, with the span of the offending part
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CommandError {
+    Empty,
+    UnknownCommand {
+        name: String,
+        span: Span,
+    },
+    WrongArity {
+        command: Commands,
+        found: usize,
+        span: Span,
+    },
+    InvalidNumber {
+        value: String,
+        span: Span,
+    },
+    UnknownButton {
+        value: String,
+        span: Span,
+    },
+    UnknownKey {
+        /// What the chord parser rejected
+        reason: String,
+        span: Span,
+    },
+    InvalidDirection {
+        value: String,
+        span: Span,
+    },
+    MissingFile {
+        path: String,
+        span: Span,
+    },
+    ExpectedString {
+        span: Span,
+    },
+    InvalidDuration {
+        value: String,
+        span: Span,
+    },
+    UnterminatedString {
+        span: Span,
+    },
+    UnknownEscape {
+        span: Span,
+    },
+    /// A `type` character the layout has no key for
+    Untypable {
+        character: char,
+        layout: String,
+        span: Span,
+    },
+    UnexpectedArgument {
+        value: String,
+        span: Span,
+    },
+    ExpectedBlock {
+        span: Span,
+    },
+    UnmatchedBrace {
+        span: Span,
+    },
+    /// A `repeat` block still waits for its closing `}`
+    UnclosedBlock {
+        span: Span,
+    },
+    NestedTooDeep {
+        span: Span,
+    },
+    ReadScript {
+        path: String,
+        reason: String,
+        span: Span,
+    },
+    InScript {
+        path: String,
+        error: Box<ScriptError>,
+        span: Span,
+    },
+    /// A command that cannot be turned into a single message, like `sleep`
+    NotAvailable {
+        command: Commands,
+        span: Span,
+    },
+}
+
+impl CommandError {
+    #[must_use]
+    pub fn span(&self) -> Span {
+        match self {
+            Self::Empty => 0..0,
+            Self::UnknownCommand { span, .. }
+            | Self::WrongArity { span, .. }
+            | Self::InvalidNumber { span, .. }
+            | Self::UnknownButton { span, .. }
+            | Self::UnknownKey { span, .. }
+            | Self::InvalidDirection { span, .. }
+            | Self::MissingFile { span, .. }
+            | Self::ExpectedString { span }
+            | Self::InvalidDuration { span, .. }
+            | Self::UnterminatedString { span }
+            | Self::UnknownEscape { span }
+            | Self::Untypable { span, .. }
+            | Self::UnexpectedArgument { span, .. }
+            | Self::ExpectedBlock { span }
+            | Self::UnmatchedBrace { span }
+            | Self::UnclosedBlock { span }
+            | Self::NestedTooDeep { span }
+            | Self::ReadScript { span, .. }
+            | Self::InScript { span, .. }
+            | Self::NotAvailable { span, .. } => span.clone(),
+        }
+    }
+
+    /// The error under `input` with the span marked, as the console prints it.
+    #[must_use]
+    pub fn report(&self, input: &str) -> String {
+        let span = self.span();
+        let start = input.get(..span.start).map_or(0, |s| s.chars().count());
+        let width = input.get(span).map_or(0, |s| s.chars().count()).max(1);
+        format!(
+            "{}\n{}{} {}",
+            input.trim_end(),
+            " ".repeat(start),
+            "^".repeat(width),
+            self
+        )
+    }
+}
+
+impl fmt::Display for CommandError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Empty => write!(f, "Empty command"),
+            Self::UnknownCommand { name, .. } => {
+                write!(f, "Unknown command '{}', see 'help'", name)
+            }
+            Self::WrongArity { command, found, .. } => {
+                let spec = command.spec();
+                let expected = if spec.min_args == spec.max_args {
+                    spec.min_args.to_string()
+                } else {
+                    format!("{} to {}", spec.min_args, spec.max_args)
+                };
+                write!(
+                    f,
+                    "'{}' takes {} argument(s), got {}, usage: {}",
+                    spec.name,
+                    expected,
+                    found,
+                    spec.usage()
+                )
+            }
+            Self::InvalidNumber { value, .. } => write!(f, "Expected a number, got '{}'", value),
+            Self::UnknownButton { value, .. } => write!(f, "Unknown mouse button '{}'", value),
+            Self::UnknownKey { reason, .. } => write!(f, "{}", reason),
+            Self::InvalidDirection { value, .. } => {
+                write!(f, "Expected 'down' or 'up', got '{}'", value)
+            }
+            Self::MissingFile { path, .. } => write!(f, "File does not exist: {}", path),
+            Self::ExpectedString { .. } => write!(f, "Expected a quoted string"),
+            Self::InvalidDuration { value, .. } => {
+                write!(f, "Expected a duration like 200ms or 2s, got '{}'", value)
+            }
+            Self::UnterminatedString { .. } => write!(f, "Unterminated string"),
+            Self::UnknownEscape { .. } => {
+                write!(f, "Unknown escape, expected \\n, \\t, \\\" or \\\\")
+            }
+            Self::Untypable {
+                character, layout, ..
+            } => write!(
+                f,
+                "'{}' cannot be typed with the {} layout",
+                character, layout
+            ),
+            Self::UnexpectedArgument { value, .. } => {
+                write!(f, "Unexpected argument '{}'", value)
+            }
+            Self::ExpectedBlock { .. } => write!(f, "Expected '{{' after the repeat count"),
+            Self::UnmatchedBrace { .. } => write!(f, "'}}' without an open 'repeat' block"),
+            Self::UnclosedBlock { .. } => {
+                write!(f, "'repeat' block is missing its closing '}}'")
+            }
+            Self::NestedTooDeep { .. } => write!(f, "Scripts are nested too deeply"),
+            Self::ReadScript { path, reason, .. } => {
+                write!(f, "Cannot read script {}: {}", path, reason)
+            }
+            Self::InScript { path, error, .. } => write!(f, "In {}:{}", path, error),
+            Self::NotAvailable { command, .. } => {
+                write!(f, "'{}' is not available here", command.name())
+            }
+        }
+    }
+}
+
+impl std::error::Error for CommandError {}
+
+/// Word or quoted string of a command line
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Token {
+    pub text: String,
+    pub span: Span,
+    pub quoted: bool,
+}
+
+/// Splits a line into words and quoted strings, dropping a trailing `#` comment.
+pub fn tokenize(input: &str) -> Result<Vec<Token>, CommandError> {
+    let mut tokens = Vec::new();
+    let mut chars = input.char_indices().peekable();
+    while let Some(&(start, c)) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c == '#' {
+            break;
+        } else if c == '"' {
+            chars.next();
+            let mut value = String::new();
+            let end = loop {
+                match chars.next() {
+                    Some((i, '"')) => break i + 1,
+                    Some((i, '\\')) => match chars.next() {
+                        Some((_, 'n')) => value.push('\n'),
+                        Some((_, 't')) => value.push('\t'),
+                        Some((_, c @ ('"' | '\\'))) => value.push(c),
+                        other => {
+                            let end = other.map_or(input.len(), |(j, c)| j + c.len_utf8());
+                            return Err(CommandError::UnknownEscape { span: i..end });
+                        }
+                    },
+                    Some((_, c)) => value.push(c),
+                    None => {
+                        return Err(CommandError::UnterminatedString {
+                            span: start..input.len(),
+                        });
+                    }
+                }
+            };
+            tokens.push(Token {
+                text: value,
+                span: start..end,
+                quoted: true,
+            });
+        } else {
+            let mut end = start;
+            while let Some(&(i, c)) = chars.peek() {
+                if c.is_whitespace() || c == '#' {
+                    break;
+                }
+                end = i + c.len_utf8();
+                chars.next();
+            }
+            tokens.push(Token {
+                text: input[start..end].to_string(),
+                span: start..end,
+                quoted: false,
+            });
+        }
+    }
+    Ok(tokens)
 }
 
 /// Parses a command string into a ServerMessage.
 ///
-/// This function validates command syntax and arguments, returning `None` for invalid input.
+/// Understands the commands of [`COMMANDS`] that make up a single message, the
+/// others (`sleep`, `repeat`, ...) need a [`crate::script::Script`].
 ///
 /// # Arguments
 ///
@@ -25,136 +444,191 @@ pub enum GenericAction {
 ///
 /// # Returns
 ///
-/// - `Some(ServerMessage)` if the command is valid
-/// - `None` if the command is invalid or has wrong number of arguments
-///   TODO: return error if functions fails
-pub fn parse_command(input: &str) -> Option<ServerMessage> {
-    let parts: Vec<&str> = input.split_whitespace().collect();
-    if parts.is_empty() {
-        return None;
-    }
-
-    let mode = SerializationMode::from_env();
+/// - `Ok(ServerMessage)` if the command is valid
+/// - `Err(CommandError)` pointing at what is wrong otherwise
+pub fn parse_command(input: &str) -> Result<ServerMessage, CommandError> {
+    let tokens = tokenize(input)?;
+    parse_tokens(&tokens, input.len())
+}
 
-    let Ok(command) = Commands::from_str(parts[0]) else {
-        return None;
+/// Parses a tokenized command, missing arguments are reported at `end`.
+pub fn parse_tokens(tokens: &[Token], end: usize) -> Result<ServerMessage, CommandError> {
+    let Some((command, args)) = tokens.split_first() else {
+        return Err(CommandError::Empty);
     };
+    let spec = parse_verb(command)?;
+    check_arity(spec, command, args, end)?;
 
-    match command {
-        Commands::Move if parts.len() == 3 => {
-            let x = parts[1].parse::<i32>().ok()?;
-            let y = parts[2].parse::<i32>().ok()?;
-            let action = GenericAction::MouseMove { x, y, wheel: 0 };
-            let value = serialize_action(mode, &action)?;
-            Some(ServerMessage::Action(value))
+    match spec.command {
+        Commands::Move => {
+            let x = parse_number(&args[0])?;
+            let y = parse_number(&args[1])?;
+            let event = InputEvent::MouseMove { x, y, wheel: 0 };
+            Ok(ServerMessage::Input(event.into()))
         }
-        Commands::Click if parts.len() == 3 => {
-            let button = parts[1].to_string();
-            let pressed = match detect_pressed_key(&parts) {
-                Ok(value) => value,
-                Err(value) => return value,
+        Commands::Click => {
+            let button =
+                args[0]
+                    .text
+                    .parse::<Button>()
+                    .map_err(|_| CommandError::UnknownButton {
+                        value: args[0].text.clone(),
+                        span: args[0].span.clone(),
+                    })?;
+            let press = |pressed| InputEvent::MouseButton { button, pressed };
+            let events = match parse_direction(args.get(1))? {
+                Some(pressed) => vec![press(pressed)],
+                None => vec![press(true), press(false)],
             };
-            let action = GenericAction::MouseClick { button, pressed };
-            let value = serialize_action(mode, &action)?;
-            Some(ServerMessage::Action(value))
+            Ok(ServerMessage::Input(InputFrame::new(events)))
         }
-        Commands::Key if parts.len() == 3 => {
-            let key = parts[1].to_string();
-            let pressed = match detect_pressed_key(&parts) {
-                Ok(value) => value,
-                Err(value) => return value,
-            };
-            let action = GenericAction::KeyPress { key, pressed };
-            let value = serialize_action(mode, &action)?;
-            Some(ServerMessage::Action(value))
+        Commands::Key => {
+            let (modifiers, key) = parse_keys(&args[0])?;
+            let direction = parse_direction(args.get(1))?;
+            let events = key_events(&modifiers, key, direction);
+            Ok(ServerMessage::Input(InputFrame::new(events)))
         }
-        Commands::File if parts.len() == 2 => {
-            let file_path = parts[1].to_string();
-            let path = Path::new(&file_path);
-            if !path.exists() {
-                eprintln!("File does not exist: {}", file_path);
-                return None;
+        Commands::File => {
+            let path = &args[0].text;
+            if !Path::new(path).exists() {
+                return Err(CommandError::MissingFile {
+                    path: path.clone(),
+                    span: args[0].span.clone(),
+                });
             }
-            Some(ServerMessage::File { path: file_path })
+            Ok(ServerMessage::File { path: path.clone() })
         }
-        Commands::Quit => Some(ServerMessage::Quit),
-        _ => None,
+        Commands::Quit => Ok(ServerMessage::Quit),
+        other => Err(CommandError::NotAvailable {
+            command: other,
+            span: command.span.clone(),
+        }),
     }
 }
 
-const DOWN_KEY: &str = "d";
-const UP_KEY: &str = "u";
+/// Looks up the command a line starts with.
+pub fn parse_verb(token: &Token) -> Result<&'static CommandSpec, CommandError> {
+    token
+        .text
+        .parse::<Commands>()
+        .map(Commands::spec)
+        .map_err(|()| CommandError::UnknownCommand {
+            name: token.text.clone(),
+            span: token.span.clone(),
+        })
+}
 
-fn detect_pressed_key(parts: &[&str]) -> Result<bool, Option<ServerMessage>> {
-    Ok(match parts[2] {
-        DOWN_KEY => true,
-        UP_KEY => false,
-        _ => return Err(None),
+/// Fails unless `args` has as many arguments as `spec` takes.
+pub fn check_arity(
+    spec: &CommandSpec,
+    command: &Token,
+    args: &[Token],
+    end: usize,
+) -> Result<(), CommandError> {
+    let span = if let Some(extra) = args.get(spec.max_args) {
+        extra.span.start..args.last().map_or(end, |t| t.span.end)
+    } else if args.len() < spec.min_args {
+        command.span.start..end
+    } else {
+        return Ok(());
+    };
+    Err(CommandError::WrongArity {
+        command: spec.command,
+        found: args.len(),
+        span,
     })
 }
 
-fn serialize_action<T: Serialize>(mode: SerializationMode, action: &T) -> Option<Value> {
-    Some(match mode {
-        SerializationMode::Json => serde_json::to_value(action).ok()?,
-        SerializationMode::Binary => {
-            // Use protocol's MessagePack serialization, then base64 encode for embedding in JSON
-            let bin = serialize_bin(action);
-            Value::String(general_purpose::STANDARD.encode(bin))
-        }
+/// `down` is a press, `up` a release, nothing both.
+pub fn parse_direction(token: Option<&Token>) -> Result<Option<bool>, CommandError> {
+    let Some(token) = token else {
+        return Ok(None);
+    };
+    match token.text.as_str() {
+        "down" | "d" => Ok(Some(true)),
+        "up" | "u" => Ok(Some(false)),
+        other => Err(CommandError::InvalidDirection {
+            value: other.to_string(),
+            span: token.span.clone(),
+        }),
+    }
+}
+
+pub fn parse_number(token: &Token) -> Result<i32, CommandError> {
+    token.text.parse().map_err(|_| CommandError::InvalidNumber {
+        value: token.text.clone(),
+        span: token.span.clone(),
     })
 }
 
-/// Deserializes a generic action/event from a serde_json::Value, supporting both JSON and base64-encoded MessagePack.
-pub fn deserialize_action<T: for<'de> Deserialize<'de>>(
-    mode: SerializationMode,
-    value: &Value,
-) -> Option<T> {
-    match mode {
-        SerializationMode::Json => serde_json::from_value(value.clone()).ok(),
-        SerializationMode::Binary => {
-            if let Value::String(s) = value {
-                let bin = general_purpose::STANDARD.decode(s).ok()?;
-                deserialize_bin(&bin).ok()
-            } else {
-                None
-            }
-        }
+/// Key code and the left keys of its modifiers, from a code like `30` or a chord like `ctrl+t`
+fn parse_keys(token: &Token) -> Result<(Vec<u16>, u16), CommandError> {
+    if let Ok(code) = token.text.parse::<u16>() {
+        return Ok((Vec::new(), code));
     }
+    let chord = token
+        .text
+        .parse::<Chord>()
+        .map_err(|reason| CommandError::UnknownKey {
+            reason,
+            span: token.span.clone(),
+        })?;
+    let modifiers = chord.modifiers().iter().map(|m| m.keys()[0]).collect();
+    Ok((modifiers, chord.key()))
 }
 
-/// Serializes a generic action/event and wraps it in ServerMessage::Action.
-pub fn send_action<T: Serialize>(action: &T) -> Option<ServerMessage> {
-    let mode = SerializationMode::from_env();
-    let value = serialize_action(mode, action);
-    // Send or throw
-    Some(ServerMessage::Action(value.unwrap()))
+pub(crate) fn key_event(code: u16, pressed: bool) -> InputEvent {
+    InputEvent::Key {
+        code,
+        pressed,
+        repeat: false,
+        keysym: None,
+    }
 }
 
-/// Command types recognized by the server input parser.
-pub enum Commands {
-    /// Move mouse cursor
-    Move,
-    /// Click mouse button
-    Click,
-    /// Press keyboard key
-    Key,
-    /// Transfer file
-    File,
-    /// Disconnect all clients
-    Quit,
+/// Presses modifiers then the key, releases in reverse; `None` does both.
+pub(crate) fn key_events(modifiers: &[u16], key: u16, direction: Option<bool>) -> Vec<InputEvent> {
+    let mut events = Vec::new();
+    if direction != Some(false) {
+        events.extend(modifiers.iter().map(|&m| key_event(m, true)));
+        events.push(key_event(key, true));
+    }
+    if direction != Some(true) {
+        events.push(key_event(key, false));
+        events.extend(modifiers.iter().rev().map(|&m| key_event(m, false)));
+    }
+    events
 }
 
-impl FromStr for Commands {
-    type Err = ();
-
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        match s {
-            "move" => Ok(Commands::Move),
-            "click" => Ok(Commands::Click),
-            "key" => Ok(Commands::Key),
-            "file" => Ok(Commands::File),
-            "quit" => Ok(Commands::Quit),
-            _ => Err(()),
-        }
+/// Text of the `help` command: all commands, or the usage of `topic`.
+pub fn help(topic: Option<&Token>) -> Result<String, CommandError> {
+    if let Some(topic) = topic {
+        let spec = parse_verb(topic)?;
+        return Ok(format!("{}\n    {}", spec.usage(), spec.summary));
     }
+    let width = COMMANDS
+        .iter()
+        .map(|spec| spec.usage().chars().count())
+        .max()
+        .unwrap_or_default();
+    let mut text = String::from("Commands:");
+    for spec in COMMANDS {
+        text.push_str(&format!(
+            "\n  {:width$}  {}",
+            spec.usage(),
+            spec.summary,
+            width = width
+        ));
+    }
+    Ok(text)
+}
+
+/// Command names for a short prompt, like `move | click | ... | help`
+#[must_use]
+pub fn command_names() -> String {
+    COMMANDS
+        .iter()
+        .map(|spec| spec.name)
+        .collect::<Vec<_>>()
+        .join(" | ")
 }
@@ -0,0 +1,321 @@
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use kmf_driver::layout::Layout;
+use kmf_protocol::{SerializationMode, TransportType};
+use serde::{Deserialize, Serialize};
+
+use crate::driver_loop::EdgePolicy;
+use crate::hotkeys::Modifier;
+use crate::logging::{LogFormat, LogOutput};
+
+/// File the settings are kept in, inside the user's config directory
+pub const CONFIG_FILE: &str = "kmf/config.toml";
+
+/// Version written to new files, files of a newer version are refused
+pub const CONFIG_VERSION: u32 = 1;
+
+/// Settings of the master, the slave and the wire protocol in one file.
+///
+/// Every key is optional, a missing one keeps its default.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub version: u32,
+    pub protocol: ProtocolConfig,
+    pub log: LogConfig,
+    pub master: MasterConfig,
+    pub slave: SlaveConfig,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LogConfig {
+    /// Level per module like `info,kmf_middleware::driver_loop=debug`, `KMF_LOG` overrides it
+    pub filter: String,
+    pub output: LogOutput,
+    pub format: LogFormat,
+    /// File for `output = "file"`, `$XDG_STATE_HOME/kmf/<binary>.log` when not given
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub file: Option<PathBuf>,
+    /// Logs key codes and typed characters, which gives away passwords
+    pub show_keys: bool,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ProtocolConfig {
+    /// Encoding of packet payloads, both sides have to agree on it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub serialization: Option<SerializationMode>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MasterConfig {
+    pub bind: String,
+    pub transport: TransportType,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mouse: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub keyboard: Option<String>,
+    /// Also capture keyboards and mice plugged in while running
+    pub auto_attach: bool,
+    /// Layout the typed characters are sent along in, `auto` detects it, none sends raw key codes
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub send_layout: Option<String>,
+    /// Size of this machine's screen, skips the calibration when given
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub screen: Option<ScreenSize>,
+    pub edge: EdgeConfig,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SlaveConfig {
+    /// Master to connect to
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub server: Option<String>,
+    pub transport: TransportType,
+    /// Layout received characters are typed with, detected when not given
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub layout: Option<String>,
+    /// Screen size announced to the master
+    pub screen: ScreenSize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ScreenSize {
+    pub width: u32,
+    pub height: u32,
+}
+
+/// [`EdgePolicy`] in milliseconds
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EdgeConfig {
+    pub dwell_ms: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub double_tap_ms: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub modifier: Option<Modifier>,
+    pub corner_size: i32,
+    pub button_guard: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            version: CONFIG_VERSION,
+            protocol: ProtocolConfig::default(),
+            log: LogConfig::default(),
+            master: MasterConfig::default(),
+            slave: SlaveConfig::default(),
+        }
+    }
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        Self {
+            filter: "info".to_string(),
+            output: LogOutput::Stderr,
+            format: LogFormat::Text,
+            file: None,
+            show_keys: false,
+        }
+    }
+}
+
+impl Default for MasterConfig {
+    fn default() -> Self {
+        Self {
+            bind: "0.0.0.0:8081".to_string(),
+            transport: TransportType::Tcp,
+            mouse: None,
+            keyboard: None,
+            auto_attach: false,
+            send_layout: None,
+            screen: None,
+            edge: EdgeConfig::default(),
+        }
+    }
+}
+
+impl Default for SlaveConfig {
+    fn default() -> Self {
+        Self {
+            server: None,
+            transport: TransportType::Tcp,
+            layout: None,
+            screen: ScreenSize {
+                width: 1920,
+                height: 1080,
+            },
+        }
+    }
+}
+
+impl EdgeConfig {
+    #[must_use]
+    pub fn policy(&self) -> EdgePolicy {
+        EdgePolicy {
+            dwell: Duration::from_millis(self.dwell_ms),
+            double_tap: self.double_tap_ms.map(Duration::from_millis),
+            modifier: self.modifier,
+            corner_size: self.corner_size,
+            button_guard: self.button_guard,
+        }
+    }
+}
+
+impl ProtocolConfig {
+    /// Uses the configured serialization, `PROTOCOL_SERIALIZATION` in the environment
+    /// still wins over it and it wins over a `.env` file.
+    pub fn apply(&self) {
+        if let Some(mode) = self.serialization {
+            SerializationMode::set_default(mode);
+        }
+    }
+}
+
+impl MasterConfig {
+    /// Layout to send typed characters in, `None` when raw key codes are sent.
+    pub fn send_layout(&self) -> Result<Option<Layout>, String> {
+        match self.send_layout.as_deref() {
+            None => Ok(None),
+            Some("auto") => Layout::detect()
+                .map(Some)
+                .ok_or_else(|| "Keyboard layout could not be detected".to_string()),
+            Some(name) => named_layout(name).map(Some),
+        }
+    }
+}
+
+impl SlaveConfig {
+    /// Configured layout, else the detected one, else none.
+    pub fn layout(&self) -> Result<Option<Layout>, String> {
+        match self.layout.as_deref() {
+            Some(name) => named_layout(name).map(Some),
+            None => Ok(Layout::detect()),
+        }
+    }
+}
+
+fn named_layout(name: &str) -> Result<Layout, String> {
+    Layout::named(name).ok_or_else(|| {
+        format!(
+            "Unknown keyboard layout '{}', expected an xkb layout like 'sk' or 'sk(qwerty)'",
+            name
+        )
+    })
+}
+
+impl Config {
+    /// `$XDG_CONFIG_HOME/kmf/config.toml`, `None` without a home directory.
+    #[must_use]
+    pub fn default_path() -> Option<PathBuf> {
+        dirs::config_dir().map(|dir| dir.join(CONFIG_FILE))
+    }
+
+    /// Reads and validates the config at `path`, a missing file gives the defaults.
+    pub fn load(path: &Path) -> Result<Self, String> {
+        match std::fs::read_to_string(path) {
+            Ok(text) => Self::from_toml(&text)
+                .map_err(|e| format!("Invalid config in {}: {}", path.display(), e)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(format!("Failed to read {}: {}", path.display(), e)),
+        }
+    }
+
+    /// Reads `path`, or the user's config file when not given.
+    pub fn load_or_default(path: Option<&Path>) -> Result<Self, String> {
+        match path.map(Path::to_path_buf).or_else(Self::default_path) {
+            Some(path) => Self::load(&path),
+            None => Ok(Self::default()),
+        }
+    }
+
+    /// Validates and writes the config to `path`.
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        self.validate()?;
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)
+                .map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+        }
+        std::fs::write(path, self.to_toml())
+            .map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+    }
+
+    pub fn from_toml(text: &str) -> Result<Self, String> {
+        let config: Self = toml::from_str(text).map_err(|e| e.message().to_string())?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    #[must_use]
+    pub fn to_toml(&self) -> String {
+        toml::to_string(self).expect("config serializes to TOML")
+    }
+
+    /// Checks what the types alone cannot, naming every offending key.
+    pub fn validate(&self) -> Result<(), String> {
+        let mut errors = Vec::new();
+        if self.version == 0 || self.version > CONFIG_VERSION {
+            errors.push(format!(
+                "version: {} is not supported, this build reads version {}",
+                self.version, CONFIG_VERSION
+            ));
+        }
+        if let Err(e) = tracing_subscriber::EnvFilter::try_new(&self.log.filter) {
+            errors.push(format!("log.filter: {}", e));
+        }
+        if let Err(e) = self.master.bind.parse::<SocketAddr>() {
+            errors.push(format!("master.bind: '{}' {}", self.master.bind, e));
+        }
+        if let Some(name) = self.master.send_layout.as_deref()
+            && name != "auto"
+            && let Err(e) = named_layout(name)
+        {
+            errors.push(format!("master.send_layout: {}", e));
+        }
+        if let Some(screen) = self.master.screen
+            && let Err(e) = screen.validate()
+        {
+            errors.push(format!("master.screen: {}", e));
+        }
+        if self.master.edge.corner_size < 0 {
+            errors.push("master.edge.corner_size: must not be negative".to_string());
+        }
+        if let Some(server) = self.slave.server.as_deref()
+            && server.trim().is_empty()
+        {
+            errors.push("slave.server: must not be empty".to_string());
+        }
+        if let Some(name) = self.slave.layout.as_deref()
+            && let Err(e) = named_layout(name)
+        {
+            errors.push(format!("slave.layout: {}", e));
+        }
+        if let Err(e) = self.slave.screen.validate() {
+            errors.push(format!("slave.screen: {}", e));
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors.join("; "))
+        }
+    }
+}
+
+impl ScreenSize {
+    fn validate(self) -> Result<(), String> {
+        if self.width == 0 || self.height == 0 {
+            return Err(format!("{}x{} has no area", self.width, self.height));
+        }
+        Ok(())
+    }
+}
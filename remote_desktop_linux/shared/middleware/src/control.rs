@@ -0,0 +1,269 @@
+use std::fs::{DirBuilder, Permissions};
+use std::io::{Error, ErrorKind, Result};
+use std::os::unix::fs::{DirBuilderExt, MetadataExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use nix::unistd::Uid;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{UnixListener, UnixStream};
+use tokio::task::JoinHandle;
+
+/// Methods of the master control socket
+pub const MASTER_METHODS: &[&str] = &[
+    "status",
+    "clients",
+    "send_file",
+    "switch_screen",
+    "lock_cursor",
+    "disconnect",
+    "stats",
+    "stop",
+];
+/// Methods of the slave control socket
+pub const SLAVE_METHODS: &[&str] = &["status", "stats", "stop"];
+
+/// Control request, one JSON object per line
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Request {
+    pub id: u64,
+    pub method: String,
+    #[serde(default, skip_serializing_if = "Value::is_null")]
+    pub params: Value,
+}
+
+/// Answer to the [`Request`] with the same id, carrying either a result or an error
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Response {
+    pub id: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub result: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl Response {
+    fn new(id: u64, outcome: std::result::Result<Value, String>) -> Self {
+        match outcome {
+            Ok(result) => Self {
+                id,
+                result: Some(result),
+                error: None,
+            },
+            Err(error) => Self {
+                id,
+                result: None,
+                error: Some(error),
+            },
+        }
+    }
+}
+
+/// Result of the master's `status` method
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MasterState {
+    pub bind: String,
+    pub transport: String,
+    pub daemon: bool,
+    pub uptime_secs: u64,
+    pub clients: usize,
+    /// Whether input devices are captured, without them only the console feeds the slaves
+    pub capturing: bool,
+    /// Waiting for the mouse to reach the bottom-right corner and `c`
+    pub calibrating: bool,
+    /// 0 is the master, 1 the slaves
+    pub active_screen: usize,
+    pub cursor_locked: bool,
+    /// Labels of captured devices which are currently unplugged
+    pub disconnected_devices: Vec<String>,
+}
+
+/// Entry of the master's `clients` method
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ClientEntry {
+    pub address: String,
+    pub hostname: String,
+    pub screen_width: u32,
+    pub screen_height: u32,
+}
+
+/// Result of the slave's `status` method
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SlaveState {
+    pub server: String,
+    pub connected: bool,
+    pub hostname: String,
+    pub layout: Option<String>,
+    pub uptime_secs: u64,
+    pub frames: u64,
+}
+
+/// `$XDG_RUNTIME_DIR/kmf/<role>.sock`. Without a runtime dir root uses `/run/kmf`
+/// and everyone else a `kmf-<uid>` directory in the temp dir.
+#[must_use]
+pub fn socket_path(role: &str) -> PathBuf {
+    let uid = Uid::effective();
+    let dir = match dirs::runtime_dir() {
+        Some(dir) => dir.join("kmf"),
+        None if uid.is_root() => PathBuf::from("/run/kmf"),
+        None => std::env::temp_dir().join(format!("kmf-{}", uid)),
+    };
+    dir.join(format!("{}.sock", role))
+}
+
+/// Creates `dir` accessible only to this user, an existing one has to be owned by them.
+fn private_dir(dir: &Path) -> Result<()> {
+    DirBuilder::new().recursive(true).mode(0o700).create(dir)?;
+    let owner = std::fs::symlink_metadata(dir)?.uid();
+    if owner != Uid::effective().as_raw() {
+        return Err(Error::new(
+            ErrorKind::PermissionDenied,
+            format!("{} is owned by another user (uid {})", dir.display(), owner),
+        ));
+    }
+    Ok(())
+}
+
+/// Handles one method call with its params
+pub type Handler = dyn Fn(&str, Value) -> std::result::Result<Value, String> + Send + Sync;
+
+/// Control socket accepting requests until dropped, which also removes the socket file
+#[derive(Debug)]
+pub struct ControlServer {
+    path: PathBuf,
+    task: JoinHandle<()>,
+}
+
+impl ControlServer {
+    /// Binds `path` and answers requests with `handler`.
+    ///
+    /// A socket file left behind by a crashed process is replaced, one another
+    /// process still answers on is an `AddrInUse` error. Only this user may connect,
+    /// the socket is made in a directory they own and is not accessible to others.
+    pub async fn bind(path: &Path, handler: Arc<Handler>) -> Result<Self> {
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            private_dir(dir)?;
+        }
+        if path.exists() {
+            if UnixStream::connect(path).await.is_ok() {
+                return Err(Error::new(
+                    ErrorKind::AddrInUse,
+                    format!("{} is served by another process", path.display()),
+                ));
+            }
+            std::fs::remove_file(path)?;
+        }
+        let listener = UnixListener::bind(path)?;
+        std::fs::set_permissions(path, Permissions::from_mode(0o600))?;
+
+        let task = tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                tokio::spawn(serve_connection(stream, handler.clone()));
+            }
+        });
+        Ok(Self {
+            path: path.to_path_buf(),
+            task,
+        })
+    }
+
+    #[must_use]
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for ControlServer {
+    fn drop(&mut self) {
+        self.task.abort();
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+async fn serve_connection(stream: UnixStream, handler: Arc<Handler>) {
+    let (read, mut write) = stream.into_split();
+    let mut lines = BufReader::new(read).lines();
+    while let Ok(Some(line)) = lines.next_line().await {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let response = match serde_json::from_str::<Request>(&line) {
+            Ok(request) => Response::new(request.id, handler(&request.method, request.params)),
+            Err(e) => Response::new(0, Err(format!("Invalid request: {}", e))),
+        };
+        let Ok(mut text) = serde_json::to_string(&response) else {
+            break;
+        };
+        text.push('\n');
+        if write.write_all(text.as_bytes()).await.is_err() {
+            break;
+        }
+    }
+}
+
+/// Calls `method` on the control socket at `path` and waits for its result.
+pub async fn call(path: &Path, method: &str, params: Value) -> std::result::Result<Value, String> {
+    let stream = UnixStream::connect(path)
+        .await
+        .map_err(|e| format!("Cannot connect to {}: {}", path.display(), e))?;
+    let (read, mut write) = stream.into_split();
+
+    let request = Request {
+        id: 1,
+        method: method.to_string(),
+        params,
+    };
+    let mut text = serde_json::to_string(&request).map_err(|e| e.to_string())?;
+    text.push('\n');
+    write
+        .write_all(text.as_bytes())
+        .await
+        .map_err(|e| format!("Failed to send request: {}", e))?;
+
+    let line = BufReader::new(read)
+        .lines()
+        .next_line()
+        .await
+        .map_err(|e| format!("Failed to read response: {}", e))?
+        .ok_or("Control socket closed without a response")?;
+    let response: Response =
+        serde_json::from_str(&line).map_err(|e| format!("Invalid response: {}", e))?;
+    match (response.result, response.error) {
+        (_, Some(error)) => Err(error),
+        (Some(result), None) => Ok(result),
+        (None, None) => Ok(Value::Null),
+    }
+}
+
+/// Unknown method error listing the `known` ones.
+#[must_use]
+pub fn unknown_method(method: &str, known: &[&str]) -> String {
+    format!(
+        "Unknown method '{}', known are: {}",
+        method,
+        known.join(", ")
+    )
+}
+
+/// Deserializes the params of a call, naming the method when they don't fit.
+pub fn parse_params<T: serde::de::DeserializeOwned>(
+    method: &str,
+    params: Value,
+) -> std::result::Result<T, String> {
+    serde_json::from_value(params).map_err(|e| format!("Invalid params for '{}': {}", method, e))
+}
+
+/// Resolves on SIGTERM or SIGINT, how systemd and a terminal ask to stop.
+pub async fn termination() {
+    let Ok(mut term) = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+    else {
+        let _ = tokio::signal::ctrl_c().await;
+        return;
+    };
+    tokio::select! {
+        _ = term.recv() => {}
+        _ = tokio::signal::ctrl_c() => {}
+    }
+}
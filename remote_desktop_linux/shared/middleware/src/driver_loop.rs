@@ -0,0 +1,634 @@
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+use tokio::sync::broadcast;
+use tracing::{error, info, warn};
+
+use kmf_driver::driver::{DriverEvent, EventFrame, InputSink, InputSource, MouseMove};
+use kmf_driver::event::{KeyboardPress, LockState, MouseButton, MouseClick};
+use kmf_driver::layout::{ALTGR_KEY, Layout};
+use kmf_protocol::config::ServerMessage;
+
+use crate::event::input_frame;
+use crate::hotkeys::{HotkeyAction, Hotkeys, Modifier};
+use crate::metrics::metrics;
+use crate::status::MasterStatus;
+
+/// KEY_LEFTCTRL, KEY_LEFTALT, KEY_DELETE
+const CTRL_ALT_DEL: [u16; 3] = [29, 56, 111];
+
+/// Left/right Ctrl, Shift, Alt and Meta
+const MODIFIER_KEYS: [u16; 8] = [29, 97, 42, 54, 56, 100, 125, 126];
+
+/// Distance the cursor has to move with a button held to start a drag
+const DRAG_THRESHOLD: i32 = 16;
+
+/// Which held inputs follow the focus to the other machine.
+/// Everything held is always released on the machine losing the focus.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HeldInputPolicy {
+    /// Ctrl/Shift/Alt/Meta, so e.g. a Shift+click selection can continue
+    pub modifiers: bool,
+    /// Other keys, which would start autorepeating on the new machine
+    pub keys: bool,
+    /// Mouse buttons, which would start a drag on the new machine
+    pub buttons: bool,
+}
+
+impl Default for HeldInputPolicy {
+    fn default() -> Self {
+        Self {
+            modifiers: true,
+            keys: false,
+            buttons: false,
+        }
+    }
+}
+
+/// When pushing the cursor against the edge between master and slaves switches the focus.
+/// Checks that are set must all pass, the default switches right away.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct EdgePolicy {
+    /// How long the cursor has to keep pushing against the edge
+    pub dwell: Duration,
+    /// Cross only on a second push against the edge started within this time of the first
+    pub double_tap: Option<Duration>,
+    /// Cross only while this modifier is held
+    pub modifier: Option<Modifier>,
+    /// Height at the top and bottom of the edge where it cannot be crossed,
+    /// keeps scrollbars and close buttons in the corners reachable
+    pub corner_size: i32,
+    /// No crossing with a mouse button held, unless it drags something
+    /// from away from the edge
+    pub button_guard: bool,
+}
+
+pub struct DriverLoopContext {
+    pub cursor_x: i32,
+    pub cursor_y: i32,
+    pub remote_mode: bool,
+    pub calibration_mode: bool,
+    pub master_width: i32,
+    pub master_height: i32,
+    /// Whether `writer` blocks the physical inputs, released with it on drop
+    pub inputs_grabbed: bool,
+    pub pressed_keys: HashSet<u16>,
+    pub pressed_buttons: HashSet<MouseButton>,
+    pub held_policy: HeldInputPolicy,
+    pub edge_policy: EdgePolicy,
+    /// Chords handled here instead of being forwarded, shared so edits apply while running
+    pub hotkeys: Arc<Mutex<Hotkeys>>,
+    /// Keeps the cursor on the current screen, edges are not crossed.
+    /// Shared so the GUI can toggle it while running
+    pub cursor_lock: Arc<AtomicBool>,
+    /// Layout of this machine, set to send the typed characters along with key codes
+    pub layout: Option<Layout>,
+    pub writer: Box<dyn InputSink + Send>,
+    pub tx: broadcast::Sender<ServerMessage>,
+    pub status_mutex: Arc<Mutex<MasterStatus>>,
+    pub running_flag: Arc<AtomicBool>,
+    /// Events of the frame being processed, sent to slaves as one batch
+    outgoing: EventFrame,
+    /// Events of the frame being processed, replayed locally as one batch
+    local: EventFrame,
+    /// Lock state of this machine, saved while a slave has focus
+    local_locks: LockState,
+    /// Lock state of the slaves, taken over from this machine on first switch-in
+    remote_locks: Option<LockState>,
+    /// Held keys released on a focus switch without being pressed on the new machine,
+    /// their repeats and release are dropped
+    suppressed_keys: HashSet<u16>,
+    suppressed_buttons: HashSet<MouseButton>,
+    /// Held inputs the writer pressed when the focus came back, it has to release them
+    /// as well since the desktop never saw them pressed on the devices
+    carried_keys: HashSet<u16>,
+    carried_buttons: HashSet<MouseButton>,
+    /// Keys that completed a hotkey, dropped until released so they reach no machine
+    consumed_keys: HashSet<u16>,
+    /// Start of the current push against the edge
+    edge_push: Option<Instant>,
+    /// Start of the previous push, for [`EdgePolicy::double_tap`]
+    last_edge_push: Option<Instant>,
+    /// Whether the current push is the second one of a double tap
+    double_tapped: bool,
+    /// Cursor position when the first of the held buttons was pressed
+    drag_origin: Option<(i32, i32)>,
+    /// Whether the cursor moved past [`DRAG_THRESHOLD`] since `drag_origin`
+    dragging: bool,
+}
+
+impl Default for DriverLoopContext {
+    fn default() -> Self {
+        panic!("DriverLoopContext cannot be default-constructed without arguments");
+    }
+}
+
+impl DriverLoopContext {
+    pub fn new(
+        writer: impl InputSink + Send + 'static,
+        tx: broadcast::Sender<ServerMessage>,
+        status_mutex: Arc<Mutex<MasterStatus>>,
+        running_flag: Arc<AtomicBool>,
+        width: i32,
+        height: i32,
+    ) -> Self {
+        Self {
+            cursor_x: 0,
+            cursor_y: 0,
+            remote_mode: false,
+            calibration_mode: true,
+            master_width: width,
+            master_height: height,
+            inputs_grabbed: false,
+            pressed_keys: HashSet::new(),
+            pressed_buttons: HashSet::new(),
+            held_policy: HeldInputPolicy::default(),
+            edge_policy: EdgePolicy::default(),
+            hotkeys: Arc::new(Mutex::new(Hotkeys::default())),
+            cursor_lock: Arc::new(AtomicBool::new(false)),
+            layout: None,
+            writer: Box::new(writer),
+            tx,
+            status_mutex,
+            running_flag,
+            outgoing: EventFrame::new(),
+            local: EventFrame::new(),
+            local_locks: LockState::default(),
+            remote_locks: None,
+            suppressed_keys: HashSet::new(),
+            suppressed_buttons: HashSet::new(),
+            carried_keys: HashSet::new(),
+            carried_buttons: HashSet::new(),
+            consumed_keys: HashSet::new(),
+            edge_push: None,
+            last_edge_push: None,
+            double_tapped: false,
+            drag_origin: None,
+            dragging: false,
+        }
+    }
+
+    /// Takes the size given to [`Self::new`] as this machine's screen instead of calibrating.
+    pub fn skip_calibration(&mut self) {
+        self.calibration_mode = false;
+        self.update_status();
+    }
+
+    /// Processes one `SYN_REPORT` frame and forwards it as a single batch.
+    /// Returns `false` if the loop should terminate.
+    pub fn handle_frame(&mut self, frame: &[DriverEvent], reader: &mut dyn InputSource) -> bool {
+        let captured = SystemTime::now();
+        for event in frame {
+            if !self.process_event(*event, reader) {
+                return false;
+            }
+        }
+        self.flush_frame(captured);
+        true
+    }
+
+    /// Processes a single event. Returns `false` if the loop should terminate.
+    pub fn handle_event(&mut self, event: DriverEvent, reader: &mut dyn InputSource) -> bool {
+        self.handle_frame(&[event], reader)
+    }
+
+    fn process_event(&mut self, event: DriverEvent, reader: &mut dyn InputSource) -> bool {
+        if let DriverEvent::KeyboardPress(kp) = &event {
+            if self.consumed_keys.contains(&kp.key) {
+                if !kp.pressed {
+                    self.consumed_keys.remove(&kp.key);
+                }
+                return true;
+            }
+
+            if kp.pressed && !kp.repeat {
+                let action = self
+                    .hotkeys
+                    .lock()
+                    .expect("Failed to lock hotkeys")
+                    .match_press(kp.key, &self.pressed_keys);
+                if let Some(action) = action {
+                    self.consumed_keys.insert(kp.key);
+                    return self.run_hotkey(action, reader);
+                }
+            }
+
+            if kp.pressed {
+                self.pressed_keys.insert(kp.key);
+            } else {
+                self.pressed_keys.remove(&kp.key);
+            }
+        }
+
+        if let DriverEvent::MouseClick(mc) = &event {
+            if mc.pressed {
+                if self.pressed_buttons.is_empty() {
+                    self.drag_origin = Some((self.cursor_x, self.cursor_y));
+                }
+                self.pressed_buttons.insert(mc.button);
+            } else {
+                self.pressed_buttons.remove(&mc.button);
+                if self.pressed_buttons.is_empty() {
+                    self.drag_origin = None;
+                    self.dragging = false;
+                }
+            }
+        }
+
+        if self.is_suppressed(&event) {
+            return true;
+        }
+
+        match event {
+            DriverEvent::MouseMove(mm) => self.handle_mouse_move(mm, reader),
+            DriverEvent::MouseClick(mc) => self.handle_mouse_click(mc),
+            DriverEvent::KeyboardPress(kp) => self.handle_key_press(kp, reader),
+            // only sent to slaves, never read from devices
+            DriverEvent::LockState(_) => {}
+        }
+        true
+    }
+
+    fn handle_mouse_move(&mut self, mm: MouseMove, reader: &mut dyn InputSource) {
+        if self.calibration_mode {
+            self.cursor_x = (self.cursor_x + mm.x).max(0);
+            self.cursor_y = (self.cursor_y + mm.y).max(0);
+            self.update_status();
+            return;
+        }
+
+        let total_width = self.master_width * 2;
+        self.cursor_x = (self.cursor_x + mm.x).clamp(0, total_width - 1);
+        self.cursor_y = (self.cursor_y + mm.y).clamp(0, self.master_height - 1);
+        self.track_drag();
+
+        // Determine if we crossed the boundary to the slave
+        let new_remote_mode = self.cursor_x >= self.master_width;
+
+        if new_remote_mode == self.remote_mode {
+            self.track_edge_push();
+        } else if self.may_cross() {
+            self.edge_push = None;
+            self.last_edge_push = None;
+            self.switch_mode(new_remote_mode, reader);
+        } else {
+            // stay on the current screen, pushing against its edge
+            let screen_start = if self.remote_mode {
+                self.master_width
+            } else {
+                0
+            };
+            self.cursor_x = self
+                .cursor_x
+                .clamp(screen_start, screen_start + self.master_width - 1);
+        }
+
+        self.update_status();
+
+        if self.remote_mode {
+            self.outgoing.push(DriverEvent::MouseMove(mm));
+        } else if self.inputs_grabbed {
+            // Replay event locally only if we have grabbed inputs
+            self.local.push(DriverEvent::MouseMove(mm));
+        }
+    }
+
+    /// Ends the current push against the edge once the cursor leaves it.
+    fn track_edge_push(&mut self) {
+        let edge_x = if self.remote_mode {
+            self.master_width
+        } else {
+            self.master_width - 1
+        };
+        if self.cursor_x != edge_x
+            && let Some(start) = self.edge_push.take()
+        {
+            self.last_edge_push = Some(start);
+        }
+    }
+
+    /// Starts a drag once a held button moved the cursor far enough. Pushing past the edge
+    /// does not count, a click held at the edge stays a click.
+    fn track_drag(&mut self) {
+        let Some((x, y)) = self.drag_origin else {
+            return;
+        };
+        let screen_start = if self.remote_mode {
+            self.master_width
+        } else {
+            0
+        };
+        let cursor_x = self
+            .cursor_x
+            .clamp(screen_start, screen_start + self.master_width - 1);
+        if (cursor_x - x).abs() + (self.cursor_y - y).abs() >= DRAG_THRESHOLD {
+            self.dragging = true;
+        }
+    }
+
+    /// Whether the cursor pushing against the edge may move to the other side.
+    fn may_cross(&mut self) -> bool {
+        let policy = self.edge_policy;
+        if self.cursor_locked()
+            || (policy.button_guard && !self.pressed_buttons.is_empty() && !self.dragging)
+            || self.cursor_y < policy.corner_size
+            || self.cursor_y >= self.master_height - policy.corner_size
+            || policy
+                .modifier
+                .is_some_and(|m| !m.is_held(&self.pressed_keys))
+        {
+            return false;
+        }
+
+        let now = Instant::now();
+        let start = match self.edge_push {
+            Some(start) => start,
+            None => {
+                self.double_tapped = match (self.last_edge_push, policy.double_tap) {
+                    (Some(last), Some(window)) => now.duration_since(last) <= window,
+                    _ => false,
+                };
+                *self.edge_push.insert(now)
+            }
+        };
+
+        (policy.double_tap.is_none() || self.double_tapped)
+            && now.duration_since(start) >= policy.dwell
+    }
+
+    pub fn cursor_locked(&self) -> bool {
+        self.cursor_lock.load(Ordering::SeqCst)
+    }
+
+    /// Runs the action of a pressed hotkey. Returns `false` if the loop should terminate.
+    fn run_hotkey(&mut self, action: HotkeyAction, reader: &mut dyn InputSource) -> bool {
+        info!(%action, "Hotkey");
+        match action {
+            HotkeyAction::SwitchScreen(screen) => self.switch_screen(screen, reader),
+            HotkeyAction::ToggleRemote => {
+                self.switch_screen(usize::from(!self.remote_mode), reader)
+            }
+            HotkeyAction::LockCursor => {
+                self.cursor_lock.fetch_xor(true, Ordering::SeqCst);
+                self.update_status();
+            }
+            HotkeyAction::SendCtrlAltDel => {
+                for key in CTRL_ALT_DEL {
+                    self.send_to(true, DriverEvent::keyboard_press(key, true));
+                }
+                for key in CTRL_ALT_DEL.into_iter().rev() {
+                    self.send_to(true, DriverEvent::keyboard_press(key, false));
+                }
+            }
+            HotkeyAction::StopMaster => {
+                warn!("Failsafe hotkey, stopping the master");
+                self.running_flag.store(false, Ordering::SeqCst);
+                return false;
+            }
+        }
+        true
+    }
+
+    /// Moves the focus to a screen outside of the input stream, e.g. on a control request.
+    pub fn focus_screen(&mut self, screen: usize, reader: &mut dyn InputSource) {
+        self.switch_screen(screen, reader);
+        self.flush_frame(SystemTime::now());
+    }
+
+    /// Moves the cursor to the middle of a screen, 0 is this machine and 1 the slaves.
+    fn switch_screen(&mut self, screen: usize, reader: &mut dyn InputSource) {
+        if self.calibration_mode {
+            return;
+        }
+        if screen > 1 {
+            warn!("No screen {}", screen);
+            return;
+        }
+
+        let remote = screen == 1;
+        self.cursor_x = self.master_width / 2 + if remote { self.master_width } else { 0 };
+        self.cursor_y = self.master_height / 2;
+        if remote != self.remote_mode {
+            self.switch_mode(remote, reader);
+        }
+        self.update_status();
+    }
+
+    /// Drops repeats and the release of inputs a focus switch already released.
+    fn is_suppressed(&mut self, event: &DriverEvent) -> bool {
+        match event {
+            DriverEvent::KeyboardPress(kp) if self.suppressed_keys.contains(&kp.key) => {
+                if !kp.pressed {
+                    self.suppressed_keys.remove(&kp.key);
+                }
+                true
+            }
+            DriverEvent::MouseClick(mc) if self.suppressed_buttons.contains(&mc.button) => {
+                if !mc.pressed {
+                    self.suppressed_buttons.remove(&mc.button);
+                }
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Key event for the slaves, with the typed character if layouts are translated.
+    fn remote_key(&self, kp: KeyboardPress) -> DriverEvent {
+        let keysym = self.layout.as_ref().and_then(|layout| {
+            let shift = Modifier::Shift.is_held(&self.pressed_keys);
+            let altgr = self.pressed_keys.contains(&ALTGR_KEY);
+            layout.keysym_held(kp.key, shift, altgr)
+        });
+        DriverEvent::KeyboardPress(KeyboardPress {
+            keysym: keysym.map(|sym| sym.0),
+            ..kp
+        })
+    }
+
+    fn handle_mouse_click(&mut self, mc: MouseClick) {
+        if self.remote_mode {
+            self.outgoing.push(DriverEvent::MouseClick(mc));
+        } else if (!mc.pressed && self.carried_buttons.remove(&mc.button)) || self.inputs_grabbed {
+            self.local.push(DriverEvent::MouseClick(mc));
+        }
+    }
+
+    fn handle_key_press(
+        &mut self,
+        kp: kmf_driver::event::KeyboardPress,
+        reader: &mut dyn InputSource,
+    ) {
+        // Calibration confirmation
+        if self.calibration_mode && kp.key == 46 && kp.pressed {
+            // 'c' key
+            self.master_width = (self.cursor_x + 1).max(1);
+            self.master_height = (self.cursor_y + 1).max(1);
+            self.calibration_mode = false;
+            self.remote_mode = false;
+            info!(
+                width = self.master_width,
+                height = self.master_height,
+                "Calibrated"
+            );
+            self.update_status();
+            return;
+        }
+
+        if self.remote_mode {
+            let event = self.remote_key(kp);
+            self.outgoing.push(event);
+            // grabbed keys never reach this machine, so its LEDs have to follow the slave by hand
+            if kp.pressed
+                && !kp.repeat
+                && let Some(locks) = &mut self.remote_locks
+                && locks.toggle(kp.key)
+            {
+                let _ = reader.set_lock_leds(*locks);
+            }
+        } else if (!kp.pressed && self.carried_keys.remove(&kp.key)) || self.inputs_grabbed {
+            self.local.push(DriverEvent::KeyboardPress(kp));
+        }
+    }
+
+    /// Moves the lock state with the focus: shows the focused machine's locks on the
+    /// physical keyboard and brings the slave's virtual keyboard in line on switch-in.
+    fn sync_locks(&mut self, reader: &mut dyn InputSource) {
+        let shown = if self.remote_mode {
+            self.local_locks = reader.lock_state().unwrap_or(self.local_locks);
+            let remote = *self.remote_locks.get_or_insert(self.local_locks);
+            self.outgoing.push(DriverEvent::LockState(remote));
+            remote
+        } else {
+            self.local_locks
+        };
+
+        if let Err(e) = reader.set_lock_leds(shown) {
+            warn!("Failed to set keyboard LEDs: {}", e);
+        }
+    }
+
+    /// Replays a synthesized event on one side, regardless of the current mode.
+    fn send_to(&mut self, remote: bool, event: DriverEvent) {
+        if remote {
+            match event {
+                DriverEvent::MouseClick(_) => self.outgoing.push(event),
+                DriverEvent::KeyboardPress(kp) => {
+                    let event = self.remote_key(kp);
+                    self.outgoing.push(event);
+                }
+                _ => {}
+            }
+        } else {
+            self.local.push(event);
+        }
+    }
+
+    /// Releases everything held on the side losing the focus and presses what the
+    /// policy carries over on the side getting it, so nothing stays stuck.
+    ///
+    /// Inputs the desktop got straight from the devices are released by grabbing
+    /// them, see [`InputSource::grab_with`]. The kernel drops releases the writer
+    /// sends for keys it never pressed itself.
+    fn transfer_held_inputs(&mut self, from_remote: bool) {
+        let mut keys = self.pressed_keys.iter().copied().collect::<Vec<_>>();
+        keys.sort_unstable();
+        for key in keys {
+            if from_remote || self.carried_keys.remove(&key) || self.inputs_grabbed {
+                self.send_to(from_remote, DriverEvent::keyboard_press(key, false));
+            }
+
+            let carried = if MODIFIER_KEYS.contains(&key) {
+                self.held_policy.modifiers
+            } else {
+                self.held_policy.keys
+            };
+            if carried {
+                self.suppressed_keys.remove(&key);
+                self.send_to(!from_remote, DriverEvent::keyboard_press(key, true));
+                if from_remote {
+                    self.carried_keys.insert(key);
+                }
+            } else {
+                self.suppressed_keys.insert(key);
+            }
+        }
+
+        let mut buttons = self.pressed_buttons.iter().copied().collect::<Vec<_>>();
+        buttons.sort_by_key(|b| *b as u8);
+        for button in buttons {
+            if from_remote || self.carried_buttons.remove(&button) || self.inputs_grabbed {
+                self.send_to(from_remote, DriverEvent::mouse_click(button, false));
+            }
+
+            if self.held_policy.buttons {
+                self.suppressed_buttons.remove(&button);
+                self.send_to(!from_remote, DriverEvent::mouse_click(button, true));
+                if from_remote {
+                    self.carried_buttons.insert(button);
+                }
+            } else {
+                self.suppressed_buttons.insert(button);
+            }
+        }
+    }
+
+    fn switch_mode(&mut self, new_remote: bool, reader: &mut dyn InputSource) {
+        let old_remote = self.remote_mode;
+        self.remote_mode = new_remote;
+        self.transfer_held_inputs(old_remote);
+        if self.remote_mode {
+            // Entering remote mode: grab inputs to prevent local OS from receiving them
+            if !self.inputs_grabbed {
+                let writer = &self.writer;
+                match reader.grab_with(&mut || writer.block_inputs()) {
+                    Ok(()) => self.inputs_grabbed = true,
+                    Err(e) => error!("Failed to grab inputs: {}", e),
+                }
+            }
+        } else if self.inputs_grabbed {
+            // Entering local mode: ungrab inputs
+            match self.writer.unblock_inputs() {
+                Ok(()) => self.inputs_grabbed = false,
+                Err(e) => error!("Failed to ungrab inputs: {}", e),
+            }
+        }
+        self.sync_locks(reader);
+        info!(
+            "Switched to {}",
+            if self.remote_mode {
+                "the slaves"
+            } else {
+                "this machine"
+            }
+        );
+    }
+
+    /// Replays and sends the events of the frame read at `captured`.
+    fn flush_frame(&mut self, captured: SystemTime) {
+        if !self.local.is_empty() {
+            let _ = self.writer.simulate_frame(&self.local);
+            self.local.clear();
+        }
+
+        if !self.outgoing.is_empty() {
+            let frame = input_frame(&std::mem::take(&mut self.outgoing)).captured_at(captured);
+            if self.tx.send(ServerMessage::Input(frame)).is_err() {
+                metrics().record_dropped();
+            }
+        }
+    }
+
+    fn update_status(&self) {
+        let mut status = self.status_mutex.lock().unwrap();
+        status.running = true;
+        status.calibration_mode = self.calibration_mode;
+        status.cursor_x = self.cursor_x;
+        status.cursor_y = self.cursor_y;
+        status.master_width = self.master_width;
+        status.master_height = self.master_height;
+        status.remote_mode = self.remote_mode;
+        status.cursor_locked = self.cursor_locked();
+    }
+}
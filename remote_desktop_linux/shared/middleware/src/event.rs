@@ -1,51 +1,88 @@
-use base64::{Engine as _, engine::general_purpose};
-use kmf_driver::event::{DriverEvent, KeyboardPress, MouseButton, MouseClick, MouseMove};
-use kmf_protocol::SerializationMode;
-use kmf_protocol::serialization::deserialize_bin;
-use serde_json::Value;
+use kmf_driver::event::{
+    DriverEvent, EventFrame, KeyboardPress, LockState, MouseButton, MouseClick, MouseMove,
+};
+use kmf_protocol::input::{Button, InputEvent, InputFrame};
 
-use crate::command::GenericAction;
-
-pub fn action_to_driver_event(action: Value) -> Result<DriverEvent, String> {
-    let mode = SerializationMode::from_env();
-    println!("[ACTION] Raw value: {}", action);
-    let generic = match mode {
-        SerializationMode::Json => serde_json::from_value::<GenericAction>(action)
-            .map_err(|e| format!("Failed to deserialize GenericAction: {}", e))?,
-        SerializationMode::Binary => match action {
-            Value::String(encoded) => {
-                let bytes = general_purpose::STANDARD
-                    .decode(encoded)
-                    .map_err(|e| format!("Failed to decode base64: {}", e))?;
-                deserialize_bin::<GenericAction>(&bytes)
-                    .map_err(|e| format!("Failed to deserialize GenericAction: {}", e))?
-            }
-            _ => return Err("Expected base64 string for binary action".to_string()),
+/// Wire form of a driver event.
+#[must_use]
+pub fn input_event(event: DriverEvent) -> InputEvent {
+    match event {
+        DriverEvent::MouseMove(MouseMove { x, y, wheel }) => InputEvent::MouseMove { x, y, wheel },
+        DriverEvent::MouseClick(MouseClick { button, pressed }) => InputEvent::MouseButton {
+            button: match button {
+                MouseButton::Left => Button::Left,
+                MouseButton::Right => Button::Right,
+                MouseButton::Middle => Button::Middle,
+            },
+            pressed,
         },
-    };
-
-    generic_action_to_driver_event(generic)
+        DriverEvent::KeyboardPress(KeyboardPress {
+            key,
+            pressed,
+            repeat,
+            keysym,
+        }) => InputEvent::Key {
+            code: key,
+            pressed,
+            repeat,
+            keysym,
+        },
+        DriverEvent::LockState(LockState {
+            caps_lock,
+            num_lock,
+            scroll_lock,
+        }) => InputEvent::LockState {
+            caps_lock,
+            num_lock,
+            scroll_lock,
+        },
+    }
 }
 
-fn generic_action_to_driver_event(action: GenericAction) -> Result<DriverEvent, String> {
-    match action {
-        GenericAction::MouseMove { x, y, wheel } => {
-            Ok(DriverEvent::MouseMove(MouseMove { x, y, wheel }))
-        }
-        GenericAction::MouseClick { button, pressed } => {
-            let button = match button.to_lowercase().as_str() {
-                "left" => MouseButton::Left,
-                "right" => MouseButton::Right,
-                "middle" => MouseButton::Middle,
-                _ => return Err(format!("Unknown mouse button: {}", button)),
-            };
-            Ok(DriverEvent::MouseClick(MouseClick { button, pressed }))
-        }
-        GenericAction::KeyPress { key, pressed } => {
-            let key = key
-                .parse::<u16>()
-                .map_err(|_| format!("Invalid key code: {}", key))?;
-            Ok(DriverEvent::KeyboardPress(KeyboardPress { key, pressed }))
-        }
+/// Driver event replaying a received input event.
+#[must_use]
+pub fn driver_event(event: InputEvent) -> DriverEvent {
+    match event {
+        InputEvent::MouseMove { x, y, wheel } => DriverEvent::mouse_move(x, y, wheel),
+        InputEvent::MouseButton { button, pressed } => DriverEvent::mouse_click(
+            match button {
+                Button::Left => MouseButton::Left,
+                Button::Right => MouseButton::Right,
+                Button::Middle => MouseButton::Middle,
+            },
+            pressed,
+        ),
+        InputEvent::Key {
+            code,
+            pressed,
+            repeat,
+            keysym,
+        } => DriverEvent::KeyboardPress(KeyboardPress {
+            key: code,
+            pressed,
+            repeat,
+            keysym,
+        }),
+        InputEvent::LockState {
+            caps_lock,
+            num_lock,
+            scroll_lock,
+        } => DriverEvent::LockState(LockState {
+            caps_lock,
+            num_lock,
+            scroll_lock,
+        }),
     }
 }
+
+/// Wire form of a frame of driver events.
+#[must_use]
+pub fn input_frame(frame: &[DriverEvent]) -> InputFrame {
+    InputFrame::new(frame.iter().copied().map(input_event).collect())
+}
+
+/// Driver events replaying a received frame as one batch.
+#[must_use]
+pub fn driver_frame(frame: InputFrame) -> EventFrame {
+    frame.events.into_iter().map(driver_event).collect()
+}
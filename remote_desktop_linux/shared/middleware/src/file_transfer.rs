@@ -2,6 +2,7 @@ use kmf_protocol::{AsyncStream, Packet};
 use std::path::Path;
 use tokio::fs::File;
 use tokio::io::AsyncReadExt;
+use tracing::{debug, info};
 
 /// Sends a file to a client using the two-packet protocol (DropSend + Data).
 ///
@@ -37,11 +38,11 @@ pub async fn send_file<S: AsyncStream>(
 
     kmf_protocol::send(Packet::Data(data.clone()), socket).await?;
 
-    println!("[DEBUG] File sent: {} ({} bytes)", filename, data.len());
+    debug!(%filename, bytes = data.len(), "File sent");
 
     match kmf_protocol::receive(socket).await {
         Ok(Packet::Ok) => {
-            println!("[DEBUG] File transfer acknowledged");
+            debug!("File transfer acknowledged");
             Ok(())
         }
         Ok(Packet::Err {
@@ -71,11 +72,11 @@ pub async fn receive_file<S: AsyncStream>(
     match kmf_protocol::receive(socket).await {
         Ok(Packet::Data(data)) => {
             save_file(filename, &data).await?;
-            println!("[FILE] Saved: {} ({} bytes)", filename, data.len());
+            info!(%filename, bytes = data.len(), "File saved");
             kmf_protocol::send(Packet::Ok, socket).await?;
             Ok(())
         }
-        Ok(other) => Err(format!("Expected Data packet, got: {:?}", other).into()),
+        Ok(other) => Err(format!("Expected Data packet, got: {:?}", other.redacted()).into()),
         Err(e) => Err(format!("Failed to receive file data: {}", e).into()),
     }
 }
@@ -0,0 +1,346 @@
+use std::collections::{BTreeMap, HashSet};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use kmf_driver::keys::{key_code, key_name};
+use serde::{Deserialize, Serialize};
+
+/// File the hotkeys are kept in, inside the user's config directory
+pub const HOTKEYS_FILE: &str = "kmf/hotkeys.toml";
+
+/// Modifier of a chord, matching its left and right key alike
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Modifier {
+    Ctrl,
+    Alt,
+    Shift,
+    Meta,
+}
+
+impl Modifier {
+    pub const ALL: [Modifier; 4] = [Self::Ctrl, Self::Alt, Self::Shift, Self::Meta];
+
+    /// Left and right key codes
+    #[must_use]
+    pub const fn keys(self) -> [u16; 2] {
+        match self {
+            Self::Ctrl => [29, 97],
+            Self::Alt => [56, 100],
+            Self::Shift => [42, 54],
+            Self::Meta => [125, 126],
+        }
+    }
+
+    #[must_use]
+    pub fn of_key(key: u16) -> Option<Self> {
+        Self::ALL.into_iter().find(|m| m.keys().contains(&key))
+    }
+
+    /// Whether the left or right key is among the `held` keys.
+    #[must_use]
+    pub fn is_held(self, held: &HashSet<u16>) -> bool {
+        self.keys().iter().any(|k| held.contains(k))
+    }
+
+    const fn name(self) -> &'static str {
+        match self {
+            Self::Ctrl => "ctrl",
+            Self::Alt => "alt",
+            Self::Shift => "shift",
+            Self::Meta => "meta",
+        }
+    }
+
+    fn parse(name: &str) -> Option<Self> {
+        match name {
+            "ctrl" | "control" => Some(Self::Ctrl),
+            "alt" => Some(Self::Alt),
+            "shift" => Some(Self::Shift),
+            "meta" | "super" | "win" => Some(Self::Meta),
+            _ => None,
+        }
+    }
+}
+
+/// Modifiers plus one key, written like `ctrl+alt+q`.
+///
+/// Matches only with exactly these modifiers held, so `ctrl+t` does not fire on `ctrl+shift+t`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Chord {
+    modifiers: Vec<Modifier>,
+    key: u16,
+}
+
+impl Chord {
+    #[must_use]
+    pub fn new(mut modifiers: Vec<Modifier>, key: u16) -> Self {
+        modifiers.sort();
+        modifiers.dedup();
+        Self { modifiers, key }
+    }
+
+    #[must_use]
+    pub fn key(&self) -> u16 {
+        self.key
+    }
+
+    #[must_use]
+    pub fn modifiers(&self) -> &[Modifier] {
+        &self.modifiers
+    }
+
+    /// Whether pressing `key` with `held` keys down triggers the chord.
+    #[must_use]
+    pub fn matches(&self, key: u16, held: &HashSet<u16>) -> bool {
+        key == self.key
+            && Modifier::ALL
+                .into_iter()
+                .all(|m| m.is_held(held) == self.modifiers.contains(&m))
+    }
+}
+
+impl FromStr for Modifier {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse(&s.trim().to_lowercase()).ok_or_else(|| format!("Unknown modifier '{}'", s))
+    }
+}
+
+impl fmt::Display for Modifier {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Chord {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut modifiers = Vec::new();
+        let mut key = None;
+        for part in s.split('+').map(str::trim) {
+            if part.is_empty() {
+                return Err(format!("Empty key in chord '{}'", s));
+            }
+            let lower = part.to_lowercase();
+            if let Some(modifier) = Modifier::parse(&lower) {
+                modifiers.push(modifier);
+                continue;
+            }
+            let code = key_code(&lower).ok_or_else(|| format!("Unknown key '{}'", part))?;
+            if key.replace(code).is_some() {
+                return Err(format!("Chord '{}' has more than one non-modifier key", s));
+            }
+        }
+        let key = key.ok_or_else(|| format!("Chord '{}' has no key besides modifiers", s))?;
+        Ok(Self::new(modifiers, key))
+    }
+}
+
+impl fmt::Display for Chord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for modifier in &self.modifiers {
+            write!(f, "{}+", modifier.name())?;
+        }
+        match key_name(self.key) {
+            Some(name) => write!(f, "{}", name),
+            None => write!(f, "key_{}", self.key),
+        }
+    }
+}
+
+/// What a hotkey does on the master
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HotkeyAction {
+    /// Moves the focus to a screen, 0 is the master and 1 the slaves
+    SwitchScreen(usize),
+    /// Toggles keeping the cursor on the current screen
+    LockCursor,
+    /// Moves the focus to the other side
+    ToggleRemote,
+    /// Sends Ctrl+Alt+Del to the slaves, which the master's own system would intercept
+    SendCtrlAltDel,
+    /// Stops the master, the failsafe when input gets stuck
+    StopMaster,
+}
+
+impl FromStr for HotkeyAction {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if let Some(screen) = s.strip_prefix("switch_screen:") {
+            return screen
+                .trim()
+                .parse()
+                .map(Self::SwitchScreen)
+                .map_err(|_| format!("Invalid screen number in '{}'", s));
+        }
+        match s {
+            "lock_cursor" => Ok(Self::LockCursor),
+            "toggle_remote" => Ok(Self::ToggleRemote),
+            "ctrl_alt_del" => Ok(Self::SendCtrlAltDel),
+            "stop_master" => Ok(Self::StopMaster),
+            _ => Err(format!(
+                "Unknown hotkey action '{}', expected switch_screen:<n>, lock_cursor, \
+                 toggle_remote, ctrl_alt_del or stop_master",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for HotkeyAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::SwitchScreen(screen) => write!(f, "switch_screen:{}", screen),
+            Self::LockCursor => f.write_str("lock_cursor"),
+            Self::ToggleRemote => f.write_str("toggle_remote"),
+            Self::SendCtrlAltDel => f.write_str("ctrl_alt_del"),
+            Self::StopMaster => f.write_str("stop_master"),
+        }
+    }
+}
+
+/// Chords the master reacts to instead of forwarding them.
+///
+/// Stored as a table of chord to action:
+///
+/// ```toml
+/// "ctrl+alt+q" = "stop_master"
+/// "ctrl+alt+right" = "switch_screen:1"
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(
+    try_from = "BTreeMap<String, String>",
+    into = "BTreeMap<String, String>"
+)]
+pub struct Hotkeys {
+    bindings: Vec<(Chord, HotkeyAction)>,
+}
+
+impl Default for Hotkeys {
+    fn default() -> Self {
+        let defaults = [
+            ("ctrl+alt+q", HotkeyAction::StopMaster),
+            ("ctrl+alt+left", HotkeyAction::SwitchScreen(0)),
+            ("ctrl+alt+right", HotkeyAction::SwitchScreen(1)),
+            ("ctrl+alt+l", HotkeyAction::LockCursor),
+            ("scrolllock", HotkeyAction::LockCursor),
+            ("ctrl+alt+end", HotkeyAction::SendCtrlAltDel),
+        ];
+        Self {
+            bindings: defaults
+                .into_iter()
+                .map(|(chord, action)| (chord.parse().expect("valid default chord"), action))
+                .collect(),
+        }
+    }
+}
+
+impl Hotkeys {
+    #[must_use]
+    pub fn empty() -> Self {
+        Self {
+            bindings: Vec::new(),
+        }
+    }
+
+    /// Binds `chord` to `action`, replacing what it was bound to before.
+    pub fn bind(&mut self, chord: Chord, action: HotkeyAction) {
+        self.unbind(&chord);
+        self.bindings.push((chord, action));
+    }
+
+    pub fn unbind(&mut self, chord: &Chord) {
+        self.bindings.retain(|(bound, _)| bound != chord);
+    }
+
+    pub fn bindings(&self) -> impl Iterator<Item = (&Chord, HotkeyAction)> {
+        self.bindings.iter().map(|(chord, action)| (chord, *action))
+    }
+
+    /// Action of the chord completed by pressing `key` while `held` keys are down.
+    #[must_use]
+    pub fn match_press(&self, key: u16, held: &HashSet<u16>) -> Option<HotkeyAction> {
+        self.bindings
+            .iter()
+            .find(|(chord, _)| chord.matches(key, held))
+            .map(|(_, action)| *action)
+    }
+
+    /// `$XDG_CONFIG_HOME/kmf/hotkeys.toml`, `None` without a home directory.
+    #[must_use]
+    pub fn default_path() -> Option<PathBuf> {
+        dirs::config_dir().map(|dir| dir.join(HOTKEYS_FILE))
+    }
+
+    /// Reads hotkeys from `path`, a missing file gives the defaults.
+    pub fn load(path: &Path) -> Result<Self, String> {
+        match std::fs::read_to_string(path) {
+            Ok(text) => Self::from_toml(&text)
+                .map_err(|e| format!("Invalid hotkeys in {}: {}", path.display(), e)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(format!("Failed to read {}: {}", path.display(), e)),
+        }
+    }
+
+    /// Hotkeys from the user's config file, the defaults if there is none or it is invalid.
+    #[must_use]
+    pub fn load_user() -> Self {
+        let Some(path) = Self::default_path() else {
+            return Self::default();
+        };
+        Self::load(&path).unwrap_or_else(|e| {
+            tracing::warn!("{}, using default hotkeys", e);
+            Self::default()
+        })
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)
+                .map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+        }
+        std::fs::write(path, self.to_toml())
+            .map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+    }
+
+    pub fn from_toml(text: &str) -> Result<Self, String> {
+        toml::from_str(text).map_err(|e| e.message().to_string())
+    }
+
+    #[must_use]
+    pub fn to_toml(&self) -> String {
+        toml::to_string(self).expect("hotkeys serialize to a flat table")
+    }
+}
+
+impl TryFrom<BTreeMap<String, String>> for Hotkeys {
+    type Error = String;
+
+    fn try_from(table: BTreeMap<String, String>) -> Result<Self, Self::Error> {
+        let mut hotkeys = Self::empty();
+        for (chord, action) in table {
+            let chord = chord.parse::<Chord>()?;
+            if hotkeys.bindings.iter().any(|(bound, _)| *bound == chord) {
+                return Err(format!("Chord '{}' is bound more than once", chord));
+            }
+            hotkeys.bind(chord, action.parse()?);
+        }
+        Ok(hotkeys)
+    }
+}
+
+impl From<Hotkeys> for BTreeMap<String, String> {
+    fn from(hotkeys: Hotkeys) -> Self {
+        hotkeys
+            .bindings
+            .into_iter()
+            .map(|(chord, action)| (chord.to_string(), action.to_string()))
+            .collect()
+    }
+}
@@ -1,3 +1,15 @@
+#[cfg(feature = "linux")]
+pub mod capture;
+pub mod clients;
 pub mod command;
+pub mod config;
+pub mod control;
+pub mod driver_loop;
 pub mod event;
 pub mod file_transfer;
+pub mod hotkeys;
+pub mod logging;
+pub mod metrics;
+pub mod recording;
+pub mod script;
+pub mod status;
@@ -0,0 +1,102 @@
+//! `tracing` subscriber of the binaries and the GUI.
+//!
+//! Filters by module like `info,kmf_driver=debug`, and writes text or JSON lines to stderr
+//! or a file, or structured entries to journald. Records of the `log` crate are forwarded.
+
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use tracing_appender::rolling::{RollingFileAppender, Rotation};
+use tracing_subscriber::fmt::MakeWriter;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{EnvFilter, Layer, Registry, fmt};
+
+/// Environment variable overriding the configured filter, like `RUST_LOG`
+pub const LOG_ENV: &str = "KMF_LOG";
+
+/// Where log lines go
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogOutput {
+    #[default]
+    Stderr,
+    File,
+    Journald,
+}
+
+/// How log lines look on stderr and in files, journald always gets structured fields
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+type BoxedLayer = Box<dyn Layer<Registry> + Send + Sync>;
+
+/// Installs the logger for the whole process, `name` identifies it in journald
+/// and names the default log file.
+pub fn init(config: &crate::config::LogConfig, name: &str) -> Result<(), String> {
+    let filter = match std::env::var(LOG_ENV) {
+        Ok(text) => EnvFilter::try_new(text).map_err(|e| format!("Invalid {}: {}", LOG_ENV, e))?,
+        Err(_) => EnvFilter::try_new(&config.filter).map_err(|e| e.to_string())?,
+    };
+    kmf_protocol::redact::set_show_keys(config.show_keys);
+
+    let output = match config.output {
+        LogOutput::Stderr => format_layer(config.format, std::io::stderr, true),
+        LogOutput::File => {
+            let path = match &config.file {
+                Some(path) => path.clone(),
+                None => default_file(name).ok_or("No state directory for the log file")?,
+            };
+            format_layer(config.format, open_log_file(&path)?, false)
+        }
+        LogOutput::Journald => tracing_journald::layer()
+            .map_err(|e| format!("Cannot reach journald: {}", e))?
+            .with_syslog_identifier(name.to_string())
+            .boxed(),
+    };
+
+    tracing_subscriber::registry()
+        .with(output.with_filter(filter))
+        .try_init()
+        .map_err(|e| format!("Logging is already set up: {}", e))
+}
+
+fn format_layer<W>(format: LogFormat, writer: W, ansi: bool) -> BoxedLayer
+where
+    W: for<'w> MakeWriter<'w> + Send + Sync + 'static,
+{
+    let layer = fmt::layer().with_writer(writer).with_ansi(ansi);
+    match format {
+        LogFormat::Text => layer.boxed(),
+        LogFormat::Json => layer.json().boxed(),
+    }
+}
+
+/// `$XDG_STATE_HOME/kmf/<name>.log`
+#[must_use]
+pub fn default_file(name: &str) -> Option<PathBuf> {
+    dirs::state_dir()
+        .or_else(dirs::data_local_dir)
+        .map(|dir| dir.join("kmf").join(format!("{}.log", name)))
+}
+
+/// Appends to `path`, creating its directory.
+fn open_log_file(path: &Path) -> Result<RollingFileAppender, String> {
+    let Some(file) = path.file_name() else {
+        return Err(format!("Invalid log file {}", path.display()));
+    };
+    let dir = path
+        .parent()
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+    RollingFileAppender::builder()
+        .rotation(Rotation::NEVER)
+        .filename_prefix(file.to_string_lossy())
+        .build(dir)
+        .map_err(|e| format!("Failed to open {}: {}", path.display(), e))
+}
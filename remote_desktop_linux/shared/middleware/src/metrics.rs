@@ -0,0 +1,477 @@
+//! Latency and throughput of the input pipeline.
+//!
+//! The master stamps frames when it reads them, the slave records how long they took
+//! until it replayed them. Everything is kept per process in [`metrics`] and read as a
+//! [`MetricsSnapshot`], by `kmfctl stats` and the GUI's `/metrics` endpoint.
+
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::io;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, LazyLock, Mutex};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant, SystemTime};
+
+use hdrhistogram::Histogram;
+use kmf_protocol::AsyncStream;
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+/// Longest latency told apart, slower frames count as this
+const MAX_LATENCY_US: u64 = 60_000_000;
+
+/// Deepest broadcast queue told apart
+const MAX_QUEUE_DEPTH: u64 = 1 << 16;
+
+/// Upper bounds of the exported latency buckets, in microseconds
+const LATENCY_BUCKETS_US: [u64; 12] = [
+    250, 500, 1_000, 2_500, 5_000, 10_000, 25_000, 50_000, 100_000, 250_000, 500_000, 1_000_000,
+];
+
+/// Upper bounds of the exported queue depth buckets
+const QUEUE_DEPTH_BUCKETS: [u64; 8] = [0, 1, 2, 5, 10, 25, 50, 100];
+
+static METRICS: LazyLock<Metrics> = LazyLock::new(Metrics::new);
+
+/// Metrics of this process
+#[must_use]
+pub fn metrics() -> &'static Metrics {
+    &METRICS
+}
+
+pub struct Metrics {
+    started: Instant,
+    /// Capture on the master to the replay on this slave, in microseconds
+    latency: Mutex<Histogram<u64>>,
+    /// Capture on this master to the slave's acknowledgement, in microseconds
+    round_trip: Mutex<Histogram<u64>>,
+    /// Messages still queued for a slave when it takes the next one
+    queue_depth: Mutex<Histogram<u64>>,
+    /// Frames captured for the slaves while none was connected
+    dropped: AtomicU64,
+    /// Messages slaves missed because they fell behind the broadcast channel
+    lagged: AtomicU64,
+    /// Traffic of all connections, also the closed ones
+    bytes_sent: AtomicU64,
+    bytes_received: AtomicU64,
+    peers: Mutex<BTreeMap<String, Arc<PeerCounters>>>,
+}
+
+#[derive(Debug, Default)]
+struct PeerCounters {
+    bytes_sent: AtomicU64,
+    bytes_received: AtomicU64,
+    lagged: AtomicU64,
+}
+
+/// Percentiles of a histogram
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Summary {
+    pub count: u64,
+    pub mean: f64,
+    pub p50: u64,
+    pub p90: u64,
+    pub p99: u64,
+    pub max: u64,
+    /// Upper bound and how many values are at most it, for Prometheus
+    #[serde(default)]
+    pub buckets: Vec<(u64, u64)>,
+}
+
+/// Traffic of one connection
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PeerSnapshot {
+    pub address: String,
+    pub bytes_sent: u64,
+    pub bytes_received: u64,
+    pub lagged: u64,
+}
+
+/// Result of the `stats` control method, latencies in microseconds
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MetricsSnapshot {
+    pub uptime_secs: u64,
+    pub latency_us: Summary,
+    pub round_trip_us: Summary,
+    pub queue_depth: Summary,
+    pub dropped: u64,
+    pub lagged: u64,
+    pub bytes_sent: u64,
+    pub bytes_received: u64,
+    pub peers: Vec<PeerSnapshot>,
+}
+
+impl std::fmt::Debug for Metrics {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Metrics").finish_non_exhaustive()
+    }
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Metrics {
+    #[must_use]
+    pub fn new() -> Self {
+        let histogram = |max| Mutex::new(Histogram::new_with_max(max, 3).expect("valid bounds"));
+        Self {
+            started: Instant::now(),
+            latency: histogram(MAX_LATENCY_US),
+            round_trip: histogram(MAX_LATENCY_US),
+            queue_depth: histogram(MAX_QUEUE_DEPTH),
+            dropped: AtomicU64::new(0),
+            lagged: AtomicU64::new(0),
+            bytes_sent: AtomicU64::new(0),
+            bytes_received: AtomicU64::new(0),
+            peers: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    /// Records a frame captured at `captured` and replayed just now. Frames stamped
+    /// ahead of this machine's clock, whose clock is behind the master's, are left out.
+    pub fn record_replayed(&self, captured: Option<SystemTime>) {
+        if let Some(latency) = captured.and_then(|time| time.elapsed().ok()) {
+            record(&self.latency, latency);
+        }
+    }
+
+    /// Records a frame captured at `captured` that a slave acknowledged just now.
+    pub fn record_acknowledged(&self, captured: Option<SystemTime>) {
+        if let Some(round_trip) = captured.and_then(|time| time.elapsed().ok()) {
+            record(&self.round_trip, round_trip);
+        }
+    }
+
+    pub fn record_queue_depth(&self, depth: usize) {
+        self.queue_depth
+            .lock()
+            .expect("Failed to lock histogram")
+            .saturating_record(depth as u64);
+    }
+
+    pub fn record_dropped(&self) {
+        self.dropped.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Starts counting the traffic of a connection, until the returned [`Peer`] is dropped.
+    pub fn peer(&'static self, address: &str) -> Peer {
+        let counters = Arc::new(PeerCounters::default());
+        self.peers
+            .lock()
+            .expect("Failed to lock peers")
+            .insert(address.to_string(), counters.clone());
+        Peer(Arc::new(PeerEntry {
+            metrics: self,
+            address: address.to_string(),
+            counters,
+        }))
+    }
+
+    #[must_use]
+    pub fn snapshot(&self) -> MetricsSnapshot {
+        let peers = self
+            .peers
+            .lock()
+            .expect("Failed to lock peers")
+            .iter()
+            .map(|(address, counters)| PeerSnapshot {
+                address: address.clone(),
+                bytes_sent: counters.bytes_sent.load(Ordering::Relaxed),
+                bytes_received: counters.bytes_received.load(Ordering::Relaxed),
+                lagged: counters.lagged.load(Ordering::Relaxed),
+            })
+            .collect();
+        MetricsSnapshot {
+            uptime_secs: self.started.elapsed().as_secs(),
+            latency_us: summary(&self.latency, &LATENCY_BUCKETS_US),
+            round_trip_us: summary(&self.round_trip, &LATENCY_BUCKETS_US),
+            queue_depth: summary(&self.queue_depth, &QUEUE_DEPTH_BUCKETS),
+            dropped: self.dropped.load(Ordering::Relaxed),
+            lagged: self.lagged.load(Ordering::Relaxed),
+            bytes_sent: self.bytes_sent.load(Ordering::Relaxed),
+            bytes_received: self.bytes_received.load(Ordering::Relaxed),
+            peers,
+        }
+    }
+}
+
+fn record(histogram: &Mutex<Histogram<u64>>, duration: Duration) {
+    histogram
+        .lock()
+        .expect("Failed to lock histogram")
+        .saturating_record(duration.as_micros().try_into().unwrap_or(u64::MAX));
+}
+
+fn summary(histogram: &Mutex<Histogram<u64>>, bounds: &[u64]) -> Summary {
+    let histogram = histogram.lock().expect("Failed to lock histogram");
+    let buckets = bounds
+        .iter()
+        .map(|&bound| (bound, histogram.count_between(0, bound)))
+        .collect();
+    if histogram.is_empty() {
+        return Summary {
+            buckets,
+            ..Summary::default()
+        };
+    }
+    Summary {
+        count: histogram.len(),
+        mean: histogram.mean(),
+        p50: histogram.value_at_quantile(0.5),
+        p90: histogram.value_at_quantile(0.9),
+        p99: histogram.value_at_quantile(0.99),
+        max: histogram.max(),
+        buckets,
+    }
+}
+
+/// Handle on the counters of one connection, they are removed with the last clone
+#[derive(Debug, Clone)]
+pub struct Peer(Arc<PeerEntry>);
+
+#[derive(Debug)]
+struct PeerEntry {
+    metrics: &'static Metrics,
+    address: String,
+    counters: Arc<PeerCounters>,
+}
+
+impl Drop for PeerEntry {
+    fn drop(&mut self) {
+        let mut peers = self.metrics.peers.lock().expect("Failed to lock peers");
+        // a new connection from the same address keeps its counters
+        if peers
+            .get(&self.address)
+            .is_some_and(|counters| Arc::ptr_eq(counters, &self.counters))
+        {
+            peers.remove(&self.address);
+        }
+    }
+}
+
+impl Peer {
+    /// Messages this peer missed by falling behind the broadcast channel
+    pub fn record_lagged(&self, missed: u64) {
+        self.0.counters.lagged.fetch_add(missed, Ordering::Relaxed);
+        self.0.metrics.lagged.fetch_add(missed, Ordering::Relaxed);
+    }
+
+    /// `stream` counting its traffic for this peer.
+    #[must_use]
+    pub fn count(&self, stream: Box<dyn AsyncStream>) -> Box<dyn AsyncStream> {
+        Box::new(CountingStream {
+            inner: stream,
+            peer: self.clone(),
+        })
+    }
+
+    fn sent(&self, bytes: usize) {
+        self.0
+            .counters
+            .bytes_sent
+            .fetch_add(bytes as u64, Ordering::Relaxed);
+        self.0
+            .metrics
+            .bytes_sent
+            .fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    fn received(&self, bytes: usize) {
+        self.0
+            .counters
+            .bytes_received
+            .fetch_add(bytes as u64, Ordering::Relaxed);
+        self.0
+            .metrics
+            .bytes_received
+            .fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+}
+
+struct CountingStream {
+    inner: Box<dyn AsyncStream>,
+    peer: Peer,
+}
+
+impl AsyncRead for CountingStream {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let before = buf.filled().len();
+        let poll = Pin::new(&mut self.inner).poll_read(cx, buf);
+        if let Poll::Ready(Ok(())) = poll {
+            self.peer.received(buf.filled().len() - before);
+        }
+        poll
+    }
+}
+
+impl AsyncWrite for CountingStream {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let poll = Pin::new(&mut self.inner).poll_write(cx, buf);
+        if let Poll::Ready(Ok(written)) = poll {
+            self.peer.sent(written);
+        }
+        poll
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
+impl MetricsSnapshot {
+    /// Prometheus text exposition format, latencies in seconds.
+    #[must_use]
+    pub fn to_prometheus(&self) -> String {
+        let mut out = String::new();
+        gauge(
+            &mut out,
+            "kmf_uptime_seconds",
+            "Time since the process started",
+            self.uptime_secs,
+        );
+        histogram(
+            &mut out,
+            "kmf_input_latency_seconds",
+            "Capture on the master to the replay on this slave",
+            &self.latency_us,
+            1e6,
+        );
+        histogram(
+            &mut out,
+            "kmf_input_round_trip_seconds",
+            "Capture on this master to the acknowledgement of a slave",
+            &self.round_trip_us,
+            1e6,
+        );
+        histogram(
+            &mut out,
+            "kmf_broadcast_queue_depth",
+            "Messages queued for a slave when it takes the next one",
+            &self.queue_depth,
+            1.0,
+        );
+        counter(
+            &mut out,
+            "kmf_input_dropped_total",
+            "Frames captured for the slaves while none was connected",
+            self.dropped,
+        );
+        counter(
+            &mut out,
+            "kmf_broadcast_lagged_total",
+            "Messages slaves missed by falling behind",
+            self.lagged,
+        );
+        counter(
+            &mut out,
+            "kmf_bytes_sent_total",
+            "Bytes sent to all peers",
+            self.bytes_sent,
+        );
+        counter(
+            &mut out,
+            "kmf_bytes_received_total",
+            "Bytes received from all peers",
+            self.bytes_received,
+        );
+
+        type PeerValue = fn(&PeerSnapshot) -> u64;
+        let per_peer: [(&str, &str, PeerValue); 3] = [
+            (
+                "kmf_peer_bytes_sent_total",
+                "Bytes sent to a connected peer",
+                |peer| peer.bytes_sent,
+            ),
+            (
+                "kmf_peer_bytes_received_total",
+                "Bytes received from a connected peer",
+                |peer| peer.bytes_received,
+            ),
+            (
+                "kmf_peer_lagged_total",
+                "Messages a connected slave missed by falling behind",
+                |peer| peer.lagged,
+            ),
+        ];
+        for (name, help, value) in per_peer {
+            header(&mut out, name, help, "counter");
+            for peer in &self.peers {
+                let _ = writeln!(
+                    out,
+                    "{}{{peer=\"{}\"}} {}",
+                    name,
+                    escape(&peer.address),
+                    value(peer)
+                );
+            }
+        }
+        out
+    }
+}
+
+fn header(out: &mut String, name: &str, help: &str, kind: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+fn gauge(out: &mut String, name: &str, help: &str, value: impl std::fmt::Display) {
+    header(out, name, help, "gauge");
+    let _ = writeln!(out, "{} {}", name, value);
+}
+
+fn counter(out: &mut String, name: &str, help: &str, value: u64) {
+    header(out, name, help, "counter");
+    let _ = writeln!(out, "{} {}", name, value);
+}
+
+/// Cumulative buckets plus the largest value as `<name>_max`, recorded values are
+/// divided by `unit` for the exported one
+fn histogram(out: &mut String, name: &str, help: &str, summary: &Summary, unit: f64) {
+    header(out, name, help, "histogram");
+    for (bound, count) in &summary.buckets {
+        let _ = writeln!(
+            out,
+            "{}_bucket{{le=\"{}\"}} {}",
+            name,
+            *bound as f64 / unit,
+            count
+        );
+    }
+    let _ = writeln!(out, "{}_bucket{{le=\"+Inf\"}} {}", name, summary.count);
+    let _ = writeln!(
+        out,
+        "{}_sum {}",
+        name,
+        summary.mean * summary.count as f64 / unit
+    );
+    let _ = writeln!(out, "{}_count {}", name, summary.count);
+    gauge(
+        out,
+        &format!("{}_max", name),
+        &format!("Largest value of {}", name),
+        summary.max as f64 / unit,
+    );
+}
+
+fn escape(label: &str) -> String {
+    label
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
@@ -0,0 +1,206 @@
+use std::io::{BufRead, Error, ErrorKind, Result, Write};
+use std::path::Path;
+use std::str::FromStr;
+use std::time::Duration;
+
+use kmf_driver::event::{DriverEvent, EventFrame};
+use kmf_protocol::input::InputFrame;
+use serde::{Deserialize, Serialize};
+
+use crate::event::{driver_frame, input_frame};
+
+/// First bytes of a binary recording, followed by its format version
+const BINARY_MAGIC: &[u8; 6] = b"KMFREC";
+const BINARY_VERSION: u8 = 1;
+
+/// How a recording is stored
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecordingFormat {
+    /// One JSON object per frame, readable and easy to edit by hand
+    JsonLines,
+    /// Timestamp and compact [`InputFrame::encode`] per frame
+    Binary,
+}
+
+impl RecordingFormat {
+    /// `.json`/`.jsonl` files are JSON lines, everything else binary.
+    #[must_use]
+    pub fn for_path(path: &Path) -> Self {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("json" | "jsonl") => Self::JsonLines,
+            _ => Self::Binary,
+        }
+    }
+}
+
+impl FromStr for RecordingFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "json" | "jsonl" => Ok(Self::JsonLines),
+            "binary" | "bin" => Ok(Self::Binary),
+            _ => Err(format!("Unknown recording format '{}'", s)),
+        }
+    }
+}
+
+/// Frame of a recording with the time since recording started
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RecordedFrame {
+    #[serde(rename = "at_us", with = "micros")]
+    pub at: Duration,
+    pub events: EventFrame,
+}
+
+impl RecordedFrame {
+    /// When to replay the frame at `speed` times the original pace.
+    #[must_use]
+    pub fn replay_at(&self, speed: f64) -> Duration {
+        if speed > 0.0 {
+            self.at.div_f64(speed)
+        } else {
+            self.at
+        }
+    }
+}
+
+mod micros {
+    use serde::{Deserialize, Deserializer, Serializer};
+    use std::time::Duration;
+
+    pub fn serialize<S: Serializer>(at: &Duration, s: S) -> Result<S::Ok, S::Error> {
+        s.serialize_u64(at.as_micros() as u64)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<Duration, D::Error> {
+        u64::deserialize(d).map(Duration::from_micros)
+    }
+}
+
+/// Writes timestamped frames to a recording
+#[derive(Debug)]
+pub struct Recorder<W: Write> {
+    out: W,
+    format: RecordingFormat,
+}
+
+impl<W: Write> Recorder<W> {
+    pub fn new(mut out: W, format: RecordingFormat) -> Result<Self> {
+        if format == RecordingFormat::Binary {
+            out.write_all(BINARY_MAGIC)?;
+            out.write_all(&[BINARY_VERSION])?;
+        }
+        Ok(Self { out, format })
+    }
+
+    pub fn record(&mut self, at: Duration, events: &[DriverEvent]) -> Result<()> {
+        match self.format {
+            RecordingFormat::JsonLines => {
+                let frame = RecordedFrame {
+                    at,
+                    events: events.to_vec(),
+                };
+                serde_json::to_writer(&mut self.out, &frame)?;
+                self.out.write_all(b"\n")
+            }
+            RecordingFormat::Binary => {
+                let encoded = input_frame(events).encode();
+                self.out.write_all(&(at.as_micros() as u64).to_be_bytes())?;
+                self.out.write_all(&(encoded.len() as u32).to_be_bytes())?;
+                self.out.write_all(&encoded)
+            }
+        }
+    }
+
+    /// Flushes and hands back the writer.
+    pub fn finish(mut self) -> Result<W> {
+        self.out.flush()?;
+        Ok(self.out)
+    }
+}
+
+/// Reads the frames of a recording in either format, detected from its first bytes
+#[derive(Debug)]
+pub struct Playback<R: BufRead> {
+    input: R,
+    format: RecordingFormat,
+    line: String,
+}
+
+impl<R: BufRead> Playback<R> {
+    pub fn new(mut input: R) -> Result<Self> {
+        let format = if input.fill_buf()?.starts_with(BINARY_MAGIC) {
+            let mut header = [0u8; BINARY_MAGIC.len() + 1];
+            input.read_exact(&mut header)?;
+            let version = header[BINARY_MAGIC.len()];
+            if version != BINARY_VERSION {
+                return Err(invalid(format!(
+                    "Unsupported recording version {}",
+                    version
+                )));
+            }
+            RecordingFormat::Binary
+        } else {
+            RecordingFormat::JsonLines
+        };
+        Ok(Self {
+            input,
+            format,
+            line: String::new(),
+        })
+    }
+
+    #[must_use]
+    pub fn format(&self) -> RecordingFormat {
+        self.format
+    }
+
+    fn next_json(&mut self) -> Result<Option<RecordedFrame>> {
+        loop {
+            self.line.clear();
+            if self.input.read_line(&mut self.line)? == 0 {
+                return Ok(None);
+            }
+            if !self.line.trim().is_empty() {
+                return serde_json::from_str(&self.line)
+                    .map(Some)
+                    .map_err(Error::from);
+            }
+        }
+    }
+
+    fn next_binary(&mut self) -> Result<Option<RecordedFrame>> {
+        if self.input.fill_buf()?.is_empty() {
+            return Ok(None);
+        }
+        let mut header = [0u8; 12];
+        self.input.read_exact(&mut header)?;
+        let (at, len) = header.split_at(8);
+        let at = u64::from_be_bytes(at.try_into().unwrap_or_default());
+        let len = u32::from_be_bytes(len.try_into().unwrap_or_default()) as usize;
+        let mut encoded = vec![0u8; len];
+        self.input.read_exact(&mut encoded)?;
+        let frame = InputFrame::decode(&encoded).map_err(invalid)?;
+        Ok(Some(RecordedFrame {
+            at: Duration::from_micros(at),
+            events: driver_frame(frame),
+        }))
+    }
+}
+
+impl<R: BufRead> Iterator for Playback<R> {
+    type Item = Result<RecordedFrame>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.format {
+            RecordingFormat::JsonLines => self.next_json(),
+            RecordingFormat::Binary => self.next_binary(),
+        }
+        .transpose()
+    }
+}
+
+fn invalid(message: String) -> Error {
+    Error::new(ErrorKind::InvalidData, message)
+}
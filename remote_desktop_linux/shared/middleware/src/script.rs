@@ -0,0 +1,368 @@
+use std::fmt;
+use std::ops::ControlFlow;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use kmf_driver::layout::{ALTGR_KEY, Keysym, Layout, Level};
+use kmf_protocol::config::ServerMessage;
+use kmf_protocol::input::{InputEvent, InputFrame};
+
+use crate::command::{
+    CommandError, Commands, Token, check_arity, key_events, parse_tokens, parse_verb, tokenize,
+};
+use crate::hotkeys::Modifier;
+
+/// KEY_ENTER, KEY_TAB
+const ENTER_KEY: u16 = 28;
+const TAB_KEY: u16 = 15;
+
+/// Nesting limit for `run` so scripts including each other fail instead of recursing forever
+const MAX_RUN_DEPTH: usize = 8;
+
+/// Error in a script at a 1-based line and column
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScriptError {
+    pub line: usize,
+    pub column: usize,
+    pub error: CommandError,
+}
+
+impl ScriptError {
+    fn new(line: usize, text: &str, error: CommandError) -> Self {
+        let start = error.span().start;
+        let column = text.get(..start).map_or(0, |s| s.chars().count()) + 1;
+        Self {
+            line,
+            column,
+            error,
+        }
+    }
+
+    /// Whether the script only lacks the closing `}` of a block.
+    #[must_use]
+    pub fn is_incomplete(&self) -> bool {
+        matches!(self.error, CommandError::UnclosedBlock { .. })
+    }
+}
+
+impl fmt::Display for ScriptError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.column, self.error)
+    }
+}
+
+impl std::error::Error for ScriptError {}
+
+/// One thing a running script does
+#[derive(Debug, Clone)]
+pub enum Step {
+    /// Broadcast a message to the slaves
+    Send(ServerMessage),
+    Sleep(Duration),
+}
+
+#[derive(Debug, Clone)]
+enum Statement {
+    Step(Step),
+    Repeat { count: u32, body: Vec<Statement> },
+}
+
+/// Parsed master console script.
+///
+/// One command of [`crate::command::COMMANDS`] per line, `#` starts a comment:
+///
+/// ```text
+/// repeat 3 {
+///     key ctrl+t
+///     sleep 300ms
+///     type "kmf\n"
+/// }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Script {
+    statements: Vec<Statement>,
+}
+
+impl Script {
+    /// Parses `source`, typing `type` strings with the keys of `layout`.
+    pub fn parse(source: &str, layout: &Layout) -> Result<Self, ScriptError> {
+        Self::parse_in(source, layout, Path::new("."), 0)
+    }
+
+    /// Reads and parses a script file, `run` paths are relative to its directory.
+    pub fn load(path: &Path, layout: &Layout) -> Result<Self, String> {
+        let source = std::fs::read_to_string(path)
+            .map_err(|e| format!("Cannot read script {}: {}", path.display(), e))?;
+        let dir = path.parent().unwrap_or(Path::new("."));
+        Self::parse_in(&source, layout, dir, 0).map_err(|e| format!("{}:{}", path.display(), e))
+    }
+
+    fn parse_in(
+        source: &str,
+        layout: &Layout,
+        dir: &Path,
+        depth: usize,
+    ) -> Result<Self, ScriptError> {
+        let mut parser = Parser {
+            layout,
+            dir,
+            depth,
+            blocks: vec![Block::default()],
+            line: 0,
+        };
+        for (index, text) in source.lines().enumerate() {
+            parser
+                .line(text)
+                .map_err(|e| ScriptError::new(index + 1, text, e))?;
+        }
+        if parser.blocks.len() > 1 {
+            let open = parser.blocks.pop().unwrap_or_default();
+            return Err(ScriptError {
+                line: open.line,
+                column: open.column,
+                error: CommandError::UnclosedBlock { span: open.span },
+            });
+        }
+        let statements = parser.blocks.pop().unwrap_or_default().body;
+        Ok(Self { statements })
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.statements.is_empty()
+    }
+
+    /// Calls `f` with every step in order, repeat blocks unrolled, until it breaks.
+    pub fn run(&self, mut f: impl FnMut(&Step) -> ControlFlow<()>) -> ControlFlow<()> {
+        run_statements(&self.statements, &mut f)
+    }
+
+    /// All steps in order with repeat blocks unrolled.
+    #[must_use]
+    pub fn steps(&self) -> Vec<Step> {
+        let mut steps = Vec::new();
+        let _ = self.run(|step| {
+            steps.push(step.clone());
+            ControlFlow::Continue(())
+        });
+        steps
+    }
+}
+
+fn run_statements(
+    statements: &[Statement],
+    f: &mut impl FnMut(&Step) -> ControlFlow<()>,
+) -> ControlFlow<()> {
+    for statement in statements {
+        match statement {
+            Statement::Step(step) => f(step)?,
+            Statement::Repeat { count, body } => {
+                for _ in 0..*count {
+                    run_statements(body, f)?;
+                }
+            }
+        }
+    }
+    ControlFlow::Continue(())
+}
+
+/// Statements of a block being parsed, with where its `repeat` started
+#[derive(Debug, Default)]
+struct Block {
+    count: u32,
+    line: usize,
+    column: usize,
+    span: crate::command::Span,
+    body: Vec<Statement>,
+}
+
+struct Parser<'a> {
+    layout: &'a Layout,
+    dir: &'a Path,
+    depth: usize,
+    /// Top level first, innermost open `repeat` last
+    blocks: Vec<Block>,
+    line: usize,
+}
+
+impl Parser<'_> {
+    fn line(&mut self, text: &str) -> Result<(), CommandError> {
+        self.line += 1;
+        let tokens = tokenize(text)?;
+        let Some((command, args)) = tokens.split_first() else {
+            return Ok(());
+        };
+
+        let statements = if command.text == "}" && !command.quoted {
+            self.close_block(command, args)?
+        } else {
+            self.command(&tokens, text)?
+        };
+        if let Some(block) = self.blocks.last_mut() {
+            block.body.extend(statements);
+        }
+        Ok(())
+    }
+
+    /// Statements of a command line, none for one opening a `repeat` block.
+    fn command(&mut self, tokens: &[Token], text: &str) -> Result<Vec<Statement>, CommandError> {
+        let (command, args) = (&tokens[0], &tokens[1..]);
+        let spec = parse_verb(command)?;
+        check_arity(spec, command, args, text.len())?;
+        Ok(match spec.command {
+            Commands::Type => self.type_text(&args[0])?,
+            Commands::Sleep => {
+                let duration =
+                    parse_duration(&args[0].text).ok_or_else(|| CommandError::InvalidDuration {
+                        value: args[0].text.clone(),
+                        span: args[0].span.clone(),
+                    })?;
+                vec![Statement::Step(Step::Sleep(duration))]
+            }
+            Commands::Repeat => {
+                let count =
+                    args[0]
+                        .text
+                        .parse::<u32>()
+                        .map_err(|_| CommandError::InvalidNumber {
+                            value: args[0].text.clone(),
+                            span: args[0].span.clone(),
+                        })?;
+                if args[1].text != "{" || args[1].quoted {
+                    return Err(CommandError::ExpectedBlock {
+                        span: args[1].span.clone(),
+                    });
+                }
+                self.blocks.push(Block {
+                    count,
+                    line: self.line,
+                    column: text[..command.span.start].chars().count() + 1,
+                    span: command.span.clone(),
+                    body: Vec::new(),
+                });
+                Vec::new()
+            }
+            Commands::Run => self.run(command, &args[0])?,
+            Commands::Help => {
+                return Err(CommandError::NotAvailable {
+                    command: Commands::Help,
+                    span: command.span.clone(),
+                });
+            }
+            _ => vec![Statement::Step(Step::Send(parse_tokens(
+                tokens,
+                text.len(),
+            )?))],
+        })
+    }
+
+    /// Closes the innermost `repeat` block.
+    fn close_block(
+        &mut self,
+        brace: &Token,
+        args: &[Token],
+    ) -> Result<Vec<Statement>, CommandError> {
+        if let Some(extra) = args.first() {
+            return Err(CommandError::UnexpectedArgument {
+                value: extra.text.clone(),
+                span: extra.span.clone(),
+            });
+        }
+        if self.blocks.len() < 2 {
+            return Err(CommandError::UnmatchedBrace {
+                span: brace.span.clone(),
+            });
+        }
+        let block = self.blocks.pop().unwrap_or_default();
+        Ok(vec![Statement::Repeat {
+            count: block.count,
+            body: block.body,
+        }])
+    }
+
+    /// Statements of another script file, inlined.
+    fn run(&self, command: &Token, file: &Token) -> Result<Vec<Statement>, CommandError> {
+        if self.depth >= MAX_RUN_DEPTH {
+            return Err(CommandError::NestedTooDeep {
+                span: command.span.clone(),
+            });
+        }
+        let path: PathBuf = self.dir.join(&file.text);
+        let source = std::fs::read_to_string(&path).map_err(|e| CommandError::ReadScript {
+            path: path.display().to_string(),
+            reason: e.to_string(),
+            span: file.span.clone(),
+        })?;
+        let dir = path.parent().unwrap_or(Path::new("."));
+        Script::parse_in(&source, self.layout, dir, self.depth + 1)
+            .map(|script| script.statements)
+            .map_err(|e| CommandError::InScript {
+                path: path.display().to_string(),
+                error: Box::new(e),
+                span: file.span.clone(),
+            })
+    }
+
+    /// One frame per character, pressing Shift or AltGr around keys that need it.
+    fn type_text(&self, token: &Token) -> Result<Vec<Statement>, CommandError> {
+        if !token.quoted {
+            return Err(CommandError::ExpectedString {
+                span: token.span.clone(),
+            });
+        }
+        token
+            .text
+            .chars()
+            .map(|c| {
+                let (key, modifiers) = match c {
+                    '\n' => (ENTER_KEY, Vec::new()),
+                    '\t' => (TAB_KEY, Vec::new()),
+                    c => {
+                        let (key, level) =
+                            self.layout.locate(Keysym::from_char(c)).ok_or_else(|| {
+                                CommandError::Untypable {
+                                    character: c,
+                                    layout: self.layout.name().to_string(),
+                                    span: token.span.clone(),
+                                }
+                            })?;
+                        let modifiers = match level {
+                            Level::Base => Vec::new(),
+                            Level::Shift => vec![Modifier::Shift.keys()[0]],
+                            Level::AltGr => vec![ALTGR_KEY],
+                        };
+                        (key, modifiers)
+                    }
+                };
+                let keysym = (!c.is_control()).then(|| Keysym::from_char(c).0);
+                let mut events = key_events(&modifiers, key, None);
+                for event in &mut events {
+                    if let InputEvent::Key {
+                        code, keysym: sym, ..
+                    } = event
+                        && *code == key
+                    {
+                        *sym = keysym;
+                    }
+                }
+                Ok(Statement::Step(Step::Send(ServerMessage::Input(
+                    InputFrame::new(events),
+                ))))
+            })
+            .collect()
+    }
+}
+
+/// Parses `200ms`, `2s` or `1.5s`.
+#[must_use]
+pub fn parse_duration(text: &str) -> Option<Duration> {
+    let (value, scale) = if let Some(ms) = text.strip_suffix("ms") {
+        (ms, 0.001)
+    } else if let Some(s) = text.strip_suffix('s') {
+        (s, 1.0)
+    } else {
+        return None;
+    };
+    let value = value.parse::<f64>().ok().filter(|v| *v >= 0.0)?;
+    Duration::try_from_secs_f64(value * scale).ok()
+}
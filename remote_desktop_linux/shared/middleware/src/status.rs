@@ -9,6 +9,8 @@ pub struct MasterStatusSnapshot {
     pub master_width: i32,
     pub master_height: i32,
     pub remote_mode: bool,
+    pub cursor_locked: bool,
+    pub disconnected_devices: Vec<String>,
 }
 
 #[derive(Debug, Clone)]
@@ -20,6 +22,10 @@ pub struct MasterStatus {
     pub master_width: i32,
     pub master_height: i32,
     pub remote_mode: bool,
+    /// Cursor confined to the current screen, edges are not crossed
+    pub cursor_locked: bool,
+    /// Labels of configured input devices which are currently unplugged
+    pub disconnected_devices: Vec<String>,
 }
 
 impl MasterStatus {
@@ -27,10 +33,12 @@ impl MasterStatus {
         self.running = true;
         self.calibration_mode = true;
         self.remote_mode = false;
+        self.cursor_locked = false;
         self.cursor_x = 0;
         self.cursor_y = 0;
         self.master_width = 1920;
         self.master_height = 1080;
+        self.disconnected_devices.clear();
     }
 }
 
@@ -44,6 +52,8 @@ impl Default for MasterStatus {
             master_width: 1920,
             master_height: 1080,
             remote_mode: false,
+            cursor_locked: false,
+            disconnected_devices: Vec::new(),
         }
     }
 }
@@ -58,6 +68,8 @@ impl From<&MasterStatus> for MasterStatusSnapshot {
             master_width: status.master_width,
             master_height: status.master_height,
             remote_mode: status.remote_mode,
+            cursor_locked: status.cursor_locked,
+            disconnected_devices: status.disconnected_devices.clone(),
         }
     }
 }
@@ -0,0 +1,163 @@
+use kmf_middleware::command::{
+    COMMANDS, CommandError, Commands, command_names, help, parse_command, tokenize,
+};
+use kmf_protocol::config::ServerMessage;
+use kmf_protocol::input::{Button, InputEvent};
+
+fn events(input: &str) -> Vec<InputEvent> {
+    match parse_command(input) {
+        Ok(ServerMessage::Input(frame)) => frame.events,
+        other => panic!("expected input for {input:?}, got {other:?}"),
+    }
+}
+
+fn key(code: u16, pressed: bool) -> InputEvent {
+    InputEvent::Key {
+        code,
+        pressed,
+        repeat: false,
+        keysym: None,
+    }
+}
+
+#[test]
+fn parses_single_line_commands() {
+    assert_eq!(
+        events("move 10 -5"),
+        vec![InputEvent::MouseMove {
+            x: 10,
+            y: -5,
+            wheel: 0
+        }]
+    );
+    assert_eq!(
+        events("click middle d"),
+        vec![InputEvent::MouseButton {
+            button: Button::Middle,
+            pressed: true
+        }]
+    );
+    assert_eq!(events("key enter up"), vec![key(28, false)]);
+    assert_eq!(events("key 30"), vec![key(30, true), key(30, false)]);
+    assert_eq!(
+        events("key ctrl+c down"),
+        vec![key(29, true), key(46, true)]
+    );
+    assert!(matches!(parse_command("quit"), Ok(ServerMessage::Quit)));
+}
+
+/// Input, the error it should give and that error's span
+type Case = (
+    &'static str,
+    fn(&CommandError) -> bool,
+    std::ops::Range<usize>,
+);
+
+#[test]
+fn errors_carry_the_span_of_the_offending_token() {
+    let cases: &[Case] = &[
+        (
+            "jump 1",
+            |e| matches!(e, CommandError::UnknownCommand { .. }),
+            0..4,
+        ),
+        (
+            "move 1",
+            |e| matches!(e, CommandError::WrongArity { found: 1, .. }),
+            0..6,
+        ),
+        (
+            "quit now please",
+            |e| matches!(e, CommandError::WrongArity { found: 2, .. }),
+            5..15,
+        ),
+        (
+            "move 1 y",
+            |e| matches!(e, CommandError::InvalidNumber { .. }),
+            7..8,
+        ),
+        (
+            "click side d",
+            |e| matches!(e, CommandError::UnknownButton { .. }),
+            6..10,
+        ),
+        (
+            "key ctrl+nope",
+            |e| matches!(e, CommandError::UnknownKey { .. }),
+            4..13,
+        ),
+        (
+            "key a sideways",
+            |e| matches!(e, CommandError::InvalidDirection { .. }),
+            6..14,
+        ),
+        (
+            "file /no/such/file",
+            |e| matches!(e, CommandError::MissingFile { .. }),
+            5..18,
+        ),
+        (
+            "file \"open",
+            |e| matches!(e, CommandError::UnterminatedString { .. }),
+            5..10,
+        ),
+        (
+            "sleep 1s",
+            |e| {
+                matches!(
+                    e,
+                    CommandError::NotAvailable {
+                        command: Commands::Sleep,
+                        ..
+                    }
+                )
+            },
+            0..5,
+        ),
+    ];
+    for (input, is_expected, span) in cases {
+        let error = parse_command(input).unwrap_err();
+        assert!(is_expected(&error), "{input:?} gave {error:?}");
+        assert_eq!(error.span(), *span, "{input:?}");
+    }
+    assert!(matches!(
+        parse_command("   # just a comment"),
+        Err(CommandError::Empty)
+    ));
+}
+
+#[test]
+fn errors_read_well() {
+    let error = parse_command("click left down twice").unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "'click' takes 1 to 2 argument(s), got 3, usage: click <left|right|middle> [down|up]"
+    );
+
+    let input = "key ctrl+nope d";
+    assert_eq!(
+        parse_command(input).unwrap_err().report(input),
+        "key ctrl+nope d\n    ^^^^^^^^^ Unknown key 'nope'"
+    );
+}
+
+#[test]
+fn help_comes_from_the_command_table() {
+    let all = help(None).unwrap();
+    for spec in COMMANDS {
+        assert!(all.contains(&spec.usage()), "{} missing", spec.name);
+        assert!(all.contains(spec.summary));
+    }
+    assert!(command_names().starts_with("move | click | key"));
+
+    let tokens = tokenize("help repeat").unwrap();
+    assert_eq!(
+        help(Some(&tokens[1])).unwrap(),
+        "repeat <n> {\n    Run the lines up to the closing } n times"
+    );
+    let tokens = tokenize("help fly").unwrap();
+    assert!(matches!(
+        help(Some(&tokens[1])),
+        Err(CommandError::UnknownCommand { span, .. }) if span == (5..8)
+    ));
+}
@@ -0,0 +1,130 @@
+use std::time::Duration;
+
+use kmf_driver::layout::Layout;
+use kmf_middleware::config::{CONFIG_VERSION, Config, ScreenSize};
+use kmf_middleware::hotkeys::Modifier;
+use kmf_protocol::{SerializationMode, TransportType};
+
+#[test]
+fn empty_file_gives_defaults() {
+    let config = Config::from_toml("").unwrap();
+    assert_eq!(config, Config::default());
+    assert_eq!(config.version, CONFIG_VERSION);
+    assert_eq!(config.master.bind, "0.0.0.0:8081");
+    assert_eq!(config.master.transport, TransportType::Tcp);
+    assert_eq!(config.protocol.serialization, None);
+    assert_eq!(
+        config.slave.screen,
+        ScreenSize {
+            width: 1920,
+            height: 1080
+        }
+    );
+}
+
+#[test]
+fn parses_all_sections() {
+    let text = r#"
+        version = 1
+
+        [protocol]
+        serialization = "binary"
+
+        [master]
+        bind = "127.0.0.1:9000"
+        transport = "quic"
+        keyboard = "/dev/input/by-id/usb-kbd-event-kbd"
+        auto_attach = true
+        send_layout = "de"
+        screen = { width = 2560, height = 1440 }
+
+        [master.edge]
+        dwell_ms = 150
+        modifier = "ctrl"
+        button_guard = true
+
+        [slave]
+        server = "192.168.1.10:9000"
+        layout = "sk"
+        screen = { width = 1366, height = 768 }
+    "#;
+    let config = Config::from_toml(text).unwrap();
+
+    assert_eq!(
+        config.protocol.serialization,
+        Some(SerializationMode::Binary)
+    );
+    assert_eq!(config.master.transport, TransportType::Quic);
+    assert!(config.master.mouse.is_none());
+    assert_eq!(config.master.screen.unwrap().width, 2560);
+    let policy = config.master.edge.policy();
+    assert_eq!(policy.dwell, Duration::from_millis(150));
+    assert_eq!(policy.double_tap, None);
+    assert_eq!(policy.modifier, Some(Modifier::Ctrl));
+    assert!(policy.button_guard);
+    assert_eq!(config.slave.server.as_deref(), Some("192.168.1.10:9000"));
+    // layouts are compiled by libxkbcommon, not every machine has it
+    if Layout::named("us").is_some() {
+        assert_eq!(config.master.send_layout().unwrap().unwrap().name(), "de");
+        assert_eq!(config.slave.layout().unwrap().unwrap().name(), "sk");
+    }
+
+    assert_eq!(Config::from_toml(&config.to_toml()).unwrap(), config);
+}
+
+#[test]
+fn rejects_unknown_keys_and_types() {
+    let err = Config::from_toml("[master]\nbnd = \"0.0.0.0:1\"").unwrap_err();
+    assert!(err.contains("bnd"), "{}", err);
+
+    let err = Config::from_toml("[master]\ntransport = \"udp\"").unwrap_err();
+    assert!(err.contains("udp"), "{}", err);
+}
+
+#[test]
+fn validation_names_every_offending_key() {
+    let text = r#"
+        version = 2
+
+        [master]
+        bind = "localhost"
+        send_layout = "klingon"
+
+        [master.edge]
+        corner_size = -5
+
+        [slave]
+        screen = { width = 0, height = 768 }
+    "#;
+    let err = Config::from_toml(text).unwrap_err();
+    for key in [
+        "version",
+        "master.bind",
+        "master.send_layout",
+        "master.edge.corner_size",
+        "slave.screen",
+    ] {
+        assert!(err.contains(key), "{} missing in: {}", key, err);
+    }
+}
+
+#[test]
+fn save_refuses_invalid_config_and_load_reads_it_back() {
+    let path = std::env::temp_dir().join(format!("kmf-config-{}.toml", std::process::id()));
+    assert_eq!(Config::load(&path).unwrap(), Config::default());
+
+    let mut config = Config::default();
+    config.master.bind = "nowhere".to_string();
+    assert!(config.save(&path).is_err());
+    assert!(!path.exists());
+
+    config.master.bind = "127.0.0.1:8082".to_string();
+    config.slave.server = Some("10.0.0.2:8082".to_string());
+    config.save(&path).unwrap();
+    assert_eq!(Config::load(&path).unwrap(), config);
+
+    std::fs::write(&path, "[slave]\nlayout = \"xx\"").unwrap();
+    let err = Config::load(&path).unwrap_err();
+    assert!(err.starts_with("Invalid config in"), "{}", err);
+    std::fs::remove_file(&path).unwrap();
+}
@@ -0,0 +1,108 @@
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use kmf_middleware::control::{ControlServer, Handler, call, parse_params, unknown_method};
+use serde::Deserialize;
+use serde_json::{Value, json};
+
+fn socket(name: &str) -> PathBuf {
+    std::env::temp_dir()
+        .join(format!("kmf-control-{}", std::process::id()))
+        .join(format!("{}.sock", name))
+}
+
+#[derive(Deserialize)]
+struct AddParams {
+    a: i64,
+    b: i64,
+}
+
+fn handler() -> Arc<Handler> {
+    Arc::new(|method: &str, params: Value| match method {
+        "ping" => Ok(json!("pong")),
+        "add" => {
+            let AddParams { a, b } = parse_params(method, params)?;
+            Ok(json!(a + b))
+        }
+        _ => Err(unknown_method(method, &["ping", "add"])),
+    })
+}
+
+#[tokio::test]
+async fn test_call_round_trip() {
+    let path = socket("round-trip");
+    let _server = ControlServer::bind(&path, handler()).await.unwrap();
+
+    assert_eq!(call(&path, "ping", Value::Null).await, Ok(json!("pong")));
+    assert_eq!(
+        call(&path, "add", json!({ "a": 2, "b": 3 })).await,
+        Ok(json!(5))
+    );
+}
+
+#[tokio::test]
+async fn test_call_reports_handler_errors() {
+    let path = socket("errors");
+    let _server = ControlServer::bind(&path, handler()).await.unwrap();
+
+    let err = call(&path, "frobnicate", Value::Null).await.unwrap_err();
+    assert_eq!(err, "Unknown method 'frobnicate', known are: ping, add");
+
+    let err = call(&path, "add", json!({ "a": 2 })).await.unwrap_err();
+    assert!(err.starts_with("Invalid params for 'add'"), "{}", err);
+}
+
+#[tokio::test]
+async fn test_bind_refuses_a_served_socket_and_replaces_a_stale_one() {
+    let path = socket("stale");
+    let server = ControlServer::bind(&path, handler()).await.unwrap();
+    let err = ControlServer::bind(&path, handler()).await.unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::AddrInUse);
+
+    drop(server);
+    assert!(!path.exists(), "dropping the server removes its socket");
+
+    // a file left behind by a crashed process
+    std::fs::write(&path, b"").unwrap();
+    let _server = ControlServer::bind(&path, handler()).await.unwrap();
+    assert_eq!(call(&path, "ping", Value::Null).await, Ok(json!("pong")));
+}
+
+#[tokio::test]
+async fn test_bind_keeps_the_socket_private() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let path = std::env::temp_dir()
+        .join(format!("kmf-private-{}", std::process::id()))
+        .join("private.sock");
+    let _server = ControlServer::bind(&path, handler()).await.unwrap();
+
+    let mode =
+        |path: &std::path::Path| std::fs::metadata(path).unwrap().permissions().mode() & 0o777;
+    assert_eq!(mode(path.parent().unwrap()), 0o700);
+    assert_eq!(mode(&path), 0o600);
+}
+
+#[tokio::test]
+async fn test_bind_refuses_a_directory_of_another_user() {
+    let dir = std::env::temp_dir().join(format!("kmf-foreign-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    // only root can hand the directory to someone else
+    if std::os::unix::fs::chown(&dir, Some(65534), None).is_err() {
+        return;
+    }
+
+    let err = ControlServer::bind(&dir.join("master.sock"), handler())
+        .await
+        .unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::PermissionDenied);
+    assert!(err.to_string().contains("another user"), "{}", err);
+    std::fs::remove_dir(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn test_call_without_server_fails() {
+    let path = socket("missing");
+    let err = call(&path, "ping", Value::Null).await.unwrap_err();
+    assert!(err.starts_with("Cannot connect to"), "{}", err);
+}
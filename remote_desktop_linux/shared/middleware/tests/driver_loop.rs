@@ -0,0 +1,575 @@
+use std::collections::HashSet;
+use std::sync::atomic::AtomicBool;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+
+use std::time::Duration;
+
+use kmf_driver::driver::{DriverEvent, InputSink, InputSource, RecordingSink, ScriptedSource};
+use kmf_driver::event::MouseButton;
+use kmf_driver::layout::{KeyTranslator, Layout};
+use kmf_middleware::driver_loop::{DriverLoopContext, EdgePolicy, HeldInputPolicy};
+use kmf_middleware::event::driver_frame;
+use kmf_middleware::hotkeys::{HotkeyAction, Hotkeys, Modifier};
+use kmf_middleware::status::MasterStatus;
+use kmf_protocol::config::ServerMessage;
+use tokio::sync::broadcast;
+
+const KEY_LEFTSHIFT: u16 = 42;
+const KEY_LEFTCTRL: u16 = 29;
+const KEY_LEFTALT: u16 = 56;
+const KEY_A: u16 = 30;
+const KEY_2: u16 = 3;
+const KEY_Q: u16 = 16;
+const KEY_L: u16 = 38;
+const KEY_SCROLLLOCK: u16 = 70;
+const KEY_LEFT: u16 = 105;
+const KEY_END: u16 = 107;
+const KEY_DELETE: u16 = 111;
+const KEY_F12: u16 = 88;
+
+/// Writer keeping the pressed state like the kernel does for a uinput device,
+/// releases of inputs it never pressed are dropped instead of recorded
+#[derive(Clone, Default)]
+struct PressedSink {
+    recording: RecordingSink,
+    keys: Arc<Mutex<HashSet<u16>>>,
+    buttons: Arc<Mutex<HashSet<MouseButton>>>,
+}
+
+impl PressedSink {
+    fn nothing_held(&self) -> bool {
+        self.keys.lock().unwrap().is_empty() && self.buttons.lock().unwrap().is_empty()
+    }
+}
+
+impl InputSink for PressedSink {
+    fn simulate_frame(&mut self, frame: &[DriverEvent]) -> std::io::Result<()> {
+        let mut keys = self.keys.lock().unwrap();
+        let mut buttons = self.buttons.lock().unwrap();
+        let replayed = frame
+            .iter()
+            .filter(|event| match event {
+                DriverEvent::KeyboardPress(kp) if !kp.pressed => keys.remove(&kp.key),
+                DriverEvent::KeyboardPress(kp) => kp.repeat || keys.insert(kp.key),
+                DriverEvent::MouseClick(mc) if !mc.pressed => buttons.remove(&mc.button),
+                DriverEvent::MouseClick(mc) => buttons.insert(mc.button),
+                _ => true,
+            })
+            .copied()
+            .collect::<Vec<_>>();
+        self.recording.simulate_frame(&replayed)
+    }
+
+    fn block_inputs(&self) -> std::io::Result<()> {
+        self.recording.block_inputs()
+    }
+
+    fn unblock_inputs(&self) -> std::io::Result<()> {
+        self.recording.unblock_inputs()
+    }
+}
+
+struct Harness {
+    ctx: DriverLoopContext,
+    reader: ScriptedSource,
+    local: RecordingSink,
+    writer: PressedSink,
+    remote: broadcast::Receiver<ServerMessage>,
+}
+
+impl Harness {
+    /// Calibrated 100x100 master with the cursor next to the slave edge
+    fn new() -> Self {
+        let writer = PressedSink::default();
+        let (tx, remote) = broadcast::channel(64);
+        let mut ctx = DriverLoopContext::new(
+            writer.clone(),
+            tx,
+            Arc::new(Mutex::new(MasterStatus::default())),
+            Arc::new(AtomicBool::new(true)),
+            100,
+            100,
+        );
+        ctx.calibration_mode = false;
+        ctx.cursor_x = 99;
+        ctx.cursor_y = 50;
+
+        Self {
+            ctx,
+            reader: ScriptedSource::default(),
+            local: writer.recording.clone(),
+            writer,
+            remote,
+        }
+    }
+
+    /// Reads `event` from the source, returns whether the loop keeps running.
+    fn read(&mut self, event: DriverEvent) -> bool {
+        self.reader.push_frame(vec![event]);
+        let mut cx = Context::from_waker(Waker::noop());
+        let Poll::Ready(Some(frame)) = self.reader.poll_next_frame(&mut cx) else {
+            unreachable!("scripted frames are always ready");
+        };
+        self.ctx.handle_frame(&frame, &mut self.reader)
+    }
+
+    fn send(&mut self, event: DriverEvent) {
+        assert!(self.read(event));
+    }
+
+    /// Presses and releases `key` with the `held` keys down.
+    fn chord(&mut self, held: &[u16], key: u16) -> bool {
+        for k in held {
+            self.send(DriverEvent::keyboard_press(*k, true));
+        }
+        let running = self.read(DriverEvent::keyboard_press(key, true));
+        if running {
+            self.send(DriverEvent::keyboard_press(key, false));
+            for k in held.iter().rev() {
+                self.send(DriverEvent::keyboard_press(*k, false));
+            }
+        }
+        running
+    }
+
+    /// Events the slaves received, lock state syncs left out
+    fn remote_events(&mut self) -> Vec<DriverEvent> {
+        let mut events = Vec::new();
+        while let Ok(message) = self.remote.try_recv() {
+            if let ServerMessage::Input(frame) = message {
+                events.extend(driver_frame(frame));
+            }
+        }
+        events.retain(|e| !matches!(e, DriverEvent::LockState(_)));
+        events
+    }
+
+    fn enter_slave(&mut self) {
+        self.send(DriverEvent::mouse_move(5, 0, 0));
+        assert!(self.ctx.remote_mode);
+    }
+
+    fn leave_slave(&mut self) {
+        self.send(DriverEvent::mouse_move(-20, 0, 0));
+        assert!(!self.ctx.remote_mode);
+    }
+}
+
+#[test]
+fn drag_across_edge_releases_button() {
+    let mut h = Harness::new();
+
+    h.send(DriverEvent::mouse_click(MouseButton::Left, true));
+    h.enter_slave();
+
+    // released by the device itself, the writer never pressed the button
+    assert_eq!(
+        h.reader.released(),
+        [DriverEvent::mouse_click(MouseButton::Left, false)]
+    );
+    assert!(h.local.take().is_empty());
+    assert_eq!(h.remote_events(), vec![DriverEvent::mouse_move(5, 0, 0)]);
+
+    // the physical release belongs to the drag on the master, the slave never sees it
+    h.send(DriverEvent::mouse_click(MouseButton::Left, false));
+    assert!(h.remote_events().is_empty());
+}
+
+#[test]
+fn button_held_on_slave_is_released_when_leaving() {
+    let mut h = Harness::new();
+    h.enter_slave();
+    h.send(DriverEvent::mouse_click(MouseButton::Right, true));
+    h.remote_events();
+
+    h.leave_slave();
+    assert_eq!(
+        h.remote_events(),
+        vec![DriverEvent::mouse_click(MouseButton::Right, false)]
+    );
+
+    h.send(DriverEvent::mouse_click(MouseButton::Right, false));
+    assert!(h.local.take().concat().is_empty());
+    assert!(h.remote_events().is_empty());
+}
+
+#[test]
+fn modifiers_follow_the_focus() {
+    let mut h = Harness::new();
+
+    h.send(DriverEvent::keyboard_press(KEY_LEFTSHIFT, true));
+    h.enter_slave();
+
+    assert_eq!(
+        h.reader.released(),
+        [DriverEvent::keyboard_press(KEY_LEFTSHIFT, false)]
+    );
+    assert_eq!(
+        h.remote_events(),
+        vec![
+            DriverEvent::keyboard_press(KEY_LEFTSHIFT, true),
+            DriverEvent::mouse_move(5, 0, 0),
+        ]
+    );
+
+    h.send(DriverEvent::keyboard_press(KEY_LEFTSHIFT, false));
+    assert_eq!(
+        h.remote_events(),
+        vec![DriverEvent::keyboard_press(KEY_LEFTSHIFT, false)]
+    );
+}
+
+#[test]
+fn regular_keys_do_not_repeat_on_the_new_side() {
+    let mut h = Harness::new();
+
+    h.send(DriverEvent::keyboard_press(KEY_A, true));
+    h.enter_slave();
+    assert_eq!(
+        h.reader.released(),
+        [DriverEvent::keyboard_press(KEY_A, false)]
+    );
+
+    h.send(DriverEvent::keyboard_repeat(KEY_A));
+    h.send(DriverEvent::keyboard_press(KEY_A, false));
+    assert_eq!(h.remote_events(), vec![DriverEvent::mouse_move(5, 0, 0)]);
+
+    // released for real, the next press goes to the slave again
+    h.send(DriverEvent::keyboard_press(KEY_A, true));
+    assert_eq!(
+        h.remote_events(),
+        vec![DriverEvent::keyboard_press(KEY_A, true)]
+    );
+}
+
+#[test]
+fn policy_can_carry_everything() {
+    let mut h = Harness::new();
+    h.ctx.held_policy = HeldInputPolicy {
+        modifiers: true,
+        keys: true,
+        buttons: true,
+    };
+
+    h.send(DriverEvent::keyboard_press(KEY_A, true));
+    h.send(DriverEvent::mouse_click(MouseButton::Left, true));
+    h.enter_slave();
+
+    assert_eq!(
+        h.reader.released(),
+        [
+            DriverEvent::keyboard_press(KEY_A, false),
+            DriverEvent::mouse_click(MouseButton::Left, false),
+        ]
+    );
+    assert_eq!(
+        h.remote_events(),
+        vec![
+            DriverEvent::keyboard_press(KEY_A, true),
+            DriverEvent::mouse_click(MouseButton::Left, true),
+            DriverEvent::mouse_move(5, 0, 0),
+        ]
+    );
+}
+
+#[test]
+fn writer_releases_only_what_it_pressed() {
+    let mut h = Harness::new();
+    h.ctx.held_policy.buttons = true;
+
+    h.send(DriverEvent::keyboard_press(KEY_LEFTCTRL, true));
+    h.enter_slave();
+    h.send(DriverEvent::mouse_click(MouseButton::Left, true));
+
+    // both come back pressed through the writer, the desktop never saw them on the devices
+    h.leave_slave();
+    assert_eq!(
+        h.local.take().concat(),
+        vec![
+            DriverEvent::keyboard_press(KEY_LEFTCTRL, true),
+            DriverEvent::mouse_click(MouseButton::Left, true),
+        ]
+    );
+
+    // so their physical release has to go through the writer as well
+    h.send(DriverEvent::mouse_click(MouseButton::Left, false));
+    h.send(DriverEvent::keyboard_press(KEY_LEFTCTRL, false));
+    assert_eq!(
+        h.local.take().concat(),
+        vec![
+            DriverEvent::mouse_click(MouseButton::Left, false),
+            DriverEvent::keyboard_press(KEY_LEFTCTRL, false),
+        ]
+    );
+    assert!(h.writer.nothing_held());
+
+    // pressed on the desktop directly, nothing for the writer to release
+    h.send(DriverEvent::keyboard_press(KEY_A, true));
+    h.ctx.cursor_x = 99;
+    h.enter_slave();
+    h.leave_slave();
+    h.send(DriverEvent::keyboard_press(KEY_A, false));
+    assert!(h.local.take().is_empty());
+    assert!(h.writer.nothing_held());
+}
+
+fn mentions_key(events: &[DriverEvent], key: u16) -> bool {
+    events
+        .iter()
+        .any(|e| matches!(e, DriverEvent::KeyboardPress(kp) if kp.key == key))
+}
+
+#[test]
+fn hotkey_switches_screen_without_forwarding() {
+    let mut h = Harness::new();
+    h.enter_slave();
+
+    assert!(h.chord(&[KEY_LEFTCTRL, KEY_LEFTALT], KEY_LEFT));
+    assert!(!h.ctx.remote_mode);
+    assert_eq!((h.ctx.cursor_x, h.ctx.cursor_y), (50, 50));
+
+    assert!(!mentions_key(&h.remote_events(), KEY_LEFT));
+    assert!(!mentions_key(&h.local.take().concat(), KEY_LEFT));
+}
+
+#[test]
+fn focus_request_flushes_released_inputs() {
+    let mut h = Harness::new();
+    h.enter_slave();
+    h.send(DriverEvent::keyboard_press(KEY_A, true));
+    h.remote_events();
+
+    // e.g. `kmfctl switch 0`, outside of any input frame
+    h.ctx.focus_screen(0, &mut h.reader);
+    assert!(!h.ctx.remote_mode);
+    assert_eq!((h.ctx.cursor_x, h.ctx.cursor_y), (50, 50));
+    assert_eq!(
+        h.remote_events(),
+        vec![DriverEvent::keyboard_press(KEY_A, false)]
+    );
+
+    h.ctx.focus_screen(1, &mut h.reader);
+    assert!(h.ctx.remote_mode);
+    assert_eq!(h.ctx.cursor_x, 150);
+}
+
+#[test]
+fn ctrl_alt_del_goes_to_the_slaves() {
+    let mut h = Harness::new();
+
+    assert!(h.chord(&[KEY_LEFTCTRL, KEY_LEFTALT], KEY_END));
+    assert!(!h.ctx.remote_mode);
+    assert_eq!(
+        h.remote_events(),
+        vec![
+            DriverEvent::keyboard_press(KEY_LEFTCTRL, true),
+            DriverEvent::keyboard_press(KEY_LEFTALT, true),
+            DriverEvent::keyboard_press(KEY_DELETE, true),
+            DriverEvent::keyboard_press(KEY_DELETE, false),
+            DriverEvent::keyboard_press(KEY_LEFTALT, false),
+            DriverEvent::keyboard_press(KEY_LEFTCTRL, false),
+        ]
+    );
+}
+
+#[test]
+fn failsafe_stops_the_loop() {
+    let mut h = Harness::new();
+    assert!(!h.chord(&[KEY_LEFTCTRL, KEY_LEFTALT], KEY_Q));
+    assert!(!h.ctx.running_flag.load(std::sync::atomic::Ordering::SeqCst));
+}
+
+#[test]
+fn locked_cursor_stays_on_its_screen() {
+    let mut h = Harness::new();
+    assert!(h.chord(&[KEY_LEFTCTRL, KEY_LEFTALT], KEY_L));
+    assert!(h.ctx.cursor_locked());
+
+    h.send(DriverEvent::mouse_move(5, 0, 0));
+    assert!(!h.ctx.remote_mode);
+    assert_eq!(h.ctx.cursor_x, 99);
+    assert!(h.ctx.status_mutex.lock().unwrap().cursor_locked);
+}
+
+#[test]
+fn locked_cursor_stays_on_the_slave() {
+    let mut h = Harness::new();
+    h.enter_slave();
+    assert!(h.chord(&[], KEY_SCROLLLOCK));
+    assert!(h.ctx.cursor_locked());
+
+    // pushing against the master edge keeps moving the slave's cursor
+    h.send(DriverEvent::mouse_move(-30, 0, 0));
+    assert!(h.ctx.remote_mode);
+    assert_eq!(h.ctx.cursor_x, 100);
+    assert_eq!(
+        h.remote_events(),
+        vec![
+            DriverEvent::mouse_move(5, 0, 0),
+            DriverEvent::mouse_move(-30, 0, 0),
+        ]
+    );
+
+    // unlocked from the GUI, the edge works again
+    h.ctx
+        .cursor_lock
+        .store(false, std::sync::atomic::Ordering::SeqCst);
+    h.send(DriverEvent::mouse_move(-30, 0, 0));
+    assert!(!h.ctx.remote_mode);
+}
+
+#[test]
+fn hotkeys_can_be_replaced() {
+    let mut h = Harness::new();
+    let mut hotkeys = Hotkeys::empty();
+    hotkeys.bind("f12".parse().unwrap(), HotkeyAction::ToggleRemote);
+    *h.ctx.hotkeys.lock().unwrap() = hotkeys;
+
+    assert!(h.chord(&[], KEY_F12));
+    assert!(h.ctx.remote_mode);
+
+    // no failsafe bound anymore, the chord reaches the slave
+    assert!(h.chord(&[KEY_LEFTCTRL, KEY_LEFTALT], KEY_Q));
+    assert!(mentions_key(&h.remote_events(), KEY_Q));
+}
+
+/// Pushes against the master's right edge, returns whether the focus moved to the slave.
+fn push_right(h: &mut Harness) -> bool {
+    h.send(DriverEvent::mouse_move(5, 0, 0));
+    h.ctx.remote_mode
+}
+
+fn back_off(h: &mut Harness) {
+    h.send(DriverEvent::mouse_move(-10, 0, 0));
+    assert!(!h.ctx.remote_mode);
+}
+
+#[test]
+fn corners_are_dead_zones() {
+    let mut h = Harness::new();
+    h.ctx.edge_policy = EdgePolicy {
+        corner_size: 10,
+        ..EdgePolicy::default()
+    };
+
+    h.ctx.cursor_y = 5;
+    assert!(!push_right(&mut h));
+    assert_eq!(h.ctx.cursor_x, 99);
+
+    h.ctx.cursor_y = 95;
+    assert!(!push_right(&mut h));
+
+    h.ctx.cursor_y = 50;
+    assert!(push_right(&mut h));
+}
+
+#[test]
+fn modifier_has_to_be_held_to_cross() {
+    let mut h = Harness::new();
+    h.ctx.edge_policy.modifier = Some(Modifier::Ctrl);
+
+    assert!(!push_right(&mut h));
+    h.send(DriverEvent::keyboard_press(KEY_LEFTCTRL, true));
+    assert!(push_right(&mut h));
+}
+
+#[test]
+fn held_button_blocks_crossing() {
+    let mut h = Harness::new();
+    h.ctx.edge_policy.button_guard = true;
+
+    h.send(DriverEvent::mouse_click(MouseButton::Left, true));
+    assert!(!push_right(&mut h));
+
+    h.send(DriverEvent::mouse_click(MouseButton::Left, false));
+    assert!(push_right(&mut h));
+}
+
+#[test]
+fn dragging_crosses_despite_button_guard() {
+    let mut h = Harness::new();
+    h.ctx.edge_policy.button_guard = true;
+
+    // held at the edge, pushing does not make it a drag
+    h.send(DriverEvent::mouse_click(MouseButton::Left, true));
+    h.send(DriverEvent::mouse_move(50, 0, 0));
+    assert!(!push_right(&mut h));
+    h.send(DriverEvent::mouse_click(MouseButton::Left, false));
+
+    h.ctx.cursor_x = 50;
+    h.send(DriverEvent::mouse_click(MouseButton::Left, true));
+    h.send(DriverEvent::mouse_move(49, 0, 0));
+    assert!(!h.ctx.remote_mode);
+    assert!(push_right(&mut h));
+}
+
+#[test]
+fn dwell_time_delays_crossing() {
+    let mut h = Harness::new();
+    h.ctx.edge_policy.dwell = Duration::from_millis(30);
+
+    assert!(!push_right(&mut h));
+    std::thread::sleep(Duration::from_millis(40));
+    // leaving the edge starts over
+    back_off(&mut h);
+    h.ctx.cursor_x = 99;
+    assert!(!push_right(&mut h));
+    std::thread::sleep(Duration::from_millis(40));
+    assert!(push_right(&mut h));
+}
+
+#[test]
+fn double_tap_crosses_on_second_push() {
+    let mut h = Harness::new();
+    h.ctx.edge_policy.double_tap = Some(Duration::from_millis(30));
+
+    assert!(!push_right(&mut h));
+    assert!(!push_right(&mut h));
+    back_off(&mut h);
+    std::thread::sleep(Duration::from_millis(40));
+
+    // too slow, this push is the first tap again
+    h.ctx.cursor_x = 99;
+    assert!(!push_right(&mut h));
+    back_off(&mut h);
+    h.ctx.cursor_x = 99;
+    assert!(push_right(&mut h));
+}
+
+#[test]
+fn layout_aware_master_sends_characters() {
+    let mut h = Harness::new();
+    h.ctx.layout = Layout::fixture("sk");
+    h.enter_slave();
+    h.remote_events();
+
+    // Shift+2 types '2' on a Slovak keyboard, a US slave types it without Shift
+    assert!(h.chord(&[KEY_LEFTSHIFT], KEY_2));
+    let events = h.remote_events();
+    let mut us = KeyTranslator::new(Layout::fixture("us"));
+    assert_eq!(
+        us.translate(&events),
+        vec![
+            DriverEvent::keyboard_press(KEY_LEFTSHIFT, true),
+            DriverEvent::keyboard_press(KEY_LEFTSHIFT, false),
+            DriverEvent::keyboard_press(KEY_2, true),
+            DriverEvent::keyboard_press(KEY_2, false),
+            DriverEvent::keyboard_press(KEY_LEFTSHIFT, true),
+            DriverEvent::keyboard_press(KEY_LEFTSHIFT, false),
+        ]
+    );
+
+    // without a layout only key codes are sent
+    h.ctx.layout = None;
+    assert!(h.chord(&[KEY_LEFTSHIFT], KEY_2));
+    assert_eq!(
+        us.translate(&h.remote_events()),
+        vec![
+            DriverEvent::keyboard_press(KEY_LEFTSHIFT, true),
+            DriverEvent::keyboard_press(KEY_2, true),
+            DriverEvent::keyboard_press(KEY_2, false),
+            DriverEvent::keyboard_press(KEY_LEFTSHIFT, false),
+        ]
+    );
+}
@@ -0,0 +1,90 @@
+use std::collections::HashSet;
+
+use kmf_middleware::hotkeys::{Chord, HotkeyAction, Hotkeys, Modifier};
+
+const KEY_LEFTCTRL: u16 = 29;
+const KEY_RIGHTCTRL: u16 = 97;
+const KEY_LEFTALT: u16 = 56;
+const KEY_LEFTSHIFT: u16 = 42;
+const KEY_Q: u16 = 16;
+const KEY_T: u16 = 20;
+
+fn held(keys: &[u16]) -> HashSet<u16> {
+    keys.iter().copied().collect()
+}
+
+#[test]
+fn parses_and_prints_chords() {
+    let chord = "Alt + CTRL + q".parse::<Chord>().unwrap();
+    assert_eq!(
+        chord,
+        Chord::new(vec![Modifier::Ctrl, Modifier::Alt], KEY_Q)
+    );
+    assert_eq!(chord.to_string(), "ctrl+alt+q");
+
+    assert_eq!(
+        "super+pgdn".parse::<Chord>().unwrap().to_string(),
+        "meta+pagedown"
+    );
+}
+
+#[test]
+fn rejects_invalid_chords() {
+    assert!("ctrl+alt".parse::<Chord>().is_err());
+    assert!("ctrl+a+b".parse::<Chord>().is_err());
+    assert!("ctrl+nosuchkey".parse::<Chord>().is_err());
+    assert!("ctrl++q".parse::<Chord>().is_err());
+}
+
+#[test]
+fn modifiers_must_match_exactly() {
+    let chord = "ctrl+t".parse::<Chord>().unwrap();
+    assert!(chord.matches(KEY_T, &held(&[KEY_LEFTCTRL])));
+    assert!(chord.matches(KEY_T, &held(&[KEY_RIGHTCTRL])));
+    assert!(!chord.matches(KEY_T, &held(&[])));
+    assert!(!chord.matches(KEY_T, &held(&[KEY_LEFTCTRL, KEY_LEFTSHIFT])));
+    assert!(!chord.matches(KEY_Q, &held(&[KEY_LEFTCTRL])));
+}
+
+#[test]
+fn default_keeps_failsafe() {
+    let hotkeys = Hotkeys::default();
+    assert_eq!(
+        hotkeys.match_press(KEY_Q, &held(&[KEY_LEFTCTRL, KEY_LEFTALT])),
+        Some(HotkeyAction::StopMaster)
+    );
+    assert_eq!(hotkeys.match_press(KEY_Q, &held(&[KEY_LEFTCTRL])), None);
+}
+
+#[test]
+fn toml_round_trip() {
+    let text = r#"
+        "ctrl+alt+q" = "stop_master"
+        "meta+2" = "switch_screen:1"
+        "ctrl+shift+space" = "toggle_remote"
+    "#;
+    let hotkeys = Hotkeys::from_toml(text).unwrap();
+    assert_eq!(hotkeys.bindings().count(), 3);
+    assert_eq!(Hotkeys::from_toml(&hotkeys.to_toml()).unwrap(), hotkeys);
+
+    let error = Hotkeys::from_toml(r#""ctrl+q" = "launch_rockets""#).unwrap_err();
+    assert!(error.contains("launch_rockets"), "{error}");
+
+    let duplicate = r#"
+        "ctrl+q" = "stop_master"
+        "control+q" = "lock_cursor"
+    "#;
+    assert!(Hotkeys::from_toml(duplicate).is_err());
+}
+
+#[test]
+fn missing_file_gives_defaults() {
+    let path = std::env::temp_dir().join(format!("kmf-hotkeys-{}.toml", std::process::id()));
+    assert_eq!(Hotkeys::load(&path).unwrap(), Hotkeys::default());
+
+    let mut hotkeys = Hotkeys::empty();
+    hotkeys.bind("f12".parse().unwrap(), HotkeyAction::LockCursor);
+    hotkeys.save(&path).unwrap();
+    assert_eq!(Hotkeys::load(&path).unwrap(), hotkeys);
+    std::fs::remove_file(&path).unwrap();
+}
@@ -0,0 +1,43 @@
+use kmf_middleware::config::LogConfig;
+use kmf_middleware::logging::{self, LogFormat, LogOutput};
+use kmf_protocol::Packet;
+use kmf_protocol::input::{InputEvent, InputFrame};
+
+#[test]
+fn json_file_output_carries_spans_and_hides_keys() {
+    let path = std::env::temp_dir().join(format!("kmf-log-{}.jsonl", std::process::id()));
+    let config = LogConfig {
+        filter: "debug".to_string(),
+        output: LogOutput::File,
+        format: LogFormat::Json,
+        file: Some(path.clone()),
+        show_keys: false,
+    };
+    logging::init(&config, "kmf-test").unwrap();
+
+    let packet = Packet::Input(InputFrame::new(vec![InputEvent::Key {
+        code: 30,
+        pressed: true,
+        repeat: false,
+        keysym: Some(0x61),
+    }]));
+    tracing::info_span!("client", addr = "10.0.0.2:5000").in_scope(|| {
+        tracing::debug!(packet = ?packet.redacted(), "Received");
+        tracing::trace!("filtered out");
+    });
+
+    let text = std::fs::read_to_string(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    let lines: Vec<serde_json::Value> = text
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(lines.len(), 1, "{}", text);
+    assert_eq!(lines[0]["level"], "DEBUG");
+    assert_eq!(lines[0]["fields"]["message"], "Received");
+    assert_eq!(lines[0]["spans"][0]["name"], "client");
+    assert_eq!(lines[0]["span"]["addr"], "10.0.0.2:5000");
+    assert!(text.contains("<redacted>"), "{}", text);
+    assert!(!text.contains("code: 30"), "{}", text);
+    assert!(!text.contains("keysym"), "{}", text);
+}
@@ -0,0 +1,89 @@
+use std::time::{Duration, SystemTime};
+
+use kmf_middleware::metrics::{Metrics, metrics};
+use kmf_protocol::{AsyncStream, Packet};
+
+#[test]
+fn histograms_summarize_latency_and_queue_depth() {
+    let metrics = Metrics::new();
+    let now = SystemTime::now();
+    for ms in [1, 2, 3, 40] {
+        metrics.record_replayed(Some(now - Duration::from_millis(ms)));
+    }
+    // unstamped frames and a slave clock behind the master's are left out
+    metrics.record_replayed(None);
+    metrics.record_replayed(Some(now + Duration::from_secs(5)));
+    metrics.record_acknowledged(Some(now - Duration::from_millis(5)));
+    for depth in [0, 0, 1, 7] {
+        metrics.record_queue_depth(depth);
+    }
+    metrics.record_dropped();
+
+    let snapshot = metrics.snapshot();
+    assert_eq!(snapshot.latency_us.count, 4);
+    assert!((2_000..3_100).contains(&snapshot.latency_us.p50));
+    assert!(snapshot.latency_us.max >= 40_000);
+    assert_eq!(snapshot.round_trip_us.count, 1);
+    assert_eq!(snapshot.queue_depth.count, 4);
+    assert_eq!(snapshot.queue_depth.max, 7);
+    assert_eq!(snapshot.dropped, 1);
+
+    let text = snapshot.to_prometheus();
+    for line in [
+        "# TYPE kmf_input_latency_seconds histogram",
+        "kmf_input_latency_seconds_bucket{le=\"0.00025\"} 0",
+        "kmf_input_latency_seconds_bucket{le=\"0.025\"} 3",
+        "kmf_input_latency_seconds_bucket{le=\"+Inf\"} 4",
+        "kmf_input_latency_seconds_count 4",
+        "kmf_broadcast_queue_depth_bucket{le=\"0\"} 2",
+        "kmf_broadcast_queue_depth_bucket{le=\"5\"} 3",
+        "# TYPE kmf_broadcast_queue_depth_max gauge",
+        "kmf_broadcast_queue_depth_max 7",
+    ] {
+        assert!(text.contains(line), "{} missing in {}", line, text);
+    }
+    assert!(!text.contains("quantile"), "{}", text);
+    assert!(text.contains("kmf_input_dropped_total 1"), "{}", text);
+}
+
+#[tokio::test]
+async fn peers_count_their_traffic_until_dropped() {
+    let (local, mut remote) = tokio::io::duplex(1024);
+    let peer = metrics().peer("10.0.0.9:4000");
+    let mut stream = peer.count(Box::new(local) as Box<dyn AsyncStream>);
+
+    kmf_protocol::send(Packet::Ok, &mut stream).await.unwrap();
+    assert!(matches!(
+        kmf_protocol::receive(&mut remote).await,
+        Ok(Packet::Ok)
+    ));
+    kmf_protocol::send(Packet::EdgeL, &mut remote)
+        .await
+        .unwrap();
+    kmf_protocol::receive(&mut stream).await.unwrap();
+    peer.record_lagged(3);
+
+    let snapshot = metrics().snapshot();
+    let entry = snapshot
+        .peers
+        .iter()
+        .find(|entry| entry.address == "10.0.0.9:4000")
+        .unwrap();
+    assert_eq!((entry.bytes_sent, entry.bytes_received), (1, 1));
+    assert_eq!(entry.lagged, 3);
+    assert!(
+        snapshot
+            .to_prometheus()
+            .contains("kmf_peer_bytes_sent_total{peer=\"10.0.0.9:4000\"} 1")
+    );
+
+    drop(stream);
+    drop(peer);
+    assert!(
+        metrics()
+            .snapshot()
+            .peers
+            .iter()
+            .all(|entry| entry.address != "10.0.0.9:4000")
+    );
+}
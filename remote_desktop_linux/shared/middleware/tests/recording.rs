@@ -0,0 +1,102 @@
+use std::io::Cursor;
+use std::path::Path;
+use std::time::Duration;
+
+use kmf_driver::event::{DriverEvent, KeyboardPress, LockState, MouseButton};
+use kmf_middleware::recording::{Playback, RecordedFrame, Recorder, RecordingFormat};
+
+fn frames() -> Vec<RecordedFrame> {
+    vec![
+        RecordedFrame {
+            at: Duration::from_micros(0),
+            events: vec![DriverEvent::mouse_move(3, -2, 0)],
+        },
+        RecordedFrame {
+            at: Duration::from_millis(16),
+            events: vec![
+                DriverEvent::mouse_move(400, 0, 1),
+                DriverEvent::mouse_click(MouseButton::Left, true),
+            ],
+        },
+        RecordedFrame {
+            at: Duration::from_millis(250),
+            events: vec![
+                DriverEvent::KeyboardPress(KeyboardPress {
+                    key: 30,
+                    pressed: true,
+                    repeat: false,
+                    keysym: Some(0x61),
+                }),
+                DriverEvent::keyboard_repeat(30),
+                DriverEvent::LockState(LockState {
+                    caps_lock: true,
+                    num_lock: false,
+                    scroll_lock: false,
+                }),
+            ],
+        },
+    ]
+}
+
+fn record(format: RecordingFormat) -> Vec<u8> {
+    let mut recorder = Recorder::new(Vec::new(), format).unwrap();
+    for frame in frames() {
+        recorder.record(frame.at, &frame.events).unwrap();
+    }
+    recorder.finish().unwrap()
+}
+
+#[test]
+fn both_formats_round_trip() {
+    for format in [RecordingFormat::JsonLines, RecordingFormat::Binary] {
+        let data = record(format);
+        let playback = Playback::new(Cursor::new(data)).unwrap();
+        assert_eq!(playback.format(), format);
+        let read: Vec<_> = playback.map(Result::unwrap).collect();
+        assert_eq!(read, frames(), "{format:?}");
+    }
+}
+
+#[test]
+fn json_lines_are_one_frame_per_line() {
+    let data = String::from_utf8(record(RecordingFormat::JsonLines)).unwrap();
+    assert_eq!(data.lines().count(), 3);
+    assert!(data.starts_with(r#"{"at_us":0,"events":[{"#));
+
+    // blank lines, e.g. from hand edits, are skipped
+    let edited = data.replace('\n', "\n\n");
+    assert_eq!(Playback::new(Cursor::new(edited)).unwrap().count(), 3);
+}
+
+#[test]
+fn binary_is_smaller_and_rejects_damage() {
+    let binary = record(RecordingFormat::Binary);
+    assert!(binary.len() * 4 < record(RecordingFormat::JsonLines).len());
+
+    let truncated = &binary[..binary.len() - 1];
+    let mut playback = Playback::new(Cursor::new(truncated)).unwrap();
+    assert!(playback.nth(2).unwrap().is_err());
+
+    let mut future = binary.clone();
+    future[6] = 99;
+    assert!(Playback::new(Cursor::new(future)).is_err());
+}
+
+#[test]
+fn formats_and_speed() {
+    assert_eq!(
+        RecordingFormat::for_path(Path::new("demo.jsonl")),
+        RecordingFormat::JsonLines
+    );
+    assert_eq!(
+        RecordingFormat::for_path(Path::new("demo.kmfrec")),
+        RecordingFormat::Binary
+    );
+    assert_eq!("JSON".parse(), Ok(RecordingFormat::JsonLines));
+    assert!("xml".parse::<RecordingFormat>().is_err());
+
+    let frame = &frames()[2];
+    assert_eq!(frame.replay_at(1.0), Duration::from_millis(250));
+    assert_eq!(frame.replay_at(2.0), Duration::from_millis(125));
+    assert_eq!(frame.replay_at(0.5), Duration::from_millis(500));
+}
@@ -0,0 +1,199 @@
+use std::time::Duration;
+
+use kmf_driver::layout::Layout;
+use kmf_middleware::script::{Script, Step, parse_duration};
+use kmf_protocol::config::ServerMessage;
+use kmf_protocol::input::{Button, InputEvent};
+
+const KEY_LEFTCTRL: u16 = 29;
+const KEY_LEFTSHIFT: u16 = 42;
+const KEY_T: u16 = 20;
+const KEY_H: u16 = 35;
+const KEY_1: u16 = 2;
+const KEY_ENTER: u16 = 28;
+
+fn us() -> Layout {
+    Layout::fixture("us").unwrap()
+}
+
+fn parse(source: &str) -> Vec<Step> {
+    Script::parse(source, &us()).unwrap().steps()
+}
+
+/// Events of every frame the steps send, sleeps dropped
+fn events(steps: &[Step]) -> Vec<Vec<InputEvent>> {
+    steps
+        .iter()
+        .filter_map(|step| match step {
+            Step::Send(ServerMessage::Input(frame)) => Some(frame.events.clone()),
+            _ => None,
+        })
+        .collect()
+}
+
+fn key(code: u16, pressed: bool) -> InputEvent {
+    InputEvent::Key {
+        code,
+        pressed,
+        repeat: false,
+        keysym: None,
+    }
+}
+
+#[test]
+fn chords_are_tapped_with_their_modifiers() {
+    let steps = parse("key ctrl+shift+t\nkey enter down\nkey 28 up");
+    assert_eq!(
+        events(&steps),
+        vec![
+            vec![
+                key(KEY_LEFTCTRL, true),
+                key(KEY_LEFTSHIFT, true),
+                key(KEY_T, true),
+                key(KEY_T, false),
+                key(KEY_LEFTSHIFT, false),
+                key(KEY_LEFTCTRL, false),
+            ],
+            vec![key(KEY_ENTER, true)],
+            vec![key(KEY_ENTER, false)],
+        ]
+    );
+}
+
+#[test]
+fn type_presses_shift_for_capitals_and_sends_keysyms() {
+    let steps = parse(r#"type "H!\n""#);
+    let typed = |code, pressed, c: char| InputEvent::Key {
+        code,
+        pressed,
+        repeat: false,
+        keysym: Some(c as u32),
+    };
+    assert_eq!(
+        events(&steps),
+        vec![
+            vec![
+                key(KEY_LEFTSHIFT, true),
+                typed(KEY_H, true, 'H'),
+                typed(KEY_H, false, 'H'),
+                key(KEY_LEFTSHIFT, false),
+            ],
+            vec![
+                key(KEY_LEFTSHIFT, true),
+                typed(KEY_1, true, '!'),
+                typed(KEY_1, false, '!'),
+                key(KEY_LEFTSHIFT, false),
+            ],
+            vec![key(KEY_ENTER, true), key(KEY_ENTER, false)],
+        ]
+    );
+}
+
+#[test]
+fn repeat_blocks_unroll_with_sleeps() {
+    let source = "
+        # drag back and forth
+        repeat 2 {
+            click left down
+            repeat 3 {
+                move 10 0   # right
+            }
+            sleep 200ms
+            click left up
+        }
+    ";
+    let steps = parse(source);
+    assert_eq!(steps.len(), 2 * (1 + 3 + 1 + 1));
+    assert!(matches!(steps[4], Step::Sleep(d) if d == Duration::from_millis(200)));
+    assert_eq!(
+        events(&steps)[0],
+        vec![InputEvent::MouseButton {
+            button: Button::Left,
+            pressed: true
+        }]
+    );
+    assert_eq!(
+        events(&parse("click right")),
+        vec![vec![
+            InputEvent::MouseButton {
+                button: Button::Right,
+                pressed: true
+            },
+            InputEvent::MouseButton {
+                button: Button::Right,
+                pressed: false
+            },
+        ]]
+    );
+}
+
+#[test]
+fn errors_point_at_line_and_column() {
+    let error = |source: &str| Script::parse(source, &us()).unwrap_err();
+
+    let e = error("move 1 2\nkey ctrl+bogus");
+    assert_eq!((e.line, e.column), (2, 5));
+    assert_eq!(e.to_string(), "2:5: Unknown key 'bogus'");
+
+    let e = error("  move 1 x");
+    assert_eq!((e.line, e.column), (1, 10));
+
+    let e = error("sleep 5 minutes");
+    assert_eq!((e.line, e.column), (1, 9));
+
+    let e = error("type \"ľ\"");
+    assert!(e.error.to_string().contains("us layout"));
+
+    let e = error("type \"open");
+    assert_eq!(
+        (e.line, e.column, e.error.to_string().as_str()),
+        (1, 6, "Unterminated string")
+    );
+
+    let e = error("}\n");
+    assert_eq!((e.line, e.column), (1, 1));
+
+    let e = error("frobnicate");
+    assert_eq!(
+        e.error.to_string(),
+        "Unknown command 'frobnicate', see 'help'"
+    );
+
+    let e = error("move 1 2\n  repeat 3 {\n move 1 1");
+    assert!(e.is_incomplete());
+    assert_eq!((e.line, e.column), (2, 3));
+    assert!(!error("move").is_incomplete());
+}
+
+#[test]
+fn durations_and_script_files() {
+    assert_eq!(parse_duration("200ms"), Some(Duration::from_millis(200)));
+    assert_eq!(parse_duration("1.5s"), Some(Duration::from_millis(1500)));
+    assert_eq!(parse_duration("200"), None);
+    assert_eq!(parse_duration("-1s"), None);
+
+    let dir = std::env::temp_dir().join(format!("kmf-script-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("inner.kmf"), "move 1 1\nmove 2 2\n").unwrap();
+    std::fs::write(
+        dir.join("outer.kmf"),
+        "repeat 2 {\nrun inner.kmf\n}\nquit\n",
+    )
+    .unwrap();
+    std::fs::write(dir.join("broken.kmf"), "move 1 1\nclick sideways\n").unwrap();
+
+    let steps = Script::load(&dir.join("outer.kmf"), &us()).unwrap().steps();
+    assert_eq!(events(&steps).len(), 4);
+    assert!(matches!(
+        steps.last(),
+        Some(Step::Send(ServerMessage::Quit))
+    ));
+
+    let e = Script::load(&dir.join("broken.kmf"), &us()).unwrap_err();
+    assert!(
+        e.ends_with("broken.kmf:2:7: Unknown mouse button 'sideways'"),
+        "{e}"
+    );
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,146 @@
+//! Compares the wire encodings of input frames on typical event streams:
+//! JSON, the former MessagePack+base64-in-JSON action encoding and the compact
+//! binary layout of `InputFrame::encode`.
+//!
+//! Run with `cargo bench -p kmf-protocol`.
+
+use base64::Engine;
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use kmf_protocol::serialization::{
+    deserialize_bin, deserialize_json, serialize_bin, serialize_json,
+};
+use kmf_protocol::{Button, InputEvent, InputFrame};
+
+/// Pointer motion as a mouse reports it: one small relative move per frame.
+fn mouse_motion() -> Vec<InputFrame> {
+    (0..1000)
+        .map(|i| {
+            InputFrame::from(InputEvent::MouseMove {
+                x: (i % 7) - 3,
+                y: (i % 5) - 2,
+                wheel: 0,
+            })
+        })
+        .collect()
+}
+
+/// Typing with layout translation on: press and release of each key carry a keysym.
+fn typing() -> Vec<InputFrame> {
+    "the quick brown fox jumps over the lazy dog"
+        .chars()
+        .cycle()
+        .take(500)
+        .flat_map(|c| {
+            [true, false].map(|pressed| {
+                InputFrame::from(InputEvent::Key {
+                    code: 30,
+                    pressed,
+                    repeat: false,
+                    keysym: Some(c as u32),
+                })
+            })
+        })
+        .collect()
+}
+
+/// Drags: a click followed by diagonal moves, occasionally fast enough to leave `i8`.
+fn drag() -> Vec<InputFrame> {
+    (0..1000)
+        .map(|i| {
+            let mut events = vec![InputEvent::MouseMove {
+                x: if i % 10 == 0 { 300 } else { 4 },
+                y: -3,
+                wheel: 0,
+            }];
+            if i % 50 == 0 {
+                events.push(InputEvent::MouseButton {
+                    button: Button::Left,
+                    pressed: i % 100 == 0,
+                });
+            }
+            InputFrame::new(events)
+        })
+        .collect()
+}
+
+/// How actions were sent before `InputFrame`: MessagePack, base64 encoded into a
+/// JSON string, which the packet layer then serialized once more.
+fn legacy_encode(frame: &InputFrame) -> Vec<u8> {
+    let packed = serialize_bin(frame);
+    let value = serde_json::Value::String(base64::engine::general_purpose::STANDARD.encode(packed));
+    serialize_bin(&value)
+}
+
+fn legacy_decode(data: &[u8]) -> InputFrame {
+    let value: serde_json::Value = deserialize_bin(data).unwrap();
+    let packed = base64::engine::general_purpose::STANDARD
+        .decode(value.as_str().unwrap())
+        .unwrap();
+    deserialize_bin(&packed).unwrap()
+}
+
+type Encoder = fn(&InputFrame) -> Vec<u8>;
+type Decoder = fn(&[u8]) -> InputFrame;
+
+const FORMATS: [(&str, Encoder, Decoder); 3] = [
+    (
+        "json",
+        |f| serialize_json(f),
+        |d| deserialize_json(d).unwrap(),
+    ),
+    ("msgpack+base64", legacy_encode, legacy_decode),
+    ("compact", InputFrame::encode, |d| {
+        InputFrame::decode(d).unwrap()
+    }),
+];
+
+fn streams() -> [(&'static str, Vec<InputFrame>); 3] {
+    [
+        ("mouse_motion", mouse_motion()),
+        ("typing", typing()),
+        ("drag", drag()),
+    ]
+}
+
+fn encoding(c: &mut Criterion) {
+    for (stream, frames) in streams() {
+        let mut group = c.benchmark_group(format!("encode/{stream}"));
+        for (format, encode, _) in FORMATS {
+            let bytes: usize = frames.iter().map(|f| encode(f).len()).sum();
+            println!(
+                "{stream}/{format}: {bytes} bytes for {} frames",
+                frames.len()
+            );
+            group.throughput(Throughput::Elements(frames.len() as u64));
+            group.bench_function(BenchmarkId::from_parameter(format), |b| {
+                b.iter(|| {
+                    for frame in &frames {
+                        black_box(encode(black_box(frame)));
+                    }
+                });
+            });
+        }
+        group.finish();
+    }
+}
+
+fn decoding(c: &mut Criterion) {
+    for (stream, frames) in streams() {
+        let mut group = c.benchmark_group(format!("decode/{stream}"));
+        for (format, encode, decode) in FORMATS {
+            let encoded: Vec<Vec<u8>> = frames.iter().map(encode).collect();
+            group.throughput(Throughput::Elements(frames.len() as u64));
+            group.bench_function(BenchmarkId::from_parameter(format), |b| {
+                b.iter(|| {
+                    for data in &encoded {
+                        black_box(decode(black_box(data)));
+                    }
+                });
+            });
+        }
+        group.finish();
+    }
+}
+
+criterion_group!(benches, encoding, decoding);
+criterion_main!(benches);
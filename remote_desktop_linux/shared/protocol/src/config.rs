@@ -1,5 +1,6 @@
 use serde::{Deserialize, Serialize};
-use serde_json::Value;
+
+use crate::input::InputFrame;
 
 /// Protocol version for compatibility checks
 /// Used in the ServerHello
@@ -61,8 +62,8 @@ impl PeerInfo {
 /// Each slave handler then translates these into protocol packets.
 #[derive(Clone, Debug)]
 pub enum ServerMessage {
-    /// An action to be executed on slaves
-    Action(Value),
+    /// Input to be replayed on slaves
+    Input(InputFrame),
     /// A file to be transferred to slaves
     File { path: String },
     /// Signal to disconnect all slaves gracefully
@@ -0,0 +1,357 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Version of the input event schema, carried by every [`InputFrame`].
+/// Bumped whenever [`InputEvent`] changes incompatibly.
+pub const INPUT_VERSION: u16 = 1;
+
+/// Mouse button
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Button {
+    Left,
+    Right,
+    Middle,
+}
+
+impl FromStr for Button {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "left" => Ok(Self::Left),
+            "right" => Ok(Self::Right),
+            "middle" => Ok(Self::Middle),
+            _ => Err(format!("Unknown mouse button '{}'", s)),
+        }
+    }
+}
+
+impl fmt::Display for Button {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Left => "left",
+            Self::Right => "right",
+            Self::Middle => "middle",
+        })
+    }
+}
+
+/// One input event as sent from the master to its slaves
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum InputEvent {
+    /// Relative pointer motion and wheel steps
+    MouseMove {
+        x: i32,
+        y: i32,
+        wheel: i32,
+    },
+    MouseButton {
+        button: Button,
+        pressed: bool,
+    },
+    /// Key by its Linux input event code (`KEY_*`)
+    Key {
+        code: u16,
+        pressed: bool,
+        /// Autorepeat of an already held key, `pressed` stays `true`
+        #[serde(default)]
+        repeat: bool,
+        /// Keysym the master's layout typed, `None` replays the raw key code
+        #[serde(default)]
+        keysym: Option<u32>,
+    },
+    /// Caps/Num/Scroll Lock state the slave switches to when it gets focus
+    LockState {
+        caps_lock: bool,
+        num_lock: bool,
+        scroll_lock: bool,
+    },
+}
+
+/// Events the master's hardware reported in one `SYN_REPORT` frame, replayed as one batch
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InputFrame {
+    /// Schema version, [`INPUT_VERSION`] when sent by this build
+    pub version: u16,
+    pub events: Vec<InputEvent>,
+    /// When the master read the frame from its devices, in microseconds since the Unix
+    /// epoch. `None` for frames typed on the console or played from scripts
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timestamp_us: Option<u64>,
+}
+
+impl InputFrame {
+    #[must_use]
+    pub fn new(events: Vec<InputEvent>) -> Self {
+        Self {
+            version: INPUT_VERSION,
+            events,
+            timestamp_us: None,
+        }
+    }
+
+    /// The frame stamped with the time its events were read.
+    #[must_use]
+    pub fn captured_at(self, time: SystemTime) -> Self {
+        let timestamp_us = time
+            .duration_since(UNIX_EPOCH)
+            .map(|since| since.as_micros() as u64)
+            .ok();
+        Self {
+            timestamp_us,
+            ..self
+        }
+    }
+
+    /// When the events were read, `None` for unstamped frames.
+    #[must_use]
+    pub fn captured(&self) -> Option<SystemTime> {
+        Some(UNIX_EPOCH + Duration::from_micros(self.timestamp_us?))
+    }
+
+    /// Fails for frames of a schema version this build does not understand.
+    pub fn check_version(&self) -> Result<(), String> {
+        if self.version == INPUT_VERSION {
+            Ok(())
+        } else {
+            Err(format!(
+                "Unsupported input event version {}, expected {}",
+                self.version, INPUT_VERSION
+            ))
+        }
+    }
+}
+
+impl From<InputEvent> for InputFrame {
+    fn from(event: InputEvent) -> Self {
+        Self::new(vec![event])
+    }
+}
+
+/// Event tags of the compact encoding
+mod tag {
+    pub const MOVE_SMALL: u8 = 0;
+    pub const MOVE: u8 = 1;
+    pub const BUTTON: u8 = 2;
+    pub const KEY: u8 = 3;
+    pub const KEY_KEYSYM: u8 = 4;
+    pub const LOCK_STATE: u8 = 5;
+    pub const TIMESTAMP: u8 = 6;
+}
+
+const PRESSED: u8 = 0b001;
+const REPEAT: u8 = 0b010;
+const CAPS_LOCK: u8 = 0b001;
+const NUM_LOCK: u8 = 0b010;
+const SCROLL_LOCK: u8 = 0b100;
+
+impl InputFrame {
+    /// Compact fixed-layout encoding used by the binary serialization mode.
+    ///
+    /// `[version:u16]` followed by the events, each a tag byte and its fields in
+    /// big-endian order. A timestamped frame starts with a timestamp pseudo event:
+    ///
+    /// | Tag | Event                    | Fields                             | Size |
+    /// |-----|--------------------------|------------------------------------|------|
+    /// | 0   | `MouseMove` in `i8` range| `x:i8 y:i8 wheel:i8`               | 4    |
+    /// | 1   | `MouseMove`              | `x:i32 y:i32 wheel:i32`            | 13   |
+    /// | 2   | `MouseButton`            | `button:u8 flags:u8`               | 3    |
+    /// | 3   | `Key`                    | `code:u16 flags:u8`                | 4    |
+    /// | 4   | `Key` with a keysym      | `code:u16 flags:u8 keysym:u32`     | 8    |
+    /// | 5   | `LockState`              | `locks:u8`                         | 2    |
+    /// | 6   | timestamp                | `timestamp_us:u64`                 | 9    |
+    #[must_use]
+    pub fn encode(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(2 + 4 * self.events.len());
+        self.encode_into(&mut buf);
+        buf
+    }
+
+    /// Appends the [`encode`](Self::encode)d frame to `buf`.
+    pub fn encode_into(&self, buf: &mut Vec<u8>) {
+        buf.extend_from_slice(&self.version.to_be_bytes());
+        if let Some(timestamp) = self.timestamp_us {
+            buf.push(tag::TIMESTAMP);
+            buf.extend_from_slice(&timestamp.to_be_bytes());
+        }
+        for event in &self.events {
+            event.encode_into(buf);
+        }
+    }
+
+    /// Decodes a frame written by [`encode`](Self::encode), failing on truncated
+    /// data, unknown tags or a schema version this build does not understand.
+    pub fn decode(data: &[u8]) -> Result<Self, String> {
+        let mut reader = Reader(data);
+        let version = reader.u16()?;
+        let frame = Self {
+            version,
+            events: Vec::new(),
+            timestamp_us: None,
+        };
+        frame.check_version()?;
+        let timestamp_us = if reader.0.first() == Some(&tag::TIMESTAMP) {
+            reader.u8()?;
+            Some(reader.u64()?)
+        } else {
+            None
+        };
+
+        let mut events = Vec::with_capacity(data.len() / 4);
+        while !reader.0.is_empty() {
+            events.push(InputEvent::decode(&mut reader)?);
+        }
+        Ok(Self {
+            events,
+            timestamp_us,
+            ..frame
+        })
+    }
+}
+
+impl InputEvent {
+    fn encode_into(&self, buf: &mut Vec<u8>) {
+        match *self {
+            Self::MouseMove { x, y, wheel } => {
+                match (i8::try_from(x), i8::try_from(y), i8::try_from(wheel)) {
+                    (Ok(x), Ok(y), Ok(wheel)) => {
+                        buf.extend_from_slice(&[tag::MOVE_SMALL, x as u8, y as u8, wheel as u8]);
+                    }
+                    _ => {
+                        buf.push(tag::MOVE);
+                        buf.extend_from_slice(&x.to_be_bytes());
+                        buf.extend_from_slice(&y.to_be_bytes());
+                        buf.extend_from_slice(&wheel.to_be_bytes());
+                    }
+                }
+            }
+            Self::MouseButton { button, pressed } => {
+                let button = match button {
+                    Button::Left => 0,
+                    Button::Right => 1,
+                    Button::Middle => 2,
+                };
+                buf.extend_from_slice(&[tag::BUTTON, button, flag(pressed, PRESSED)]);
+            }
+            Self::Key {
+                code,
+                pressed,
+                repeat,
+                keysym,
+            } => {
+                buf.push(if keysym.is_some() {
+                    tag::KEY_KEYSYM
+                } else {
+                    tag::KEY
+                });
+                buf.extend_from_slice(&code.to_be_bytes());
+                buf.push(flag(pressed, PRESSED) | flag(repeat, REPEAT));
+                if let Some(keysym) = keysym {
+                    buf.extend_from_slice(&keysym.to_be_bytes());
+                }
+            }
+            Self::LockState {
+                caps_lock,
+                num_lock,
+                scroll_lock,
+            } => {
+                let locks = flag(caps_lock, CAPS_LOCK)
+                    | flag(num_lock, NUM_LOCK)
+                    | flag(scroll_lock, SCROLL_LOCK);
+                buf.extend_from_slice(&[tag::LOCK_STATE, locks]);
+            }
+        }
+    }
+
+    fn decode(reader: &mut Reader<'_>) -> Result<Self, String> {
+        Ok(match reader.u8()? {
+            tag::MOVE_SMALL => Self::MouseMove {
+                x: i32::from(reader.u8()? as i8),
+                y: i32::from(reader.u8()? as i8),
+                wheel: i32::from(reader.u8()? as i8),
+            },
+            tag::MOVE => Self::MouseMove {
+                x: reader.u32()? as i32,
+                y: reader.u32()? as i32,
+                wheel: reader.u32()? as i32,
+            },
+            tag::BUTTON => {
+                let button = match reader.u8()? {
+                    0 => Button::Left,
+                    1 => Button::Right,
+                    2 => Button::Middle,
+                    other => return Err(format!("Unknown mouse button {}", other)),
+                };
+                Self::MouseButton {
+                    button,
+                    pressed: reader.u8()? & PRESSED != 0,
+                }
+            }
+            tag @ (tag::KEY | tag::KEY_KEYSYM) => {
+                let code = reader.u16()?;
+                let flags = reader.u8()?;
+                let keysym = if tag == tag::KEY_KEYSYM {
+                    Some(reader.u32()?)
+                } else {
+                    None
+                };
+                Self::Key {
+                    code,
+                    pressed: flags & PRESSED != 0,
+                    repeat: flags & REPEAT != 0,
+                    keysym,
+                }
+            }
+            tag::LOCK_STATE => {
+                let locks = reader.u8()?;
+                Self::LockState {
+                    caps_lock: locks & CAPS_LOCK != 0,
+                    num_lock: locks & NUM_LOCK != 0,
+                    scroll_lock: locks & SCROLL_LOCK != 0,
+                }
+            }
+            other => return Err(format!("Unknown input event tag {}", other)),
+        })
+    }
+}
+
+const fn flag(set: bool, bit: u8) -> u8 {
+    if set {
+        bit
+    } else {
+        0
+    }
+}
+
+/// Cursor over an encoded frame
+struct Reader<'a>(&'a [u8]);
+
+impl Reader<'_> {
+    fn take<const N: usize>(&mut self) -> Result<[u8; N], String> {
+        let (head, rest) = self
+            .0
+            .split_first_chunk::<N>()
+            .ok_or_else(|| "Truncated input frame".to_string())?;
+        self.0 = rest;
+        Ok(*head)
+    }
+
+    fn u8(&mut self) -> Result<u8, String> {
+        Ok(self.take::<1>()?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, String> {
+        self.take().map(u16::from_be_bytes)
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        self.take().map(u32::from_be_bytes)
+    }
+
+    fn u64(&mut self) -> Result<u64, String> {
+        self.take().map(u64::from_be_bytes)
+    }
+}
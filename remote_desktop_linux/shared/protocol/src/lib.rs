@@ -1,8 +1,10 @@
 // Public modules
 pub mod config;
 pub mod error;
+pub mod input;
 pub mod packet;
 mod quic;
+pub mod redact;
 pub mod serialization;
 pub mod stream;
 pub mod tcp;
@@ -11,7 +13,9 @@ pub mod transport;
 // Re-export commonly used types for convenience
 pub use config::{PeerInfo, ServerConfig, PROTOCOL_VERSION};
 pub use error::{ErrorCode, ProtocolError};
+pub use input::{Button, InputEvent, InputFrame, INPUT_VERSION};
 pub use packet::{Packet, PacketType};
+pub use redact::Redacted;
 pub use serialization::{receive, send, SerializationMode};
 pub use stream::AsyncStream;
 pub use transport::{TransportFactory, TransportType};
@@ -1,7 +1,7 @@
 use crate::config::{protocol_structure::*, ServerConfig};
 use crate::error::ErrorCode;
+use crate::input::InputFrame;
 use crate::serialization::SerializationMode;
-use serde_json::Value;
 
 /// Protocol type identifiers
 #[repr(u8)]
@@ -14,8 +14,8 @@ pub enum PacketType {
     Err = 1,
     /// Initial handshake with configuration (2)
     ServerHello = 2,
-    /// Generic action/event (3)
-    Action = 3,
+    /// Frame of input events (3)
+    Input = 3,
     /// Client-initiated disconnect (4)
     ClientQuit = 4,
     /// Server sending file to client (5)
@@ -38,7 +38,7 @@ impl TryFrom<u8> for PacketType {
             0 => Self::Ok,
             1 => Self::Err,
             2 => Self::ServerHello,
-            3 => Self::Action,
+            3 => Self::Input,
             4 => Self::ClientQuit,
             5 => Self::DropSend,
             6 => Self::DropRequest,
@@ -59,7 +59,8 @@ pub enum Packet {
         message: String,
     },
     ServerHello(ServerConfig),
-    Action(Value),
+    /// Input events to replay, see [`InputFrame`]
+    Input(InputFrame),
     ClientQuit,
 
     /// File being sent to client (includes filename)
@@ -84,7 +85,7 @@ impl Packet {
             Self::Ok => PacketType::Ok,
             Self::Err { .. } => PacketType::Err,
             Self::ServerHello(_) => PacketType::ServerHello,
-            Self::Action(_) => PacketType::Action,
+            Self::Input(_) => PacketType::Input,
             Self::ClientQuit => PacketType::ClientQuit,
             Self::DropSend { .. } => PacketType::DropSend,
             Self::DropRequest { .. } => PacketType::DropRequest,
@@ -124,21 +125,13 @@ impl Packet {
                 let bytes = Self::serialize_into(mode, config);
                 Self::insert_into_buf(&mut buf, &bytes);
             }
-            Self::Action(action) => match mode {
-                SerializationMode::Json => {
-                    let bytes = Self::serialize_into(mode, action);
-                    Self::insert_into_buf(&mut buf, &bytes);
-                }
-                SerializationMode::Binary => match action {
-                    Value::String(encoded) => {
-                        Self::insert_into_buf(&mut buf, encoded.as_bytes());
-                    }
-                    _ => {
-                        let bytes = Self::serialize_into(mode, action);
-                        Self::insert_into_buf(&mut buf, &bytes);
-                    }
-                },
-            },
+            Self::Input(frame) => {
+                let bytes = match mode {
+                    SerializationMode::Json => crate::serialization::serialize_json(frame),
+                    SerializationMode::Binary => frame.encode(),
+                };
+                Self::insert_into_buf(&mut buf, &bytes);
+            }
             Self::DropSend { filename } | Self::DropRequest { filename } => {
                 Self::insert_into_buf(&mut buf, filename.as_bytes());
             }
@@ -194,16 +187,9 @@ impl Packet {
                 let config = Self::deserialize_from(mode, payload)?;
                 Ok(Self::ServerHello(config))
             }
-            PacketType::Action => {
-                let payload = Self::read_payload(data, PAYLOAD_LENGTH_OFFSET, "Action")?;
-                let action = match mode {
-                    SerializationMode::Json => Self::deserialize_from(mode, payload)?,
-                    SerializationMode::Binary => Value::String(
-                        String::from_utf8(payload.to_vec())
-                            .map_err(|_| "Invalid Action payload encoding".to_string())?,
-                    ),
-                };
-                Ok(Self::Action(action))
+            PacketType::Input => {
+                let payload = Self::read_payload(data, PAYLOAD_LENGTH_OFFSET, "Input")?;
+                Ok(Self::Input(Self::deserialize_input(mode, payload)?))
             }
             PacketType::DropSend | PacketType::DropRequest => {
                 let payload = Self::read_payload(data, PAYLOAD_LENGTH_OFFSET, "Drop")?;
@@ -271,6 +257,19 @@ impl Packet {
         Ok(Self::Err { code, message })
     }
 
+    /// Decodes an input frame, JSON or the compact binary layout of [`InputFrame::encode`].
+    pub(crate) fn deserialize_input(
+        mode: SerializationMode,
+        data: &[u8],
+    ) -> Result<InputFrame, String> {
+        let frame: InputFrame = match mode {
+            SerializationMode::Json => crate::serialization::deserialize_json(data)?,
+            SerializationMode::Binary => InputFrame::decode(data)?,
+        };
+        frame.check_version()?;
+        Ok(frame)
+    }
+
     /// Deserializes a value from bytes according to the serialization mode.
     fn deserialize_from<T: serde::de::DeserializeOwned>(
         mode: SerializationMode,
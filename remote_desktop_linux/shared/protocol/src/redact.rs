@@ -0,0 +1,63 @@
+//! Log formatting of input that hides what was typed.
+//!
+//! Key codes and keysyms give away passwords, so logs show them only after
+//! [`set_show_keys`] was called.
+
+use crate::input::{InputEvent, InputFrame};
+use crate::packet::Packet;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static SHOW_KEYS: AtomicBool = AtomicBool::new(false);
+
+/// Lets [`Redacted`] show key codes and keysyms.
+pub fn set_show_keys(show: bool) {
+    SHOW_KEYS.store(show, Ordering::Relaxed);
+}
+
+#[must_use]
+pub fn show_keys() -> bool {
+    SHOW_KEYS.load(Ordering::Relaxed)
+}
+
+/// `Debug` of the wrapped value with keys hidden, and file contents shown as their size
+pub struct Redacted<'a, T>(pub &'a T);
+
+impl fmt::Debug for Redacted<'_, InputEvent> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            InputEvent::Key { pressed, .. } if !show_keys() => f
+                .debug_struct("Key")
+                .field("code", &format_args!("<redacted>"))
+                .field("pressed", pressed)
+                .finish_non_exhaustive(),
+            event => event.fmt(f),
+        }
+    }
+}
+
+impl fmt::Debug for Redacted<'_, InputFrame> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list()
+            .entries(self.0.events.iter().map(Redacted))
+            .finish()
+    }
+}
+
+impl fmt::Debug for Redacted<'_, Packet> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            Packet::Input(frame) => f.debug_tuple("Input").field(&Redacted(frame)).finish(),
+            Packet::Data(data) => write!(f, "Data({} bytes)", data.len()),
+            packet => packet.fmt(f),
+        }
+    }
+}
+
+impl Packet {
+    /// Formats the packet for logs, see [`Redacted`].
+    #[must_use]
+    pub fn redacted(&self) -> Redacted<'_, Packet> {
+        Redacted(self)
+    }
+}
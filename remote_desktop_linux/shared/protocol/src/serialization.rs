@@ -2,10 +2,11 @@ use crate::error::{ErrorCode, ProtocolError};
 use crate::stream::AsyncStream;
 use crate::{Packet, PacketType};
 
-use dotenvy::dotenv;
 use rmp_serde as rmps;
 use serde::{Deserialize, Serialize};
 use std::env;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::OnceLock;
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
 
 /// Sends a packet over a AsyncStream stream
@@ -20,6 +21,7 @@ use tokio::io::{AsyncReadExt, AsyncWriteExt};
 /// - `Ok(())` if the packet was sent successfully
 /// - `Err(ProtocolError)` if writing or flushing failed
 pub async fn send<S: AsyncStream>(packet: Packet, stream: &mut S) -> Result<(), ProtocolError> {
+    tracing::trace!(packet = ?packet.redacted(), "send");
     let data = packet.serialize();
     stream.write_all(&data).await?;
     stream.flush().await?; // CRITICAL: Flush immediately for real-time communication
@@ -39,6 +41,12 @@ pub async fn send<S: AsyncStream>(packet: Packet, stream: &mut S) -> Result<(),
 /// - `Ok(Packet)` if a valid packet was received
 /// - `Err(ProtocolError)` if reading failed or the packet was invalid
 pub async fn receive<S: AsyncStream>(stream: &mut S) -> Result<Packet, ProtocolError> {
+    let packet = read_packet(stream).await?;
+    tracing::trace!(packet = ?packet.redacted(), "receive");
+    Ok(packet)
+}
+
+async fn read_packet<S: AsyncStream>(stream: &mut S) -> Result<Packet, ProtocolError> {
     let mode = SerializationMode::from_env();
     let packet_type = read_packet_type(stream).await?;
 
@@ -58,10 +66,11 @@ pub async fn receive<S: AsyncStream>(stream: &mut S) -> Result<Packet, ProtocolE
             let config = deserialize(mode, &data)?;
             Ok(Packet::ServerHello(config))
         }
-        PacketType::Action => {
+        PacketType::Input => {
             let data = read_data_payload(stream).await?;
-            let action = deserialize(mode, &data)?;
-            Ok(Packet::Action(action))
+            let frame =
+                Packet::deserialize_input(mode, &data).map_err(ProtocolError::InvalidData)?;
+            Ok(Packet::Input(frame))
         }
         PacketType::DropSend => {
             let data = read_data_payload(stream).await?;
@@ -136,29 +145,64 @@ where
 }
 
 /// Serialization mode for packet payloads
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum SerializationMode {
+    #[default]
     Json,
     Binary,
 }
 
+/// Environment variable choosing the serialization mode
+pub const SERIALIZATION_ENV: &str = "PROTOCOL_SERIALIZATION";
+
+/// Mode set by [`SerializationMode::set_default`], 0 while unset
+static CONFIGURED_MODE: AtomicU8 = AtomicU8::new(0);
+
+/// Mode of the `.env` file, read once on first use
+static DOTENV_MODE: OnceLock<SerializationMode> = OnceLock::new();
+
 impl SerializationMode {
-    /// Reads the serialization mode from env(defaults JSON)
+    /// Sets the mode used when [`SERIALIZATION_ENV`] is not in the environment,
+    /// taking precedence over a `.env` file.
+    pub fn set_default(mode: SerializationMode) {
+        let value = match mode {
+            SerializationMode::Json => 1,
+            SerializationMode::Binary => 2,
+        };
+        CONFIGURED_MODE.store(value, Ordering::Relaxed);
+    }
+
+    /// Reads the serialization mode, in order of precedence from the environment,
+    /// [`SerializationMode::set_default`] and a `.env` file (defaults JSON)
     pub fn from_env() -> Self {
         // Prefer an explicitly-set environment variable (e.g. tests or runtime overrides)
-        if let Ok(val) = std::env::var("PROTOCOL_SERIALIZATION") {
-            return if val.eq_ignore_ascii_case("binary") {
-                SerializationMode::Binary
-            } else {
-                SerializationMode::Json
-            };
+        if let Ok(val) = env::var(SERIALIZATION_ENV) {
+            return Self::parse(&val);
+        }
+
+        match CONFIGURED_MODE.load(Ordering::Relaxed) {
+            1 => return SerializationMode::Json,
+            2 => return SerializationMode::Binary,
+            _ => {}
         }
 
-        // If not present, try loading from a .env file and read again
-        dotenv().ok();
-        match env::var("PROTOCOL_SERIALIZATION") {
-            Ok(val) if val.eq_ignore_ascii_case("binary") => SerializationMode::Binary,
-            _ => SerializationMode::Json,
+        // read without loading it into the environment, where it would win over a later set_default
+        *DOTENV_MODE.get_or_init(|| {
+            dotenvy::dotenv_iter()
+                .into_iter()
+                .flatten()
+                .flatten()
+                .find(|(key, _)| key == SERIALIZATION_ENV)
+                .map_or(SerializationMode::Json, |(_, val)| Self::parse(&val))
+        })
+    }
+
+    fn parse(val: &str) -> Self {
+        if val.eq_ignore_ascii_case("binary") {
+            SerializationMode::Binary
+        } else {
+            SerializationMode::Json
         }
     }
 }
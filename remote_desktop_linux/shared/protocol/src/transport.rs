@@ -2,11 +2,13 @@ use crate::quic::make_server_endpoint;
 use crate::quic::quic_single_socket;
 use crate::quic::QuinnStream;
 use crate::stream::AsyncStream;
+use serde::{Deserialize, Serialize};
 use std::io;
 use tokio::net::{TcpListener, TcpStream};
 
 /// Transport type configuration
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum TransportType {
     #[default]
     Tcp,
@@ -0,0 +1,159 @@
+use std::time::{Duration, UNIX_EPOCH};
+
+use kmf_protocol::{Button, InputEvent, InputFrame, Packet, SerializationMode, INPUT_VERSION};
+
+fn key(code: u16, pressed: bool, keysym: Option<u32>) -> InputEvent {
+    InputEvent::Key {
+        code,
+        pressed,
+        repeat: false,
+        keysym,
+    }
+}
+
+#[test]
+fn compact_encoding_round_trips_every_event() {
+    let frame = InputFrame::new(vec![
+        InputEvent::MouseMove {
+            x: -3,
+            y: 127,
+            wheel: 1,
+        },
+        InputEvent::MouseMove {
+            x: 300,
+            y: -70_000,
+            wheel: 0,
+        },
+        InputEvent::MouseButton {
+            button: Button::Middle,
+            pressed: true,
+        },
+        key(30, true, None),
+        InputEvent::Key {
+            code: 3,
+            pressed: true,
+            repeat: true,
+            keysym: Some(0x0100_013e),
+        },
+        InputEvent::LockState {
+            caps_lock: true,
+            num_lock: false,
+            scroll_lock: true,
+        },
+    ]);
+    assert_eq!(InputFrame::decode(&frame.encode()), Ok(frame));
+}
+
+#[test]
+fn compact_encoding_is_a_few_bytes_per_event() {
+    let small_move = InputFrame::from(InputEvent::MouseMove {
+        x: 5,
+        y: -2,
+        wheel: 0,
+    });
+    assert_eq!(small_move.encode(), [0, 1, 0, 5, 0xfe, 0]);
+
+    let click = InputFrame::from(InputEvent::MouseButton {
+        button: Button::Right,
+        pressed: true,
+    });
+    assert_eq!(click.encode().len(), 2 + 3);
+    assert_eq!(InputFrame::from(key(30, false, None)).encode().len(), 2 + 4);
+    assert_eq!(
+        InputFrame::from(key(30, true, Some(0x61))).encode().len(),
+        2 + 8
+    );
+}
+
+#[test]
+fn compact_decoding_rejects_bad_data() {
+    let mut data = InputFrame::from(key(30, true, Some(0x61))).encode();
+    data.pop();
+    assert_eq!(
+        InputFrame::decode(&data),
+        Err("Truncated input frame".to_string())
+    );
+
+    let unknown_tag = [0, 1, 0xff];
+    assert!(InputFrame::decode(&unknown_tag)
+        .unwrap_err()
+        .contains("tag"));
+
+    let mut frame = InputFrame::from(key(30, true, None));
+    frame.version = INPUT_VERSION + 1;
+    assert!(InputFrame::decode(&frame.encode())
+        .unwrap_err()
+        .contains("version"));
+}
+
+#[test]
+fn binary_packets_carry_the_compact_frame() {
+    let frame = InputFrame::new(vec![
+        InputEvent::MouseMove {
+            x: 1,
+            y: 1,
+            wheel: 0,
+        },
+        key(28, true, None),
+    ]);
+    let bytes = Packet::Input(frame.clone()).serialize_with_mode(SerializationMode::Binary);
+    // type + length prefix + version + two events
+    assert_eq!(bytes.len(), 1 + 4 + 2 + 4 + 4);
+
+    match Packet::deserialize_with_mode(&bytes, SerializationMode::Binary) {
+        Ok(Packet::Input(decoded)) => assert_eq!(decoded, frame),
+        other => panic!("expected Input packet, got {:?}", other),
+    }
+}
+
+#[test]
+fn logged_packets_hide_keys_and_file_contents() {
+    let packet = Packet::Input(InputFrame::new(vec![
+        key(30, true, Some(0x61)),
+        InputEvent::MouseButton {
+            button: Button::Left,
+            pressed: true,
+        },
+    ]));
+    let logged = format!("{:?}", packet.redacted());
+    assert!(logged.contains("<redacted>"), "{}", logged);
+    assert!(
+        !logged.contains("30") && !logged.contains("97"),
+        "{}",
+        logged
+    );
+    assert!(logged.contains("Left"), "{}", logged);
+
+    let logged = format!("{:?}", Packet::Data(vec![0; 2048]).redacted());
+    assert_eq!(logged, "Data(2048 bytes)");
+}
+
+#[test]
+fn capture_timestamps_survive_both_encodings() {
+    let captured = UNIX_EPOCH + Duration::from_micros(1_700_000_000_123_456);
+    let frame = InputFrame::from(key(30, true, None)).captured_at(captured);
+    assert_eq!(frame.timestamp_us, Some(1_700_000_000_123_456));
+    // the timestamp is a 9 byte pseudo event ahead of the others
+    assert_eq!(frame.encode().len(), 2 + 9 + 4);
+
+    for mode in [SerializationMode::Json, SerializationMode::Binary] {
+        let bytes = Packet::Input(frame.clone()).serialize_with_mode(mode);
+        match Packet::deserialize_with_mode(&bytes, mode) {
+            Ok(Packet::Input(decoded)) => assert_eq!(decoded, frame),
+            other => panic!("expected Input packet, got {:?}", other),
+        }
+    }
+
+    // the optional timestamp needs no new schema version
+    assert_eq!(frame.version, INPUT_VERSION);
+    let json = Packet::Input(frame.clone()).serialize_with_mode(SerializationMode::Json);
+    assert!(String::from_utf8_lossy(&json).contains("\"version\":1"));
+
+    let unstamped = InputFrame::from(key(30, true, None));
+    assert_eq!(
+        InputFrame::decode(&unstamped.encode()),
+        Ok(unstamped.clone())
+    );
+    assert_eq!(unstamped.captured(), None);
+    assert_eq!(frame.captured(), Some(captured));
+}
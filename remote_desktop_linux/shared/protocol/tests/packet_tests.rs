@@ -4,13 +4,13 @@ use tokio::io::{duplex, AsyncWriteExt};
 
 #[tokio::test]
 async fn test_read_packet_type_ok() {
-    // PacketType::Action == 3
+    // PacketType::Input == 3
     let (mut a, mut b) = duplex(64);
     a.write_all(&[3u8]).await.unwrap();
 
     let res = read_packet_type(&mut b).await;
     let pkt = res.expect("should read packet type");
-    assert_eq!(pkt, PacketType::Action);
+    assert_eq!(pkt, PacketType::Input);
 }
 
 #[tokio::test]
@@ -2,8 +2,8 @@ use kmf_protocol::serialization::{
     deserialize_bin, deserialize_json, read_data_payload, read_packet_type, serialize_bin,
     serialize_json, SerializationMode,
 };
-use kmf_protocol::PacketType;
 use kmf_protocol::{error, serialization, Packet, ProtocolError};
+use kmf_protocol::{Button, InputEvent, InputFrame, PacketType, INPUT_VERSION};
 use serde::{Deserialize, Serialize};
 use serial_test::serial;
 use std::env;
@@ -56,16 +56,30 @@ fn test_serialization_mode_from_env_binary() {
     assert_eq!(m, SerializationMode::Binary);
 }
 
+#[test]
+#[serial]
+fn test_serialization_mode_configured_default_yields_to_env() {
+    SerializationMode::set_default(SerializationMode::Binary);
+    env::remove_var("PROTOCOL_SERIALIZATION");
+    assert_eq!(SerializationMode::from_env(), SerializationMode::Binary);
+    // the workspace .env sets json, it neither wins nor ends up in the environment
+    assert!(env::var("PROTOCOL_SERIALIZATION").is_err());
+
+    env::set_var("PROTOCOL_SERIALIZATION", "json");
+    assert_eq!(SerializationMode::from_env(), SerializationMode::Json);
+    SerializationMode::set_default(SerializationMode::Json);
+}
+
 #[tokio::test]
 #[serial]
 async fn test_read_packet_type_ok() {
-    // PacketType::Action == 3
+    // PacketType::Input == 3
     let (mut a, mut b) = duplex(64);
     a.write_all(&[3u8]).await.unwrap();
 
     let res: Result<PacketType, error::ProtocolError> = read_packet_type(&mut b).await;
     let pkt = res.expect("should read packet type");
-    assert_eq!(pkt, PacketType::Action);
+    assert_eq!(pkt, PacketType::Input);
 }
 
 #[tokio::test]
@@ -105,10 +119,21 @@ async fn test_read_data_payload_truncated() {
 
 #[tokio::test]
 #[serial]
-async fn test_send_receive_action_both_modes() {
-    // action represented as a serde_json::Value; works for both Json and Binary modes
-    let action = serde_json::json!({"type": "MouseMove", "x": 123, "y": 456});
-    let packet = Packet::Action(action.clone());
+async fn test_send_receive_input_both_modes() {
+    let frame = InputFrame::new(vec![
+        InputEvent::MouseMove {
+            x: 123,
+            y: 456,
+            wheel: 0,
+        },
+        InputEvent::Key {
+            code: 30,
+            pressed: true,
+            repeat: false,
+            keysym: Some(0x61),
+        },
+    ]);
+    let packet = Packet::Input(frame.clone());
 
     for mode in &["json", "binary"] {
         std::env::set_var("PROTOCOL_SERIALIZATION", mode);
@@ -129,8 +154,29 @@ async fn test_send_receive_action_both_modes() {
         let received = serialization::receive(&mut b).await.expect("receive ok");
 
         match received {
-            Packet::Action(v) => assert_eq!(v, action),
-            other => panic!("expected Action packet, got {:?}", other),
+            Packet::Input(f) => assert_eq!(f, frame),
+            other => panic!("expected Input packet, got {:?}", other),
         }
     }
 }
+
+#[tokio::test]
+#[serial]
+async fn test_receive_rejects_unknown_input_version() {
+    std::env::set_var("PROTOCOL_SERIALIZATION", "json");
+    let mut frame = InputFrame::from(InputEvent::MouseButton {
+        button: Button::Left,
+        pressed: true,
+    });
+    frame.version = INPUT_VERSION + 1;
+    let bytes = Packet::Input(frame).serialize_with_mode(SerializationMode::Json);
+
+    let (mut a, mut b) = duplex(1024);
+    a.write_all(&bytes).await.expect("write_all");
+    a.shutdown().await.expect("shutdown");
+
+    match serialization::receive(&mut b).await {
+        Err(ProtocolError::InvalidData(msg)) => assert!(msg.contains("version")),
+        other => panic!("expected version error, got {:?}", other),
+    }
+}
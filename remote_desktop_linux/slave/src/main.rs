@@ -1,83 +1,172 @@
 use clap::Parser;
-use kmf_driver::driver::{DriverEvent, DriverWriter};
-use kmf_middleware::command::parse_command;
-use kmf_protocol::config::ServerMessage;
+use kmf_driver::driver::{DriverEvent, DriverWriter, InputSink, KeyCode, RelativeAxisCode};
+use kmf_driver::layout::{Layout, LayoutSink};
+use kmf_middleware::config::{Config, ScreenSize};
+use kmf_middleware::control::{
+    socket_path, termination, unknown_method, ControlServer, SlaveState, SLAVE_METHODS,
+};
+use kmf_middleware::logging;
+use kmf_middleware::metrics::metrics;
 use kmf_protocol::{ErrorCode, Packet, ServerConfig, TransportFactory, TransportType};
-use std::io;
-use std::io::{BufRead, Write};
-use std::str::FromStr;
-use std::sync::atomic::{AtomicBool, Ordering};
+use serde_json::Value;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::Arc;
-use tokio::sync::broadcast;
+use std::time::{Duration, Instant};
+use tokio::sync::watch;
+use tracing::{debug, error, info, info_span, warn, Instrument};
 
-#[cfg(target_os = "linux")]
-use evdev::{KeyCode, RelativeAxisCode};
-#[cfg(not(target_os = "linux"))]
-use kmf_driver::driver::{KeyCode, RelativeAxisCode};
+/// Wait between connection attempts of a daemon
+const RECONNECT_DELAY: Duration = Duration::from_secs(2);
 
 #[derive(Debug, Parser)]
 #[command(version, about, long_about = None)]
 struct Args {
-    /// Server address to connect to
+    /// Config file, defaults to $XDG_CONFIG_HOME/kmf/config.toml
+    #[arg(long)]
+    config: Option<PathBuf>,
+
+    /// Server address to connect to, `slave.server` of the config when not given
     #[arg(short, long)]
-    server: String,
+    server: Option<String>,
 
-    /// Transport type (tcp, mod)
-    #[arg(short, long, default_value = "mod")]
-    transport: String,
-}
+    /// Transport type (tcp, quic)
+    #[arg(short, long)]
+    transport: Option<TransportType>,
 
-#[tokio::main]
-async fn main() -> anyhow::Result<()> {
-    let args = Args::parse();
+    /// xkb keyboard layout of this machine (e.g. sk or sk(qwerty)), detected when not given.
+    /// Keys the master sends with their character are typed in this layout
+    #[arg(short, long)]
+    layout: Option<String>,
 
-    // Parse transport type
-    let transport = TransportType::from_str(&args.transport).unwrap_or(TransportType::Tcp);
+    /// Run headless: keep reconnecting to the master until SIGTERM or `kmfctl --slave stop`
+    #[arg(long)]
+    daemon: bool,
 
-    run_client(&args.server, transport).await?;
-    Ok(())
+    /// Control socket for kmfctl, defaults to $XDG_RUNTIME_DIR/kmf/slave.sock
+    #[arg(long)]
+    control: Option<PathBuf>,
 }
 
-/// Runs the client and connects to a server.
-pub async fn run_client(server_addr: &str, transport: TransportType) -> anyhow::Result<()> {
-    println!("Attempting to connect to server at {}...", server_addr);
-
-    let mut stream = match TransportFactory::connect_client(transport, server_addr).await {
-        Ok(stream) => {
-            println!("Successfully connected to server at {}", server_addr);
-            stream
+impl Args {
+    /// The config file's settings with the ones given on the command line replacing them.
+    fn config(&self) -> anyhow::Result<Config> {
+        let mut config =
+            Config::load_or_default(self.config.as_deref()).map_err(anyhow::Error::msg)?;
+        let slave = &mut config.slave;
+        if let Some(server) = &self.server {
+            slave.server = Some(server.clone());
         }
-        Err(e) => {
-            eprintln!("\n--- Connection Failed ---");
-            eprintln!("Error: {}", e);
-            eprintln!("\nTroubleshooting steps:");
-            eprintln!("1. Is the server running?");
-            eprintln!("2. Is the IP address '{}' correct?", server_addr);
-            eprintln!("3. Is a firewall blocking the connection? Try temporarily disabling your firewall.");
-            eprintln!("   (Check Windows Defender Firewall or any third-party antivirus software)");
-            return Ok(());
+        if let Some(transport) = self.transport {
+            slave.transport = transport;
         }
-    };
+        if let Some(layout) = &self.layout {
+            slave.layout = Some(layout.clone());
+        }
+        config
+            .validate()
+            .map_err(|e| anyhow::anyhow!("Invalid settings: {}", e))?;
+        Ok(config)
+    }
+}
 
-    // Send ServerHello handshake with client configuration
-    // This allows the server to know the client's screen dimensions and hostname
-    let config = ServerConfig {
-        version: 1,
-        screen_width: 1920,
-        screen_height: 1080,
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let args = Args::parse();
+
+    let config = args.config()?;
+    logging::init(&config.log, "kmf-slave").map_err(anyhow::Error::msg)?;
+    config.protocol.apply();
+    let slave = config.slave;
+    let server = slave.server.clone().ok_or_else(|| {
+        anyhow::anyhow!("No server given, pass --server or set slave.server in the config")
+    })?;
+    let layout = slave.layout().map_err(anyhow::Error::msg)?;
+
+    let (stop, _) = watch::channel(false);
+    let state = Arc::new(SlaveControl {
+        server,
+        screen: slave.screen,
         hostname: hostname::get()
             .unwrap_or_default()
             .to_string_lossy()
             .to_string(),
-    };
+        layout: layout.as_ref().map(|l| l.name().to_string()),
+        started: Instant::now(),
+        connected: AtomicBool::new(false),
+        frames: AtomicU64::new(0),
+        stop: stop.clone(),
+    });
 
-    if let Err(e) = kmf_protocol::send(Packet::ServerHello(config), &mut stream).await {
-        eprintln!("Failed to send ServerHello: {}", e);
-        return Ok(());
+    let control_path = args.control.unwrap_or_else(|| socket_path("slave"));
+    let handler = state.clone();
+    let _control = ControlServer::bind(
+        &control_path,
+        Arc::new(move |method: &str, params: Value| handler.handle(method, params)),
+    )
+    .await
+    .map_err(|e| anyhow::anyhow!("Control socket {}: {}", control_path.display(), e))?;
+    info!("Control socket at {}", control_path.display());
+
+    tokio::spawn(async move {
+        termination().await;
+        info!("Termination requested");
+        stop.send_replace(true);
+    });
+
+    run_client(slave.transport, layout, args.daemon, state).await?;
+    Ok(())
+}
+
+/// State the control socket reports, shared with the connection loop
+struct SlaveControl {
+    server: String,
+    /// Screen size announced to the master
+    screen: ScreenSize,
+    hostname: String,
+    layout: Option<String>,
+    started: Instant,
+    connected: AtomicBool,
+    /// Input frames replayed since start
+    frames: AtomicU64,
+    stop: watch::Sender<bool>,
+}
+
+impl SlaveControl {
+    /// Answers a call on the control socket.
+    fn handle(&self, method: &str, _params: Value) -> Result<Value, String> {
+        match method {
+            "status" => serde_json::to_value(SlaveState {
+                server: self.server.clone(),
+                connected: self.connected.load(Ordering::Relaxed),
+                hostname: self.hostname.clone(),
+                layout: self.layout.clone(),
+                uptime_secs: self.started.elapsed().as_secs(),
+                frames: self.frames.load(Ordering::Relaxed),
+            })
+            .map_err(|e| e.to_string()),
+            "stats" => serde_json::to_value(metrics().snapshot()).map_err(|e| e.to_string()),
+            "stop" => {
+                info!("Stop requested over the control socket");
+                self.stop.send_replace(true);
+                Ok(Value::Null)
+            }
+            _ => Err(unknown_method(method, SLAVE_METHODS)),
+        }
     }
 
-    println!("[INFO] ServerHello sent, waiting for messages...");
+    fn stopped(&self) -> bool {
+        *self.stop.borrow()
+    }
+}
 
+/// Runs the client and connects to a server, again and again as a daemon.
+async fn run_client(
+    transport: TransportType,
+    layout: Option<Layout>,
+    daemon: bool,
+    state: Arc<SlaveControl>,
+) -> anyhow::Result<()> {
     // Initialize DriverWriter
     // We need to tell uinput which keys and axes this virtual device supports.
     let axes = vec![
@@ -88,66 +177,137 @@ pub async fn run_client(server_addr: &str, transport: TransportType) -> anyhow::
 
     let keys = (0..0x2ff).map(KeyCode::new).collect::<Vec<KeyCode>>();
 
-    let mut writer = DriverWriter::new(keys, axes)
+    let writer = DriverWriter::new(keys, axes)
         .map_err(|e| anyhow::anyhow!("Failed to init DriverWriter: {}", e))?;
 
+    match &layout {
+        Some(layout) => info!("Typing translated keys in layout '{}'", layout.name()),
+        None => info!("Unknown keyboard layout, replaying raw key codes"),
+    }
+    let mut writer = LayoutSink::new(writer, layout);
+    let mut stop = state.stop.subscribe();
+
+    while !state.stopped() {
+        let session = info_span!("session", server = %state.server);
+        let connected = tokio::select! {
+            connected = run_session(transport, &mut writer, &state).instrument(session) => connected,
+            _ = stop.wait_for(|stop| *stop) => break,
+        };
+        state.connected.store(false, Ordering::Relaxed);
+        if !daemon {
+            break;
+        }
+        if connected {
+            info!("Reconnecting...");
+        }
+        tokio::select! {
+            _ = tokio::time::sleep(RECONNECT_DELAY) => {}
+            _ = stop.wait_for(|stop| *stop) => break,
+        }
+    }
+
+    info!("Disconnected from server");
+    Ok(())
+}
+
+/// Connects once and replays what the server sends, `false` when the connection failed.
+async fn run_session(
+    transport: TransportType,
+    writer: &mut impl InputSink,
+    state: &SlaveControl,
+) -> bool {
+    let server_addr = state.server.as_str();
+    info!("Connecting");
+
+    let mut stream = match TransportFactory::connect_client(transport, server_addr).await {
+        Ok(stream) => {
+            info!("Connected");
+            metrics().peer(server_addr).count(stream)
+        }
+        Err(e) => {
+            error!(
+                "Connection failed: {}. Is the master running, is '{}' its address, \
+                 and does no firewall block the port?",
+                e, server_addr
+            );
+            return false;
+        }
+    };
+
+    // Send ServerHello handshake with client configuration
+    // This allows the server to know the client's screen dimensions and hostname
+    let config = ServerConfig {
+        version: 1,
+        screen_width: state.screen.width,
+        screen_height: state.screen.height,
+        hostname: state.hostname.clone(),
+    };
+
+    if let Err(e) = kmf_protocol::send(Packet::ServerHello(config), &mut stream).await {
+        error!("Failed to send ServerHello: {}", e);
+        return true;
+    }
+    state.connected.store(true, Ordering::Relaxed);
+
+    info!("ServerHello sent, waiting for messages...");
+
     // Force cursor to top-left on slave (best-effort)
-    let _ = writer.simulate_event(DriverEvent::MouseMove(kmf_driver::event::MouseMove {
+    let _ = writer.simulate_frame(&[DriverEvent::MouseMove(kmf_driver::event::MouseMove {
         x: -10000,
         y: -10000,
         wheel: 0,
-    }));
-    println!("[INFO] Forced slave cursor to top-left (delta -10000,-10000)");
+    })]);
+    info!("Forced slave cursor to top-left (delta -10000,-10000)");
 
     // Main client receive loop
     loop {
         match kmf_protocol::receive(&mut stream).await {
             Ok(packet) => {
-                println!("[DEBUG] Received protocol: {:?}", packet);
+                debug!(packet = ?packet.redacted(), "Received");
+                if matches!(packet, Packet::Input(_)) {
+                    state.frames.fetch_add(1, Ordering::Relaxed);
+                }
 
-                let should_quit = handle_packet(&mut stream, packet, &mut writer)
-                    .await
-                    .unwrap();
-                if should_quit {
-                    break;
+                match handle_packet(&mut stream, packet, writer).await {
+                    Ok(false) => {}
+                    Ok(true) => break,
+                    Err(e) => {
+                        error!("Failed to answer the master: {}. Disconnecting.", e);
+                        break;
+                    }
                 }
             }
             Err(e) => {
-                eprintln!("[ERROR] Failed to receive protocol: {}. Disconnecting.", e);
+                error!("Failed to receive protocol: {}. Disconnecting.", e);
                 break;
             }
         }
     }
-
-    println!("Disconnected from server.");
-    Ok(())
+    true
 }
 
 /// Handles an incoming packet on the slave side.
 async fn handle_packet(
     stream: &mut Box<dyn kmf_protocol::AsyncStream>,
     packet: Packet,
-    writer: &mut DriverWriter,
+    writer: &mut impl InputSink,
 ) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
     match packet {
-        Packet::Action(action_val) => {
-            // println!("[ACTION] Received: {:?}", action_val);
-            if let Ok(event) = serde_json::from_value::<DriverEvent>(action_val) {
-                // println!("[ACTION] Simulating: {:?}", event);
-                if let Err(e) = writer.simulate_event(event) {
-                    eprintln!("[ERROR] Simulation failed: {}", e);
-                }
-            } else {
-                eprintln!("[ERROR] Failed to deserialize DriverEvent from Action");
+        Packet::Input(frame) => {
+            let captured = frame.captured();
+            let frame = kmf_middleware::event::driver_frame(frame);
+            if let Err(e) = writer.simulate_frame(&frame) {
+                error!("Simulation failed: {}", e);
             }
+            metrics().record_replayed(captured);
 
             kmf_protocol::send(Packet::Ok, stream).await?;
             Ok(false)
         }
         Packet::DropSend { filename } => {
-            println!("[DROP] Receiving file: {}", filename);
+            info!("Receiving file: {}", filename);
             if let Err(e) = kmf_middleware::file_transfer::receive_file(stream, &filename).await {
-                eprintln!("[ERROR] Failed to save file: {}", e);
+                error!("Failed to save file: {}", e);
                 let _ = kmf_protocol::send(
                     Packet::Err {
                         code: ErrorCode::Internal,
@@ -160,14 +320,14 @@ async fn handle_packet(
             Ok(false)
         }
         Packet::DropRequest { filename } => {
-            println!("[DROP] Server requesting file: {}", filename);
+            info!("Server requesting file: {}", filename);
             match kmf_middleware::file_transfer::read_file(&filename).await {
                 Ok(data) => {
                     kmf_protocol::send(Packet::Data(data), stream).await?;
-                    println!("[FILE] Sent: {}", filename);
+                    info!("Sent: {}", filename);
                 }
                 Err(e) => {
-                    eprintln!("[ERROR] Failed to read file: {}", e);
+                    error!("Failed to read file: {}", e);
                     let _ = kmf_protocol::send(
                         Packet::Err {
                             code: ErrorCode::Internal,
@@ -181,82 +341,30 @@ async fn handle_packet(
             Ok(false)
         }
         Packet::EdgeL => {
-            println!("[EDGE] Cursor left edge detected");
+            debug!("Cursor left edge detected");
             let _ = kmf_protocol::send(Packet::Ok, stream).await;
             Ok(false)
         }
         Packet::EdgeR => {
-            println!("[EDGE] Cursor right edge detected");
+            debug!("Cursor right edge detected");
             let _ = kmf_protocol::send(Packet::Ok, stream).await;
             Ok(false)
         }
         Packet::ClientQuit => {
-            println!("[INFO] Server requested disconnect");
+            info!("Server requested disconnect");
             Ok(true)
         }
         Packet::Ok => {
-            println!("[OK] Acknowledgment received");
+            debug!("Acknowledgment received");
             Ok(false)
         }
         Packet::Err { code, message } => {
-            eprintln!("[ERROR] Error code {} Server error: {}", code, message);
+            error!("Error code {} Server error: {}", code, message);
             Ok(false)
         }
         _ => {
-            println!("[WARN] Unhandled packet type");
+            warn!("Unhandled packet type");
             Ok(false)
         }
     }
 }
-
-/// Spawns a dedicated OS thread for handling command-line input.
-///
-/// This function **must** run in a separate OS thread (not a tokio task) because:
-/// - `stdin().lock()` is blocking I/O
-/// - Blocking the tokio runtime would prevent network operations
-/// - OS threads allow true parallel execution with the async runtime
-///
-/// The thread continuously reads commands and broadcasts them to all connected clients.
-///
-/// # Arguments
-///
-/// * `tx` - Broadcast sender for distributing commands to client handlers
-/// * `shutdown` - Atomic flag to signal server shutdown when quit is entered
-pub fn spawn_input_handler(tx: broadcast::Sender<ServerMessage>, shutdown: Arc<AtomicBool>) {
-    // IMPORTANT: Use std::thread::spawn, NOT tokio::spawn
-    std::thread::spawn(move || {
-        let stdin = io::stdin();
-        let mut reader = stdin.lock();
-        let mut line = String::new();
-
-        loop {
-            print!("Enter command (move <x> <y> | click <left|right|middle> <down|up> | key <key> <down|up> | file <path> | quit): ");
-            io::stdout().flush().expect("Failed to flush stdout");
-
-            line.clear();
-            if reader.read_line(&mut line).is_err() {
-                eprintln!("Failed to read input");
-                continue;
-            }
-
-            let input = line.trim();
-            if let Some(message) = parse_command(input) {
-                // Check if it's a quit command
-                if matches!(message, ServerMessage::Quit) {
-                    println!("[INFO] Quit command received. Shutting down server...");
-                    shutdown.store(true, Ordering::Relaxed);
-                    // Still broadcast quit to connected clients
-                    let _ = tx.send(message);
-                    break; // Exit the input handler thread
-                }
-
-                match tx.send(message) {
-                    Ok(n) => println!("[INFO] Command broadcast to {} client(s)", n),
-                    Err(_) => println!("[WARN] No clients connected"),
-                }
-            } else if !input.is_empty() {
-                eprintln!("Unknown command");
-            }
-        }
-    });
-}